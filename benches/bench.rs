@@ -9,6 +9,7 @@ use std::rc::Rc;
 use anyhow::Context as _;
 use criterion::measurement::Measurement;
 use criterion::{BatchSize, BenchmarkGroup, Criterion};
+use near_cm::config::new_wasmtime_config;
 use wac_graph::types::Package;
 use wac_graph::{CompositionGraph, EncodeOptions};
 use wasmtime::component::{Component, HasSelf};
@@ -63,7 +64,7 @@ mod bindings {
 
 mod codec_bindings {
     wasmtime::component::bindgen!({
-        world: "format",
+        world: "cosmonic:serde/format",
     });
 
     use exports::cosmonic::reflect::reflect::{Guest, List, Value};
@@ -201,6 +202,7 @@ fn unwrap_big_input_element<T>(
     }
 }
 
+#[expect(clippy::needless_borrow)]
 fn unwrap_big_input<T>(
     mut store: &mut Store<T>,
     reflect: &codec_bindings::exports::cosmonic::reflect::reflect::Guest,
@@ -229,6 +231,7 @@ fn bench_module(
     wasm: &[u8],
     config: &wasmtime::Config,
 ) -> anyhow::Result<()> {
+    #[expect(clippy::needless_borrow)]
     fn input(mut caller: Caller<'_, Ctx<ModuleState>>, ptr: u64) {
         let memory = caller.data().memory;
         let Some(Extern::Memory(memory)) = caller.get_module_export(&memory) else {
@@ -243,6 +246,8 @@ fn bench_module(
         caller.data().input.len() as _
     }
 
+    #[allow(clippy::type_complexity)]
+    #[expect(clippy::needless_borrow)]
     fn make_setup<T: wasmtime::WasmParams>(
         engine: &Engine,
         pre: &wasmtime::InstancePre<Ctx<ModuleState>>,
@@ -356,10 +361,38 @@ fn bench_module(
     Ok(())
 }
 
+/// Small and big inputs encoded in the format of a codec
+struct Inputs {
+    small: Rc<[u8]>,
+    big: Rc<[u8]>,
+}
+
+impl Inputs {
+    fn json() -> Self {
+        Self {
+            small: Rc::from(SMALL_INPUT),
+            big: Rc::from(BIG_INPUT),
+        }
+    }
+
+    /// Transcodes JSON inputs using `encode`.
+    fn transcode(
+        encode: impl Fn(&serde_json::Value) -> anyhow::Result<Vec<u8>>,
+    ) -> anyhow::Result<Self> {
+        let small = serde_json::from_slice(SMALL_INPUT)?;
+        let big = serde_json::from_slice(BIG_INPUT)?;
+        Ok(Self {
+            small: encode(&small)?.into(),
+            big: encode(&big)?.into(),
+        })
+    }
+}
+
 fn bench_component(
     g: &mut BenchmarkGroup<impl Measurement>,
     runner: &[u8],
     codec: &[u8],
+    inputs: &Inputs,
     config: &wasmtime::Config,
 ) -> anyhow::Result<()> {
     let engine = Engine::new(config)?;
@@ -389,6 +422,21 @@ fn bench_component(
         (runner, store)
     };
 
+    let setup_runner_instance = || {
+        let mut store = Store::new(
+            &engine,
+            Ctx {
+                input: Rc::default(),
+                state: (),
+            },
+        );
+        let instance = runner_pre
+            .instance_pre()
+            .instantiate(&mut store)
+            .expect("failed to instantiate runner");
+        (instance, store)
+    };
+
     let setup_codec = || {
         let mut store = Store::new(&engine, ());
         let codec = codec_pre
@@ -407,7 +455,7 @@ fn bench_component(
             BatchSize::LargeInput,
         );
     });
-    g.bench_with_input("small input", &Rc::from(SMALL_INPUT), |b, input| {
+    g.bench_with_input("small input", &inputs.small, |b, input| {
         b.iter_batched(
             || Rc::clone(input),
             |input| {
@@ -417,84 +465,129 @@ fn bench_component(
             BatchSize::LargeInput,
         );
     });
+    g.bench_with_input("small input byte args", &inputs.small, |b, input| {
+        b.iter_batched(
+            || {},
+            |()| {
+                let (runner, store) = setup_runner(Rc::default());
+                runner.call_run_small_bytes(store, input).unwrap();
+            },
+            BatchSize::LargeInput,
+        );
+    });
+
+    g.bench_with_input("small input typed args", &inputs.small, |b, input| {
+        b.iter_batched(
+            || {
+                let (codec, mut codec_store) = setup_codec();
+                let c_ty = codec
+                    .cosmonic_reflect_reflect()
+                    .tuple_type()
+                    .call_constructor(
+                        &mut codec_store,
+                        &[reflect::Type::U32, reflect::Type::U32, reflect::Type::U32],
+                    )
+                    .unwrap();
+                let ty = codec
+                    .cosmonic_reflect_reflect()
+                    .record_type()
+                    .call_constructor(
+                        &mut codec_store,
+                        &[
+                            ("a".into(), reflect::Type::String),
+                            ("b".into(), reflect::Type::U32),
+                            ("c".into(), reflect::Type::Tuple(c_ty)),
+                        ],
+                    )
+                    .unwrap();
+                (codec, codec_store, reflect::Type::Record(ty))
+            },
+            |(codec, mut codec_store, ty)| {
+                let (runner, runner_store) = setup_runner(Rc::default());
+                let v = codec
+                    .cosmonic_serde_deserializer()
+                    .call_from_list(&mut codec_store, input, ty)
+                    .unwrap()
+                    .unwrap();
+
+                let fields = v.unwrap_record(&mut codec_store, codec.cosmonic_reflect_reflect());
+                let mut fields = fields.into_iter();
+
+                let a = fields.next().unwrap().unwrap_string();
+                let b = fields.next().unwrap().unwrap_u32();
+                let c_values = fields
+                    .next()
+                    .unwrap()
+                    .unwrap_tuple(&mut codec_store, codec.cosmonic_reflect_reflect());
+                let mut c_values = c_values.into_iter();
+
+                let c0 = c_values.next().unwrap().unwrap_u32();
+                let c1 = c_values.next().unwrap().unwrap_u32();
+                let c2 = c_values.next().unwrap().unwrap_u32();
+
+                runner
+                    .call_run_small_typed(
+                        runner_store,
+                        &bindings::SmallInput {
+                            a,
+                            b,
+                            c: (c0, c1, c2),
+                        },
+                    )
+                    .unwrap();
+            },
+            BatchSize::LargeInput,
+        );
+    });
+    let small_ty = export_param_ty(&engine, &runner, "run-small-typed")?;
     g.bench_with_input(
-        "small input byte args",
+        "small input host json typed args",
         &Rc::from(SMALL_INPUT),
         |b, input| {
             b.iter_batched(
                 || {},
                 |()| {
-                    let (runner, store) = setup_runner(Rc::default());
-                    runner.call_run_small_bytes(store, input).unwrap();
+                    let (instance, mut store) = setup_runner_instance();
+                    let v = near_cm::codecs::json::decode(&small_ty, input).unwrap();
+                    call_export(&mut store, &instance, "run-small-typed", v);
                 },
                 BatchSize::LargeInput,
             );
         },
     );
-
+    let small_borsh = near_cm::codecs::borsh::encode(
+        &small_ty,
+        &near_cm::codecs::json::decode(&small_ty, SMALL_INPUT)?,
+    )?;
     g.bench_with_input(
-        "small input typed args",
-        &Rc::from(SMALL_INPUT),
+        "small input host borsh typed args",
+        &Rc::from(small_borsh),
         |b, input| {
             b.iter_batched(
-                || {
-                    let (codec, mut codec_store) = setup_codec();
-                    let c_ty = codec
-                        .cosmonic_reflect_reflect()
-                        .tuple_type()
-                        .call_constructor(
-                            &mut codec_store,
-                            &[reflect::Type::U32, reflect::Type::U32, reflect::Type::U32],
-                        )
-                        .unwrap();
-                    let ty = codec
-                        .cosmonic_reflect_reflect()
-                        .record_type()
-                        .call_constructor(
-                            &mut codec_store,
-                            &[
-                                ("a".into(), reflect::Type::String),
-                                ("b".into(), reflect::Type::U32),
-                                ("c".into(), reflect::Type::Tuple(c_ty)),
-                            ],
-                        )
-                        .unwrap();
-                    (codec, codec_store, reflect::Type::Record(ty))
+                || {},
+                |()| {
+                    let (instance, mut store) = setup_runner_instance();
+                    let v = near_cm::codecs::borsh::decode(&small_ty, input).unwrap();
+                    call_export(&mut store, &instance, "run-small-typed", v);
                 },
-                |(codec, mut codec_store, ty)| {
-                    let (runner, runner_store) = setup_runner(Rc::default());
-                    let v = codec
-                        .cosmonic_serde_deserializer()
-                        .call_from_list(&mut codec_store, input, ty)
-                        .unwrap()
-                        .unwrap();
-
-                    let fields =
-                        v.unwrap_record(&mut codec_store, codec.cosmonic_reflect_reflect());
-                    let mut fields = fields.into_iter();
-
-                    let a = fields.next().unwrap().unwrap_string();
-                    let b = fields.next().unwrap().unwrap_u32();
-                    let c_values = fields
-                        .next()
-                        .unwrap()
-                        .unwrap_tuple(&mut codec_store, codec.cosmonic_reflect_reflect());
-                    let mut c_values = c_values.into_iter();
-
-                    let c0 = c_values.next().unwrap().unwrap_u32();
-                    let c1 = c_values.next().unwrap().unwrap_u32();
-                    let c2 = c_values.next().unwrap().unwrap_u32();
-
-                    runner
-                        .call_run_small_typed(
-                            runner_store,
-                            &bindings::SmallInput {
-                                a,
-                                b,
-                                c: (c0, c1, c2),
-                            },
-                        )
-                        .unwrap();
+                BatchSize::LargeInput,
+            );
+        },
+    );
+    let small_raw = near_cm::codecs::raw::encode(
+        &small_ty,
+        &near_cm::codecs::json::decode(&small_ty, SMALL_INPUT)?,
+    )?;
+    g.bench_with_input(
+        "small input host raw typed args",
+        &Rc::from(small_raw),
+        |b, input| {
+            b.iter_batched(
+                || {},
+                |()| {
+                    let (instance, mut store) = setup_runner_instance();
+                    let v = near_cm::codecs::raw::decode(&small_ty, input).unwrap();
+                    call_export(&mut store, &instance, "run-small-typed", v);
                 },
                 BatchSize::LargeInput,
             );
@@ -514,7 +607,7 @@ fn bench_component(
             BatchSize::LargeInput,
         );
     });
-    g.bench_with_input("big input", &Rc::from(BIG_INPUT), |b, input| {
+    g.bench_with_input("big input", &inputs.big, |b, input| {
         b.iter_batched(
             || Rc::clone(input),
             |input| {
@@ -524,7 +617,7 @@ fn bench_component(
             BatchSize::LargeInput,
         );
     });
-    g.bench_with_input("big input byte args", &Rc::from(BIG_INPUT), |b, input| {
+    g.bench_with_input("big input byte args", &inputs.big, |b, input| {
         b.iter_batched(
             || {},
             |()| {
@@ -534,7 +627,7 @@ fn bench_component(
             BatchSize::LargeInput,
         );
     });
-    g.bench_with_input("big input typed args", &Rc::from(BIG_INPUT), |b, input| {
+    g.bench_with_input("big input typed args", &inputs.big, |b, input| {
         b.iter_batched(
             || {
                 let (codec, mut codec_store) = setup_codec();
@@ -595,6 +688,58 @@ fn bench_component(
             BatchSize::LargeInput,
         );
     });
+    let big_ty = export_param_ty(&engine, &runner, "run-big-typed")?;
+    g.bench_with_input(
+        "big input host json typed args",
+        &Rc::from(BIG_INPUT),
+        |b, input| {
+            b.iter_batched(
+                || {},
+                |()| {
+                    let (instance, mut store) = setup_runner_instance();
+                    let v = near_cm::codecs::json::decode(&big_ty, input).unwrap();
+                    call_export(&mut store, &instance, "run-big-typed", v);
+                },
+                BatchSize::LargeInput,
+            );
+        },
+    );
+    let big_borsh = near_cm::codecs::borsh::encode(
+        &big_ty,
+        &near_cm::codecs::json::decode(&big_ty, BIG_INPUT)?,
+    )?;
+    g.bench_with_input(
+        "big input host borsh typed args",
+        &Rc::from(big_borsh),
+        |b, input| {
+            b.iter_batched(
+                || {},
+                |()| {
+                    let (instance, mut store) = setup_runner_instance();
+                    let v = near_cm::codecs::borsh::decode(&big_ty, input).unwrap();
+                    call_export(&mut store, &instance, "run-big-typed", v);
+                },
+                BatchSize::LargeInput,
+            );
+        },
+    );
+    let big_raw =
+        near_cm::codecs::raw::encode(&big_ty, &near_cm::codecs::json::decode(&big_ty, BIG_INPUT)?)?;
+    g.bench_with_input(
+        "big input host raw typed args",
+        &Rc::from(big_raw),
+        |b, input| {
+            b.iter_batched(
+                || {},
+                |()| {
+                    let (instance, mut store) = setup_runner_instance();
+                    let v = near_cm::codecs::raw::decode(&big_ty, input).unwrap();
+                    call_export(&mut store, &instance, "run-big-typed", v);
+                },
+                BatchSize::LargeInput,
+            );
+        },
+    );
     g.bench_function("big input deserialized typed args", |b| {
         b.iter_batched(
             || {
@@ -634,6 +779,77 @@ fn bench_component(
     Ok(())
 }
 
+/// Benchmarks invocations through [`near_cm::Runtime`] of `contract` instantiated on every
+/// invocation against `contract` declared stateless, which reuses pooled instances.
+fn bench_runtime(
+    g: &mut BenchmarkGroup<impl Measurement>,
+    contract: &[u8],
+    config: &wasmtime::Config,
+) -> anyhow::Result<()> {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let storage = near_cm::storage::open("memory")?;
+    let mut runtime = near_cm::Runtime::new(config, storage, near_cm::Options::default())?;
+    runtime.load_contract("fresh", contract)?;
+    runtime.load_contract_with(
+        "pooled",
+        contract,
+        near_cm::ContractOptions { stateless: true },
+    )?;
+
+    for contract in ["fresh", "pooled"] {
+        g.bench_function(format!("noop {contract} instance"), |b| {
+            b.iter(|| {
+                rt.block_on(runtime.invoke(contract, "noop", "json", b"[]"))
+                    .unwrap()
+            });
+        });
+        g.bench_with_input(
+            format!("small input host json typed args {contract} instance"),
+            &[b"[", SMALL_INPUT, b"]"].concat(),
+            |b, input| {
+                b.iter(|| {
+                    rt.block_on(runtime.invoke(contract, "run-small-typed", "json", input))
+                        .unwrap()
+                });
+            },
+        );
+    }
+    Ok(())
+}
+
+/// Returns the type of the single parameter of function `name` exported by `component`.
+fn export_param_ty(
+    engine: &Engine,
+    component: &Component,
+    name: &str,
+) -> anyhow::Result<component::Type> {
+    let Some(component::types::ComponentItem::ComponentFunc(ty)) =
+        component.component_type().get_export(engine, name)
+    else {
+        anyhow::bail!("function `{name}` not exported");
+    };
+    let Some((_, ty)) = ty.params().next() else {
+        anyhow::bail!("function `{name}` has no parameters");
+    };
+    Ok(ty)
+}
+
+/// Calls function `name` exported by `instance` with a single parameter `v`.
+fn call_export<T>(
+    mut store: &mut Store<T>,
+    instance: &component::Instance,
+    name: &str,
+    v: component::Val,
+) {
+    let func = instance
+        .get_func(&mut store, name)
+        .expect("function not exported");
+    func.call(&mut store, &[v], &mut []).unwrap();
+    func.post_return(&mut store).unwrap();
+}
+
 fn build(manifest: impl AsRef<OsStr>) -> anyhow::Result<()> {
     let res = Command::new(env!("CARGO"))
         .args([
@@ -691,84 +907,6 @@ fn compose(runner: impl Into<Vec<u8>>, codec: impl Into<Vec<u8>>) -> anyhow::Res
     Ok(wasm)
 }
 
-// NOTE: This is adapted from current nearcore `main`
-fn new_wasmtime_config(
-    max_memory_pages: u32,
-    max_tables_per_contract: u32,
-    max_elements_per_contract_table: usize,
-) -> wasmtime::Config {
-    /// The maximum amount of concurrent calls this engine can handle.
-    /// If this limit is reached, invocations will block until an execution slot is available.
-    ///
-    /// Wasmtime will use this value to pre-allocate and pool resources internally.
-    /// Wasmtime defaults to `1_000`
-    const MAX_CONCURRENCY: u32 = 1_000;
-
-    /// Value used for [PoolingAllocationConfig::decommit_batch_size]
-    ///
-    /// Wasmtime defaults to `1`
-    const DECOMMIT_BATCH_SIZE: usize = MAX_CONCURRENCY as usize / 2;
-
-    /// Guest page size, in bytes
-    const GUEST_PAGE_SIZE: usize = 1 << 16;
-
-    fn guest_memory_size(pages: u32) -> Option<usize> {
-        let pages = usize::try_from(pages).ok()?;
-        pages.checked_mul(GUEST_PAGE_SIZE)
-    }
-
-    let max_memory_size = guest_memory_size(max_memory_pages).unwrap_or(usize::MAX);
-    let max_tables = MAX_CONCURRENCY.saturating_mul(max_tables_per_contract);
-
-    let mut pooling_config = wasmtime::PoolingAllocationConfig::default();
-    pooling_config
-        .decommit_batch_size(DECOMMIT_BATCH_SIZE)
-        .max_memory_size(max_memory_size)
-        .table_elements(max_elements_per_contract_table)
-        .total_component_instances(MAX_CONCURRENCY)
-        .total_core_instances(MAX_CONCURRENCY)
-        .total_memories(MAX_CONCURRENCY)
-        .total_tables(max_tables)
-        .max_memories_per_module(1)
-        .max_tables_per_module(max_tables_per_contract)
-        .table_keep_resident(max_elements_per_contract_table);
-
-    let mut config = wasmtime::Config::default();
-    config
-        .allocation_strategy(wasmtime::InstanceAllocationStrategy::Pooling(
-            pooling_config,
-        ))
-        // From official documentation:
-        // > Note that systems loading many modules may wish to disable this
-        // > configuration option instead of leaving it on-by-default.
-        // > Some platforms exhibit quadratic behavior when registering/unregistering
-        // > unwinding information which can greatly slow down the module loading/unloading process.
-        // https://docs.rs/wasmtime/latest/wasmtime/struct.Config.html#method.native_unwind_info
-        .native_unwind_info(false)
-        .wasm_backtrace(false)
-        .wasm_backtrace_details(wasmtime::WasmBacktraceDetails::Disable)
-        // Enable copy-on-write heap images.
-        .memory_init_cow(true)
-        // Wasm stack metering is implemented by instrumentation, we don't want wasmtime to trap before that
-        .max_wasm_stack(1024 * 1024 * 1024)
-        // Enable the Cranelift optimizing compiler.
-        .strategy(wasmtime::Strategy::Cranelift)
-        // Enable signals-based traps. This is required to elide explicit bounds-checking.
-        .signals_based_traps(true)
-        // Configure linear memories such that explicit bounds-checking can be elided.
-        .force_memory_init_memfd(true)
-        .memory_guaranteed_dense_image_size(max_memory_size.try_into().unwrap_or(u64::MAX))
-        .guard_before_linear_memory(false)
-        .memory_guard_size(0)
-        .memory_may_move(false)
-        .memory_reservation(max_memory_size.try_into().unwrap_or(u64::MAX))
-        .memory_reservation_for_growth(0)
-        .compiler_inlining(true)
-        .cranelift_nan_canonicalization(true)
-        .wasm_wide_arithmetic(true);
-    config
-}
-
 fn main() -> anyhow::Result<()> {
     let mut c = Criterion::default().configure_from_args();
 
@@ -784,6 +922,11 @@ fn main() -> anyhow::Result<()> {
         "json",
         "Cargo.toml",
     ]))?;
+    build(PathBuf::from_iter([
+        env!("CARGO_MANIFEST_DIR"),
+        "codecs",
+        "Cargo.toml",
+    ]))?;
 
     let module_bundle = fs::read(PathBuf::from_iter([
         env!("CARGO_MANIFEST_DIR"),
@@ -806,7 +949,8 @@ fn main() -> anyhow::Result<()> {
         "component_bundle.wasm",
     ]))
     .context("failed to read `component_bundle.wasm`")?;
-    let mut component_bundle = ComponentEncoder::default().module(&component_bundle)?;
+    let component_bundle_module = component_bundle;
+    let mut component_bundle = ComponentEncoder::default().module(&component_bundle_module)?;
     let component_bundle = component_bundle.encode()?;
 
     let component_codec_import = fs::read(PathBuf::from_iter([
@@ -834,7 +978,42 @@ fn main() -> anyhow::Result<()> {
     let mut codec = ComponentEncoder::default().module(&codec)?;
     let codec = codec.encode()?;
 
+    let cbor = fs::read(PathBuf::from_iter([
+        env!("CARGO_MANIFEST_DIR"),
+        "codecs",
+        "target",
+        "wasm32-unknown-unknown",
+        "release",
+        "cbor.wasm",
+    ]))
+    .context("failed to read `cbor.wasm`")?;
+    let mut cbor = ComponentEncoder::default().module(&cbor)?;
+    let cbor = cbor.encode()?;
+
+    let msgpack = fs::read(PathBuf::from_iter([
+        env!("CARGO_MANIFEST_DIR"),
+        "codecs",
+        "target",
+        "wasm32-unknown-unknown",
+        "release",
+        "msgpack.wasm",
+    ]))
+    .context("failed to read `msgpack.wasm`")?;
+    let mut msgpack = ComponentEncoder::default().module(&msgpack)?;
+    let msgpack = msgpack.encode()?;
+
     let component_composed = compose(component_codec_import.as_slice(), codec.as_slice())?;
+    let component_composed_cbor = compose(component_codec_import.as_slice(), cbor.as_slice())?;
+    let component_composed_msgpack =
+        compose(component_codec_import.as_slice(), msgpack.as_slice())?;
+
+    let json_inputs = Inputs::json();
+    let cbor_inputs = Inputs::transcode(|v| {
+        let mut buf = Vec::default();
+        ciborium::into_writer(v, &mut buf)?;
+        Ok(buf)
+    })?;
+    let msgpack_inputs = Inputs::transcode(|v| Ok(rmp_serde::to_vec(v)?))?;
 
     let config = new_wasmtime_config(2_048, 1, 10_000);
 
@@ -845,12 +1024,39 @@ fn main() -> anyhow::Result<()> {
     }
     {
         let mut g = c.benchmark_group("component bundling serde_json");
-        bench_component(&mut g, &component_bundle, &codec, &config)?;
+        bench_component(&mut g, &component_bundle, &codec, &json_inputs, &config)?;
         g.finish();
     }
     {
         let mut g = c.benchmark_group("component composed with codec");
-        bench_component(&mut g, &component_composed, &codec, &config)?;
+        bench_component(&mut g, &component_composed, &codec, &json_inputs, &config)?;
+        g.finish();
+    }
+    {
+        let mut g = c.benchmark_group("component composed with cbor codec");
+        bench_component(
+            &mut g,
+            &component_composed_cbor,
+            &cbor,
+            &cbor_inputs,
+            &config,
+        )?;
+        g.finish();
+    }
+    {
+        let mut g = c.benchmark_group("component composed with msgpack codec");
+        bench_component(
+            &mut g,
+            &component_composed_msgpack,
+            &msgpack,
+            &msgpack_inputs,
+            &config,
+        )?;
+        g.finish();
+    }
+    {
+        let mut g = c.benchmark_group("runtime instance pooling");
+        bench_runtime(&mut g, &component_bundle_module, &config)?;
         g.finish();
     }
     c.final_summary();
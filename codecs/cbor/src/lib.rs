@@ -0,0 +1,44 @@
+//! CBOR codec component
+
+use std::cell::Cell;
+
+use codec::bindings::exports::cosmonic::reflect::reflect::Value;
+use codec::{Format, Seed, Ty};
+use serde::de::{self, DeserializeSeed as _};
+
+struct Cbor;
+
+type Component = codec::Codec<Cbor>;
+
+codec::bindings::export!(Component with_types_in codec::bindings);
+
+thread_local! {
+    /// Type of the value being deserialized by [`Root`]
+    static ROOT_TY: Cell<Option<Ty>> = const { Cell::new(None) };
+}
+
+/// Root value, `ciborium` only supports deserializing types implementing
+/// [`de::DeserializeOwned`], so the type is passed in [`ROOT_TY`].
+struct Root(Value);
+
+impl<'de> de::Deserialize<'de> for Root {
+    fn deserialize<D: de::Deserializer<'de>>(de: D) -> Result<Self, D::Error> {
+        let ty = ROOT_TY
+            .take()
+            .ok_or_else(|| de::Error::custom("root type not set"))?;
+        Seed(&ty).deserialize(de).map(Self)
+    }
+}
+
+impl Format for Cbor {
+    type Error = String;
+
+    fn deserialize(mut buf: &[u8], Seed(ty): Seed<'_>) -> Result<Value, Self::Error> {
+        ROOT_TY.set(Some(ty.clone()));
+        let Root(v) = ciborium::from_reader(&mut buf).map_err(|err| err.to_string())?;
+        if !buf.is_empty() {
+            return Err(format!("{} trailing bytes", buf.len()));
+        }
+        Ok(v)
+    }
+}
@@ -0,0 +1,48 @@
+use std::path::PathBuf;
+use std::{env, fs};
+
+use anyhow::Context as _;
+use wit_bindgen_core::Files;
+use wit_bindgen_core::wit_parser::Resolve;
+
+fn main() -> anyhow::Result<()> {
+    let wit = PathBuf::from_iter([env!("CARGO_MANIFEST_DIR"), "..", "..", "wit"]);
+    println!("cargo::rerun-if-changed={}", wit.display());
+
+    let mut resolve = Resolve::default();
+    let (pkg, _) = resolve.push_path(&wit)?;
+    let world = resolve.select_world(&[pkg], Some("codec"))?;
+    let mut files = Files::default();
+    wit_bindgen_rust::Opts {
+        pub_export_macro: true,
+        generate_all: true,
+        ..Default::default()
+    }
+    .build()
+    .generate(&resolve, world, &mut files)?;
+    let (_, src) = files.iter().next().context("no bindings generated")?;
+    let src = str::from_utf8(src)?;
+
+    // `wit-bindgen` does not import borrowed resource types of `cosmonic:reflect/reflect`
+    // used by the exported deserializer interfaces
+    let mut src = src.to_string();
+    for name in ["deserializer", "stream_deserializer"] {
+        let module = format!("pub mod {name} {{");
+        anyhow::ensure!(src.contains(&module), "`{name}` module not found");
+        src = src.replacen(
+            &module,
+            &format!(
+                "{module}
+                #[allow(unused_imports)]
+                use crate::bindings::exports::cosmonic::reflect::reflect::{{
+                    EnumTypeBorrow, FlagsTypeBorrow, OptionTypeBorrow, RecordTypeBorrow,
+                    ResultTypeBorrow, TupleTypeBorrow, VariantTypeBorrow,
+                }};"
+            ),
+            1,
+        );
+    }
+    let out = PathBuf::from(env::var("OUT_DIR")?).join("format.rs");
+    fs::write(out, src)?;
+    Ok(())
+}
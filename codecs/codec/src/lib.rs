@@ -0,0 +1,730 @@
+//! Implementation of the `near-cm:codec/codec` world shared by codecs for
+//! self-describing `serde` formats.
+//!
+//! A codec implements [`Format`] and exports [`Codec`]:
+//!
+//! ```ignore
+//! type Component = codec::Codec<Cbor>;
+//! codec::bindings::export!(Component with_types_in codec::bindings);
+//! ```
+//!
+//! Values are expected in the shape `serde` derives produce for the corresponding Rust types:
+//! records are maps keyed by field names or sequences of fields in declaration order,
+//! variants are `{"<case>": <payload>}` or `"<case>"` for cases without payload, results
+//! are `{"ok": <payload>}` or `{"err": <payload>}` and flags are sequences of flag names.
+
+use core::cell::RefCell;
+use core::fmt;
+use core::marker::PhantomData;
+
+use std::rc::Rc;
+
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Unexpected, Visitor};
+
+/// Bindings generated by `build.rs`
+#[allow(clippy::all)]
+pub mod bindings {
+    include!(concat!(env!("OUT_DIR"), "/format.rs"));
+}
+
+use bindings::exports::cosmonic::reflect::reflect::{
+    self, List, ListType, RecordValue, ResultValue, TupleValue, Type, Value, VariantValue,
+};
+use bindings::exports::cosmonic::serde::deserializer;
+use bindings::exports::near_cm::codec::{stream_deserializer, type_builder};
+use bindings::near_cm::host::descriptor::{Node, NodeIndex, TypeDescriptor};
+
+/// `serde` data format
+pub trait Format: 'static {
+    type Error: fmt::Display;
+
+    /// Deserializes a single value from `buf` using `seed`, failing on trailing bytes.
+    fn deserialize(buf: &[u8], seed: Seed<'_>) -> Result<Value, Self::Error>;
+}
+
+/// Component implementing the `codec` world for [`Format`] `F`
+pub struct Codec<F>(PhantomData<F>);
+
+/// Owned representation of a reflect type
+#[derive(Clone, Debug)]
+pub enum Ty {
+    Bool,
+    U8,
+    U16,
+    U32,
+    U64,
+    S8,
+    S16,
+    S32,
+    S64,
+    F32,
+    F64,
+    Char,
+    String,
+    List(Rc<Ty>),
+    Record(Rc<[(String, Ty)]>),
+    Tuple(Rc<[Ty]>),
+    Variant(Rc<[(String, Option<Ty>)]>),
+    Enum(Rc<[String]>),
+    Option(Rc<Ty>),
+    Result(Rc<(Option<Ty>, Option<Ty>)>),
+    Flags(Rc<[String]>),
+}
+
+impl Ty {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Bool => "bool",
+            Self::U8 => "u8",
+            Self::U16 => "u16",
+            Self::U32 => "u32",
+            Self::U64 => "u64",
+            Self::S8 => "s8",
+            Self::S16 => "s16",
+            Self::S32 => "s32",
+            Self::S64 => "s64",
+            Self::F32 => "f32",
+            Self::F64 => "f64",
+            Self::Char => "char",
+            Self::String => "string",
+            Self::List(..) => "list",
+            Self::Record(..) => "record",
+            Self::Tuple(..) => "tuple",
+            Self::Variant(..) => "variant",
+            Self::Enum(..) => "enum",
+            Self::Option(..) => "option",
+            Self::Result(..) => "result",
+            Self::Flags(..) => "flags",
+        }
+    }
+}
+
+impl From<&Type<'_>> for Ty {
+    fn from(ty: &Type<'_>) -> Self {
+        match ty {
+            Type::Bool => Self::Bool,
+            Type::U8 => Self::U8,
+            Type::U16 => Self::U16,
+            Type::U32 => Self::U32,
+            Type::U64 => Self::U64,
+            Type::S8 => Self::S8,
+            Type::S16 => Self::S16,
+            Type::S32 => Self::S32,
+            Type::S64 => Self::S64,
+            Type::F32 => Self::F32,
+            Type::F64 => Self::F64,
+            Type::Char => Self::Char,
+            Type::String => Self::String,
+            Type::List(ty) => Self::List(Rc::new(ty.into())),
+            Type::Record(ty) => Self::Record(Rc::clone(&ty.get::<RecordTy>().0)),
+            Type::Tuple(ty) => Self::Tuple(Rc::clone(&ty.get::<TupleTy>().0)),
+            Type::Variant(ty) => Self::Variant(Rc::clone(&ty.get::<VariantTy>().0)),
+            Type::Enum(ty) => Self::Enum(Rc::clone(&ty.get::<EnumTy>().0)),
+            Type::Option(ty) => Self::Option(Rc::clone(&ty.get::<OptionTy>().0)),
+            Type::Result(ty) => Self::Result(Rc::clone(&ty.get::<ResultTy>().0)),
+            Type::Flags(ty) => Self::Flags(Rc::clone(&ty.get::<FlagsTy>().0)),
+        }
+    }
+}
+
+impl From<&ListType<'_>> for Ty {
+    fn from(ty: &ListType<'_>) -> Self {
+        match ty {
+            ListType::Bool => Self::Bool,
+            ListType::U8 => Self::U8,
+            ListType::U16 => Self::U16,
+            ListType::U32 => Self::U32,
+            ListType::U64 => Self::U64,
+            ListType::S8 => Self::S8,
+            ListType::S16 => Self::S16,
+            ListType::S32 => Self::S32,
+            ListType::S64 => Self::S64,
+            ListType::F32 => Self::F32,
+            ListType::F64 => Self::F64,
+            ListType::Char => Self::Char,
+            ListType::String => Self::String,
+            ListType::Record(ty) => Self::Record(Rc::clone(&ty.get::<RecordTy>().0)),
+            ListType::Tuple(ty) => Self::Tuple(Rc::clone(&ty.get::<TupleTy>().0)),
+            ListType::Variant(ty) => Self::Variant(Rc::clone(&ty.get::<VariantTy>().0)),
+            ListType::Enum(ty) => Self::Enum(Rc::clone(&ty.get::<EnumTy>().0)),
+            ListType::Option(ty) => Self::Option(Rc::clone(&ty.get::<OptionTy>().0)),
+            ListType::Result(ty) => Self::Result(Rc::clone(&ty.get::<ResultTy>().0)),
+            ListType::Flags(ty) => Self::Flags(Rc::clone(&ty.get::<FlagsTy>().0)),
+        }
+    }
+}
+
+pub struct RecordTy(Rc<[(String, Ty)]>);
+
+impl reflect::GuestRecordType for RecordTy {
+    fn new(fields: Vec<(String, Type<'_>)>) -> Self {
+        Self(
+            fields
+                .into_iter()
+                .map(|(name, ty)| (name, Ty::from(&ty)))
+                .collect(),
+        )
+    }
+}
+
+pub struct TupleTy(Rc<[Ty]>);
+
+impl reflect::GuestTupleType for TupleTy {
+    fn new(types: Vec<Type<'_>>) -> Self {
+        Self(types.iter().map(Ty::from).collect())
+    }
+}
+
+pub struct VariantTy(Rc<[(String, Option<Ty>)]>);
+
+impl reflect::GuestVariantType for VariantTy {
+    fn new(cases: Vec<(String, Option<Type<'_>>)>) -> Self {
+        Self(
+            cases
+                .into_iter()
+                .map(|(name, ty)| (name, ty.as_ref().map(Ty::from)))
+                .collect(),
+        )
+    }
+}
+
+pub struct EnumTy(Rc<[String]>);
+
+impl reflect::GuestEnumType for EnumTy {
+    fn new(cases: Vec<String>) -> Self {
+        Self(cases.into())
+    }
+}
+
+pub struct FlagsTy(Rc<[String]>);
+
+impl reflect::GuestFlagsType for FlagsTy {
+    fn new(names: Vec<String>) -> Self {
+        Self(names.into())
+    }
+}
+
+pub struct OptionTy(Rc<Ty>);
+
+impl reflect::GuestOptionType for OptionTy {
+    fn new(ty: Type<'_>) -> Self {
+        Self(Rc::new((&ty).into()))
+    }
+}
+
+pub struct ResultTy(Rc<(Option<Ty>, Option<Ty>)>);
+
+impl reflect::GuestResultType for ResultTy {
+    fn new(ok: Option<Type<'_>>, err: Option<Type<'_>>) -> Self {
+        Self(Rc::new((
+            ok.as_ref().map(Ty::from),
+            err.as_ref().map(Ty::from),
+        )))
+    }
+}
+
+pub struct RecordVal(Vec<Value>);
+
+impl reflect::GuestRecordValue for RecordVal {
+    fn into_value(v: RecordValue) -> Vec<Value> {
+        v.into_inner::<Self>().0
+    }
+}
+
+pub struct TupleVal(Vec<Value>);
+
+impl reflect::GuestTupleValue for TupleVal {
+    fn into_value(v: TupleValue) -> Vec<Value> {
+        v.into_inner::<Self>().0
+    }
+}
+
+pub struct VariantVal(u32, Option<Value>);
+
+impl reflect::GuestVariantValue for VariantVal {
+    fn into_value(v: VariantValue) -> (u32, Option<Value>) {
+        let Self(i, v) = v.into_inner();
+        (i, v)
+    }
+}
+
+pub struct OptionVal(Option<Value>);
+
+impl reflect::GuestOptionValue for OptionVal {
+    fn into_value(v: reflect::OptionValue) -> Option<Value> {
+        v.into_inner::<Self>().0
+    }
+}
+
+pub struct ResultVal(Result<Option<Value>, Option<Value>>);
+
+impl reflect::GuestResultValue for ResultVal {
+    fn into_value(v: ResultValue) -> Result<Option<Value>, Option<Value>> {
+        v.into_inner::<Self>().0
+    }
+}
+
+impl<F: Format> reflect::Guest for Codec<F> {
+    type RecordType = RecordTy;
+    type TupleType = TupleTy;
+    type VariantType = VariantTy;
+    type EnumType = EnumTy;
+    type FlagsType = FlagsTy;
+    type OptionType = OptionTy;
+    type ResultType = ResultTy;
+    type RecordValue = RecordVal;
+    type TupleValue = TupleVal;
+    type VariantValue = VariantVal;
+    type OptionValue = OptionVal;
+    type ResultValue = ResultVal;
+}
+
+pub struct Error(String);
+
+impl deserializer::GuestError for Error {
+    fn to_string(&self) -> String {
+        self.0.clone()
+    }
+}
+
+impl<F: Format> deserializer::Guest for Codec<F> {
+    type Error = Error;
+
+    fn from_list(buf: Vec<u8>, ty: Type<'_>) -> Result<Value, deserializer::Error> {
+        let ty = Ty::from(&ty);
+        F::deserialize(&buf, Seed(&ty))
+            .map_err(|err| deserializer::Error::new(Error(err.to_string())))
+    }
+}
+
+/// Decoder buffering chunks until the whole encoding has been pushed,
+/// since [`Format`]s deserialize complete buffers.
+pub struct Decoder<F> {
+    ty: Ty,
+    buf: RefCell<Vec<u8>>,
+    format: PhantomData<F>,
+}
+
+impl<F: Format> stream_deserializer::GuestDecoder for Decoder<F> {
+    fn new(ty: Type<'_>) -> Self {
+        Self {
+            ty: Ty::from(&ty),
+            buf: RefCell::default(),
+            format: PhantomData,
+        }
+    }
+
+    fn push(&self, buf: Vec<u8>) -> Result<(), deserializer::Error> {
+        self.buf.borrow_mut().extend_from_slice(&buf);
+        Ok(())
+    }
+
+    fn finish(this: stream_deserializer::Decoder) -> Result<Value, deserializer::Error> {
+        let Self { ty, buf, .. } = this.into_inner();
+        F::deserialize(&buf.into_inner(), Seed(&ty))
+            .map_err(|err| deserializer::Error::new(Error(err.to_string())))
+    }
+}
+
+impl<F: Format> stream_deserializer::Guest for Codec<F> {
+    type Decoder = Decoder<F>;
+}
+
+impl<F: Format> type_builder::Guest for Codec<F> {
+    fn build_tuple(descriptor: TypeDescriptor) -> Result<reflect::TupleType, String> {
+        let mut tys: Vec<Ty> = Vec::with_capacity(descriptor.nodes.len());
+        for node in descriptor.nodes {
+            let ty = |i: NodeIndex| {
+                tys.get(i as usize)
+                    .cloned()
+                    .ok_or_else(|| format!("node index `{i}` out of range"))
+            };
+            let ty = match node {
+                Node::Bool => Ty::Bool,
+                Node::U8 => Ty::U8,
+                Node::U16 => Ty::U16,
+                Node::U32 => Ty::U32,
+                Node::U64 => Ty::U64,
+                Node::S8 => Ty::S8,
+                Node::S16 => Ty::S16,
+                Node::S32 => Ty::S32,
+                Node::S64 => Ty::S64,
+                Node::F32 => Ty::F32,
+                Node::F64 => Ty::F64,
+                Node::Char => Ty::Char,
+                Node::String => Ty::String,
+                Node::List(i) => match ty(i)? {
+                    // `list-type` has no `list` case
+                    Ty::List(..) => return Err("lists of lists not supported".into()),
+                    ty => Ty::List(Rc::new(ty)),
+                },
+                Node::Record(fields) => Ty::Record(
+                    fields
+                        .into_iter()
+                        .map(|(name, i)| Ok((name, ty(i)?)))
+                        .collect::<Result<_, String>>()?,
+                ),
+                Node::Tuple(types) => {
+                    Ty::Tuple(types.into_iter().map(ty).collect::<Result<_, String>>()?)
+                }
+                Node::Variant(cases) => Ty::Variant(
+                    cases
+                        .into_iter()
+                        .map(|(name, i)| Ok((name, i.map(ty).transpose()?)))
+                        .collect::<Result<_, String>>()?,
+                ),
+                Node::Enum(names) => Ty::Enum(names.into()),
+                Node::Option(i) => Ty::Option(Rc::new(ty(i)?)),
+                Node::Result((ok, err)) => {
+                    Ty::Result(Rc::new((ok.map(ty).transpose()?, err.map(ty).transpose()?)))
+                }
+                // Flags are represented as a `u32` bit set
+                Node::Flags(names) if names.len() > 32 => {
+                    return Err("flags with more than 32 names not supported".into());
+                }
+                Node::Flags(names) => Ty::Flags(names.into()),
+            };
+            tys.push(ty);
+        }
+        match tys.pop() {
+            Some(Ty::Tuple(tys)) => Ok(reflect::TupleType::new(TupleTy(tys))),
+            _ => Err("type descriptor does not describe a tuple".into()),
+        }
+    }
+}
+
+/// Returns whether WIT name `name` matches key `key`.
+fn name_matches(name: &str, key: &str) -> bool {
+    name.len() == key.len()
+        && name
+            .bytes()
+            .zip(key.bytes())
+            .all(|(n, k)| n == k || n == b'-' && k == b'_')
+}
+
+/// Value of type `.0`
+#[derive(Clone, Copy)]
+pub struct Seed<'a>(pub &'a Ty);
+
+impl<'de> DeserializeSeed<'de> for Seed<'_> {
+    type Value = Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, de: D) -> Result<Self::Value, D::Error> {
+        match self.0 {
+            Ty::Option(..) => de.deserialize_option(self),
+            _ => de.deserialize_any(self),
+        }
+    }
+}
+
+/// Deserializes the remaining elements of `seq` as values of type `ty`.
+fn next_elements<'de, A: SeqAccess<'de>>(mut seq: A, ty: &Ty) -> Result<Vec<Value>, A::Error> {
+    let mut vs = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+    while let Some(v) = seq.next_element_seed(Seed(ty))? {
+        vs.push(v);
+    }
+    Ok(vs)
+}
+
+/// Deserializes exactly `tys.len()` elements of `seq`.
+fn exact_elements<'a, 'de, A: SeqAccess<'de>>(
+    mut seq: A,
+    tys: impl ExactSizeIterator<Item = &'a Ty>,
+) -> Result<Vec<Value>, A::Error> {
+    let n = tys.len();
+    let mut vs = Vec::with_capacity(n);
+    for (i, ty) in tys.enumerate() {
+        let Some(v) = seq.next_element_seed(Seed(ty))? else {
+            return Err(de::Error::invalid_length(
+                i,
+                &format!("{n} elements").as_str(),
+            ));
+        };
+        vs.push(v);
+    }
+    if seq.next_element::<de::IgnoredAny>()?.is_some() {
+        return Err(de::Error::invalid_length(
+            n + 1,
+            &format!("{n} elements").as_str(),
+        ));
+    }
+    Ok(vs)
+}
+
+macro_rules! next_list {
+    ($seq:ident, $t:ty, $case:ident) => {{
+        let mut vs = Vec::with_capacity($seq.size_hint().unwrap_or_default());
+        while let Some(v) = $seq.next_element::<$t>()? {
+            vs.push(v);
+        }
+        List::$case(vs)
+    }};
+}
+
+macro_rules! unwrap_list {
+    ($vs:ident, $case:ident) => {
+        List::$case(
+            $vs.into_iter()
+                .map(|v| match v {
+                    Value::$case(v) => v,
+                    _ => unreachable!("value does not match list element type"),
+                })
+                .collect(),
+        )
+    };
+}
+
+impl<'de> Visitor<'de> for Seed<'_> {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a value of type `{}`", self.0.name())
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Self::Value, E> {
+        match self.0 {
+            Ty::Bool => Ok(Value::Bool(v)),
+            _ => Err(E::invalid_type(Unexpected::Bool(v), &self)),
+        }
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        let err = || E::invalid_value(Unexpected::Unsigned(v), &self);
+        match self.0 {
+            Ty::U8 => v.try_into().map(Value::U8).map_err(|_| err()),
+            Ty::U16 => v.try_into().map(Value::U16).map_err(|_| err()),
+            Ty::U32 => v.try_into().map(Value::U32).map_err(|_| err()),
+            Ty::U64 => Ok(Value::U64(v)),
+            Ty::S8 => v.try_into().map(Value::S8).map_err(|_| err()),
+            Ty::S16 => v.try_into().map(Value::S16).map_err(|_| err()),
+            Ty::S32 => v.try_into().map(Value::S32).map_err(|_| err()),
+            Ty::S64 => v.try_into().map(Value::S64).map_err(|_| err()),
+            Ty::F32 => Ok(Value::F32(v as _)),
+            Ty::F64 => Ok(Value::F64(v as _)),
+            _ => Err(E::invalid_type(Unexpected::Unsigned(v), &self)),
+        }
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        let err = || E::invalid_value(Unexpected::Signed(v), &self);
+        match self.0 {
+            Ty::U8 => v.try_into().map(Value::U8).map_err(|_| err()),
+            Ty::U16 => v.try_into().map(Value::U16).map_err(|_| err()),
+            Ty::U32 => v.try_into().map(Value::U32).map_err(|_| err()),
+            Ty::U64 => v.try_into().map(Value::U64).map_err(|_| err()),
+            Ty::S8 => v.try_into().map(Value::S8).map_err(|_| err()),
+            Ty::S16 => v.try_into().map(Value::S16).map_err(|_| err()),
+            Ty::S32 => v.try_into().map(Value::S32).map_err(|_| err()),
+            Ty::S64 => Ok(Value::S64(v)),
+            Ty::F32 => Ok(Value::F32(v as _)),
+            Ty::F64 => Ok(Value::F64(v as _)),
+            _ => Err(E::invalid_type(Unexpected::Signed(v), &self)),
+        }
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+        match self.0 {
+            Ty::F32 => Ok(Value::F32(v as _)),
+            Ty::F64 => Ok(Value::F64(v)),
+            _ => Err(E::invalid_type(Unexpected::Float(v), &self)),
+        }
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        match self.0 {
+            Ty::String => Ok(Value::String(v.into())),
+            Ty::Char => {
+                let mut cs = v.chars();
+                match (cs.next(), cs.next()) {
+                    (Some(c), None) => Ok(Value::Char(c)),
+                    _ => Err(E::invalid_value(Unexpected::Str(v), &self)),
+                }
+            }
+            Ty::Enum(cases) => match cases.iter().position(|case| case == v) {
+                Some(i) => Ok(Value::Enum(i as _)),
+                None => Err(E::unknown_variant(v, &[])),
+            },
+            Ty::Variant(cases) => match cases.iter().position(|(case, _)| case == v) {
+                Some(i) if cases[i].1.is_none() => {
+                    Ok(Value::Variant(VariantValue::new(VariantVal(i as _, None))))
+                }
+                Some(..) => Err(E::custom(format_args!(
+                    "variant case `{v}` requires a payload"
+                ))),
+                None => Err(E::unknown_variant(v, &[])),
+            },
+            _ => Err(E::invalid_type(Unexpected::Str(v), &self)),
+        }
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Self::Value, E> {
+        match self.0 {
+            Ty::String => Ok(Value::String(v)),
+            _ => self.visit_str(&v),
+        }
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        match self.0 {
+            Ty::List(ty) if matches!(**ty, Ty::U8) => Ok(Value::List(List::U8(v.into()))),
+            Ty::String => match str::from_utf8(v) {
+                Ok(s) => Ok(Value::String(s.into())),
+                Err(..) => Err(E::invalid_value(Unexpected::Bytes(v), &self)),
+            },
+            _ => Err(E::invalid_type(Unexpected::Bytes(v), &self)),
+        }
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        match self.0 {
+            Ty::Option(..) => Ok(Value::Option(reflect::OptionValue::new(OptionVal(None)))),
+            _ => Err(E::invalid_type(Unexpected::Option, &self)),
+        }
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        self.visit_none()
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, de: D) -> Result<Self::Value, D::Error> {
+        match self.0 {
+            Ty::Option(ty) => {
+                let v = Seed(ty).deserialize(de)?;
+                Ok(Value::Option(reflect::OptionValue::new(OptionVal(Some(v)))))
+            }
+            _ => Err(de::Error::invalid_type(Unexpected::Option, &self)),
+        }
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        match self.0 {
+            Ty::List(ty) => {
+                let list = match &**ty {
+                    Ty::Bool => next_list!(seq, bool, Bool),
+                    Ty::U8 => next_list!(seq, u8, U8),
+                    Ty::U16 => next_list!(seq, u16, U16),
+                    Ty::U32 => next_list!(seq, u32, U32),
+                    Ty::U64 => next_list!(seq, u64, U64),
+                    Ty::S8 => next_list!(seq, i8, S8),
+                    Ty::S16 => next_list!(seq, i16, S16),
+                    Ty::S32 => next_list!(seq, i32, S32),
+                    Ty::S64 => next_list!(seq, i64, S64),
+                    Ty::F32 => next_list!(seq, f32, F32),
+                    Ty::F64 => next_list!(seq, f64, F64),
+                    Ty::Char => next_list!(seq, char, Char),
+                    Ty::String => next_list!(seq, String, String),
+                    ty => {
+                        let vs = next_elements(seq, ty)?;
+                        match ty {
+                            Ty::Record(..) => unwrap_list!(vs, Record),
+                            Ty::Tuple(..) => unwrap_list!(vs, Tuple),
+                            Ty::Variant(..) => unwrap_list!(vs, Variant),
+                            Ty::Enum(..) => unwrap_list!(vs, Enum),
+                            Ty::Option(..) => unwrap_list!(vs, Option),
+                            Ty::Result(..) => unwrap_list!(vs, Result),
+                            Ty::Flags(..) => unwrap_list!(vs, Flags),
+                            // `list-type` has no `list` case
+                            _ => unreachable!("invalid list element type"),
+                        }
+                    }
+                };
+                Ok(Value::List(list))
+            }
+            Ty::Tuple(tys) => {
+                let vs = exact_elements(seq, tys.iter())?;
+                Ok(Value::Tuple(TupleValue::new(TupleVal(vs))))
+            }
+            // Records serialized as sequences of fields in declaration order
+            Ty::Record(fields) => {
+                let vs = exact_elements(seq, fields.iter().map(|(_, ty)| ty))?;
+                Ok(Value::Record(RecordValue::new(RecordVal(vs))))
+            }
+            Ty::Flags(names) => {
+                let mut flags = 0u32;
+                while let Some(flag) = seq.next_element::<String>()? {
+                    let Some(i) = names.iter().position(|name| *name == flag) else {
+                        return Err(de::Error::custom(format_args!("unknown flag `{flag}`")));
+                    };
+                    flags |= 1 << i;
+                }
+                Ok(Value::Flags(flags))
+            }
+            _ => Err(de::Error::invalid_type(Unexpected::Seq, &self)),
+        }
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        match self.0 {
+            Ty::Record(fields) => {
+                let mut vs = Vec::with_capacity(fields.len());
+                vs.resize_with(fields.len(), || None);
+                while let Some(key) = map.next_key::<String>()? {
+                    let Some(i) = fields.iter().position(|(name, _)| name_matches(name, &key))
+                    else {
+                        return Err(de::Error::custom(format_args!("unknown field `{key}`")));
+                    };
+                    if vs[i].is_some() {
+                        return Err(de::Error::custom(format_args!("duplicate field `{key}`")));
+                    }
+                    vs[i] = Some(map.next_value_seed(Seed(&fields[i].1))?);
+                }
+                let mut record = Vec::with_capacity(fields.len());
+                for ((name, ty), v) in fields.iter().zip(vs) {
+                    let v = match (v, ty) {
+                        (Some(v), _) => v,
+                        (None, Ty::Option(..)) => {
+                            Value::Option(reflect::OptionValue::new(OptionVal(None)))
+                        }
+                        (None, _) => {
+                            return Err(de::Error::custom(format_args!("missing field `{name}`")));
+                        }
+                    };
+                    record.push(v);
+                }
+                Ok(Value::Record(RecordValue::new(RecordVal(record))))
+            }
+            Ty::Variant(cases) => {
+                let Some(key) = map.next_key::<String>()? else {
+                    return Err(de::Error::invalid_length(0, &self));
+                };
+                let Some(i) = cases.iter().position(|(case, _)| *case == key) else {
+                    return Err(de::Error::unknown_variant(&key, &[]));
+                };
+                let v = if let Some(ty) = &cases[i].1 {
+                    Some(map.next_value_seed(Seed(ty))?)
+                } else {
+                    map.next_value::<()>()?;
+                    None
+                };
+                if map.next_key::<de::IgnoredAny>()?.is_some() {
+                    return Err(de::Error::invalid_length(2, &self));
+                }
+                Ok(Value::Variant(VariantValue::new(VariantVal(i as _, v))))
+            }
+            Ty::Result(tys) => {
+                let Some(key) = map.next_key::<String>()? else {
+                    return Err(de::Error::invalid_length(0, &self));
+                };
+                let payload = |map: &mut A, ty: &Option<Ty>| {
+                    if let Some(ty) = ty {
+                        map.next_value_seed(Seed(ty)).map(Some)
+                    } else {
+                        map.next_value::<()>().map(|()| None)
+                    }
+                };
+                let (ok, err) = &**tys;
+                // `serde` serializes Rust `Result` using capitalized case names
+                let v = match key.as_str() {
+                    "ok" | "Ok" => Ok(payload(&mut map, ok)?),
+                    "err" | "Err" => Err(payload(&mut map, err)?),
+                    _ => return Err(de::Error::unknown_variant(&key, &["ok", "err"])),
+                };
+                if map.next_key::<de::IgnoredAny>()?.is_some() {
+                    return Err(de::Error::invalid_length(2, &self));
+                }
+                Ok(Value::Result(ResultValue::new(ResultVal(v))))
+            }
+            _ => Err(de::Error::invalid_type(Unexpected::Map, &self)),
+        }
+    }
+}
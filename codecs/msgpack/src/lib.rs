@@ -0,0 +1,27 @@
+//! MessagePack codec component
+
+use std::io::Cursor;
+
+use codec::bindings::exports::cosmonic::reflect::reflect::Value;
+use codec::{Format, Seed};
+use serde::de::DeserializeSeed as _;
+
+struct MessagePack;
+
+type Component = codec::Codec<MessagePack>;
+
+codec::bindings::export!(Component with_types_in codec::bindings);
+
+impl Format for MessagePack {
+    type Error = String;
+
+    fn deserialize(buf: &[u8], seed: Seed<'_>) -> Result<Value, Self::Error> {
+        let mut de = rmp_serde::Deserializer::new(Cursor::new(buf));
+        let v = seed.deserialize(&mut de).map_err(|err| err.to_string())?;
+        let n = buf.len() as u64 - de.position();
+        if n > 0 {
+            return Err(format!("{n} trailing bytes"));
+        }
+        Ok(v)
+    }
+}
@@ -0,0 +1,53 @@
+mod bindings {
+    use crate::Component;
+
+    wit_bindgen::generate!({
+        generate_all,
+    });
+
+    export!(Component);
+}
+
+use core::cell::{Cell, RefCell};
+
+use bindings::exports::myapp::builder::greeting;
+
+struct Component;
+
+struct Builder {
+    name: String,
+    greeting: RefCell<String>,
+    exclaim: Cell<bool>,
+}
+
+impl greeting::GuestBuilder for Builder {
+    fn new(name: String) -> Self {
+        Self {
+            name,
+            greeting: RefCell::new("Hello".into()),
+            exclaim: Cell::new(false),
+        }
+    }
+
+    fn greeting(&self, greeting: String) {
+        self.greeting.replace(greeting);
+    }
+
+    fn exclaim(&self) {
+        self.exclaim.set(true);
+    }
+
+    fn build(this: greeting::Builder) -> String {
+        let Self {
+            name,
+            greeting,
+            exclaim,
+        } = this.into_inner();
+        let punctuation = if exclaim.get() { "!" } else { "" };
+        format!("{}, {name}{punctuation}", greeting.into_inner())
+    }
+}
+
+impl greeting::Guest for Component {
+    type Builder = Builder;
+}
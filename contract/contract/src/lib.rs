@@ -1,12 +1,24 @@
 mod bindings {
     use crate::Component;
 
-    wit_bindgen::generate!();
+    wit_bindgen::generate!({
+        generate_all,
+    });
 
     export!(Component);
 }
 
-use bindings::exports::myapp::app::custom::{Guest, TestRecord};
+use std::sync::Once;
+
+use bindings::exports::myapp::app::custom::{Guest, Op, TestRecord};
+use bindings::near_cm::host::panic;
+
+/// Reports panic messages to the host, which returns them to the caller
+/// instead of a generic trap.
+fn set_panic_hook() {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| std::panic::set_hook(Box::new(|info| panic::abort(&info.to_string()))));
+}
 
 struct Component;
 
@@ -20,8 +32,21 @@ impl Guest for Component {
     }
 
     fn foo(t: TestRecord) -> u64 {
+        set_panic_hook();
         assert_eq!(t.foo, "myfoo");
         assert_eq!(t.bar, "mybar");
         bindings::mul(6, 7)
     }
+
+    fn sum(xs: Vec<u64>) -> u64 {
+        xs.into_iter().fold(0, u64::saturating_add)
+    }
+
+    fn fold(op: Op, xs: Vec<Option<u64>>) -> Result<u64, String> {
+        let xs = xs.into_iter().flatten();
+        match op {
+            Op::Add => Ok(xs.fold(0, u64::saturating_add)),
+            Op::Max => xs.max().ok_or_else(|| "no values".into()),
+        }
+    }
 }
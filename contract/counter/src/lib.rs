@@ -0,0 +1,38 @@
+mod bindings {
+    use crate::Component;
+
+    wit_bindgen::generate!({
+        generate_all,
+    });
+
+    export!(Component);
+}
+
+use bindings::near_cm::host::{events, log, storage};
+
+const KEY: &[u8] = b"count";
+
+struct Component;
+
+impl bindings::Guest for Component {
+    fn get() -> u64 {
+        let Some(buf) = storage::read(KEY) else {
+            return 0;
+        };
+        let buf = buf.try_into().expect("invalid counter value");
+        u64::from_le_bytes(buf)
+    }
+
+    fn increment(by: u64) -> u64 {
+        let n = Self::get().saturating_add(by);
+        storage::write(KEY, &n.to_le_bytes());
+        log::log(log::Level::Info, &format!("incremented by {by} to {n}"));
+        events::emit(
+            "counter",
+            "1.0.0",
+            "increment",
+            &format!(r#"{{"by":{by},"count":{n}}}"#),
+        );
+        n
+    }
+}
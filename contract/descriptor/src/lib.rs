@@ -0,0 +1,236 @@
+//! Guest-side helpers for building flattened type descriptors,
+//! see `near-cm:host/descriptor` interface.
+
+use std::collections::HashMap;
+
+mod bindings {
+    wit_bindgen::generate!({
+        path: "../../host/wit",
+        world: "descriptor-types",
+        additional_derives: [Hash, Eq, PartialEq],
+        generate_unused_types: true,
+        generate_all,
+    });
+}
+
+pub use bindings::near_cm::host::descriptor::{Node, NodeIndex, TypeDescriptor};
+
+/// [`TypeDescriptor`] builder, which deduplicates identical nodes.
+#[derive(Debug, Default)]
+pub struct Builder {
+    nodes: Vec<Node>,
+    indices: HashMap<Node, NodeIndex>,
+    /// Index returned by the last [`Builder::push`]
+    last: Option<NodeIndex>,
+}
+
+impl Builder {
+    /// Appends `node`, unless an identical node is already present,
+    /// and returns its index.
+    pub fn push(&mut self, node: Node) -> NodeIndex {
+        if let Some(i) = self.indices.get(&node) {
+            self.last = Some(*i);
+            return *i;
+        }
+        let i = self
+            .nodes
+            .len()
+            .try_into()
+            .expect("too many type descriptor nodes");
+        self.nodes.push(node.clone());
+        self.indices.insert(node, i);
+        self.last = Some(i);
+        i
+    }
+
+    /// Returns the [`TypeDescriptor`] of the type described by the last pushed node.
+    pub fn finish(mut self) -> TypeDescriptor {
+        if let Some(last) = self.last {
+            // The root must be the last node, even if it was deduplicated
+            if last as usize != self.nodes.len() - 1 {
+                let node = self.nodes[last as usize].clone();
+                self.nodes.push(node);
+            }
+        }
+        TypeDescriptor { nodes: self.nodes }
+    }
+}
+
+/// Types, which can be described by a [`TypeDescriptor`].
+///
+/// Implemented for Rust types `wit_bindgen` maps WIT types to. Use [`describe_record`],
+/// [`describe_variant`], [`describe_enum`] and [`describe_flags`] to implement it for
+/// generated types.
+pub trait Describe {
+    /// Appends nodes describing `Self` and returns the index of the root node.
+    fn describe(b: &mut Builder) -> NodeIndex;
+}
+
+/// Returns the [`TypeDescriptor`] of `T`.
+pub fn describe<T: Describe>() -> TypeDescriptor {
+    let mut b = Builder::default();
+    T::describe(&mut b);
+    b.finish()
+}
+
+macro_rules! impl_describe_primitive {
+    ($($t:ty => $node:ident),* $(,)?) => {
+        $(
+            impl Describe for $t {
+                fn describe(b: &mut Builder) -> NodeIndex {
+                    b.push(Node::$node)
+                }
+            }
+        )*
+    };
+}
+
+impl_describe_primitive!(
+    bool => Bool,
+    u8 => U8,
+    u16 => U16,
+    u32 => U32,
+    u64 => U64,
+    i8 => S8,
+    i16 => S16,
+    i32 => S32,
+    i64 => S64,
+    f32 => F32,
+    f64 => F64,
+    char => Char,
+    String => String,
+);
+
+impl<T: Describe> Describe for Vec<T> {
+    fn describe(b: &mut Builder) -> NodeIndex {
+        let ty = T::describe(b);
+        b.push(Node::List(ty))
+    }
+}
+
+impl<T: Describe> Describe for Option<T> {
+    fn describe(b: &mut Builder) -> NodeIndex {
+        let ty = T::describe(b);
+        b.push(Node::Option(ty))
+    }
+}
+
+/// Results with no `ok` or `err` payload have to be described manually using
+/// [`Builder::push`].
+impl<T: Describe, E: Describe> Describe for Result<T, E> {
+    fn describe(b: &mut Builder) -> NodeIndex {
+        let ok = T::describe(b);
+        let err = E::describe(b);
+        b.push(Node::Result((Some(ok), Some(err))))
+    }
+}
+
+macro_rules! impl_describe_tuple {
+    ($($t:ident),+) => {
+        impl<$($t: Describe),+> Describe for ($($t,)+) {
+            fn describe(b: &mut Builder) -> NodeIndex {
+                let tys = vec![$($t::describe(b)),+];
+                b.push(Node::Tuple(tys))
+            }
+        }
+    };
+}
+
+impl_describe_tuple!(A);
+impl_describe_tuple!(A, B);
+impl_describe_tuple!(A, B, C);
+impl_describe_tuple!(A, B, C, D);
+impl_describe_tuple!(A, B, C, D, E);
+impl_describe_tuple!(A, B, C, D, E, F);
+impl_describe_tuple!(A, B, C, D, E, F, G);
+impl_describe_tuple!(A, B, C, D, E, F, G, H);
+
+/// Implements [`Describe`] for a record type generated by `wit_bindgen`.
+///
+/// Fields are specified in declaration order using their WIT names:
+///
+/// ```ignore
+/// descriptor::describe_record!(SmallInput { "a": String, "b": u32, "c": (u32, u32, u32) });
+/// ```
+#[macro_export]
+macro_rules! describe_record {
+    ($ty:ty { $($name:literal: $field:ty),* $(,)? }) => {
+        impl $crate::Describe for $ty {
+            fn describe(b: &mut $crate::Builder) -> $crate::NodeIndex {
+                let fields = ::std::vec![
+                    $((
+                        ::std::string::String::from($name),
+                        <$field as $crate::Describe>::describe(b),
+                    )),*
+                ];
+                b.push($crate::Node::Record(fields))
+            }
+        }
+    };
+}
+
+/// Implements [`Describe`] for a variant type generated by `wit_bindgen`.
+///
+/// Cases are specified in declaration order using their WIT names:
+///
+/// ```ignore
+/// descriptor::describe_variant!(Shape { "circle"(f64), "point" });
+/// ```
+#[macro_export]
+macro_rules! describe_variant {
+    ($ty:ty { $($name:literal $(($payload:ty))?),* $(,)? }) => {
+        impl $crate::Describe for $ty {
+            fn describe(b: &mut $crate::Builder) -> $crate::NodeIndex {
+                let cases = ::std::vec![
+                    $({
+                        #[allow(unused_mut)]
+                        let mut ty = ::core::option::Option::None;
+                        $(ty = ::core::option::Option::Some(
+                            <$payload as $crate::Describe>::describe(b),
+                        );)?
+                        (::std::string::String::from($name), ty)
+                    }),*
+                ];
+                b.push($crate::Node::Variant(cases))
+            }
+        }
+    };
+}
+
+/// Implements [`Describe`] for an enum type generated by `wit_bindgen`.
+///
+/// Cases are specified in declaration order using their WIT names:
+///
+/// ```ignore
+/// descriptor::describe_enum!(Color { "red", "green", "blue" });
+/// ```
+#[macro_export]
+macro_rules! describe_enum {
+    ($ty:ty { $($name:literal),* $(,)? }) => {
+        impl $crate::Describe for $ty {
+            fn describe(b: &mut $crate::Builder) -> $crate::NodeIndex {
+                let cases = ::std::vec![$(::std::string::String::from($name)),*];
+                b.push($crate::Node::Enum(cases))
+            }
+        }
+    };
+}
+
+/// Implements [`Describe`] for a flags type generated by `wit_bindgen`.
+///
+/// Flags are specified in declaration order using their WIT names:
+///
+/// ```ignore
+/// descriptor::describe_flags!(Permissions { "read", "write" });
+/// ```
+#[macro_export]
+macro_rules! describe_flags {
+    ($ty:ty { $($name:literal),* $(,)? }) => {
+        impl $crate::Describe for $ty {
+            fn describe(b: &mut $crate::Builder) -> $crate::NodeIndex {
+                let flags = ::std::vec![$(::std::string::String::from($name)),*];
+                b.push($crate::Node::Flags(flags))
+            }
+        }
+    };
+}
@@ -0,0 +1,711 @@
+//! Decoding of function parameters using codec components
+
+use core::iter::{self, zip};
+
+use anyhow::{Context as _, bail, ensure};
+use wasmtime::component::{InstancePre, ResourceAny, Type, Val, types};
+use wasmtime::{Store, Trap};
+
+use crate::bindings::exports::cosmonic::reflect::reflect;
+use crate::bindings::exports::cosmonic::serde::deserializer;
+use crate::bindings::exports::near_cm::codec::{stream_deserializer, type_builder};
+use crate::descriptor::{Node, NodeIndex, TypeDescriptor};
+
+/// Codec component validated to implement the codec world, ready to be instantiated
+pub struct CodecPre<T: 'static> {
+    pre: InstancePre<T>,
+    reflect: reflect::GuestIndices,
+    deserializer: deserializer::GuestIndices,
+    /// Set if the component exports the optional `stream-deserializer` interface
+    stream_deserializer: Option<stream_deserializer::GuestIndices>,
+    /// Set if the component exports the optional `type-builder` interface
+    type_builder: Option<type_builder::GuestIndices>,
+}
+
+impl<T: Send + 'static> CodecPre<T> {
+    pub fn new(pre: InstancePre<T>) -> anyhow::Result<Self> {
+        let reflect = reflect::GuestIndices::new(&pre)?;
+        let deserializer = deserializer::GuestIndices::new(&pre)?;
+        let stream_deserializer = if pre
+            .component()
+            .get_export_index(None, "near-cm:codec/stream-deserializer@0.1.0")
+            .is_some()
+        {
+            Some(stream_deserializer::GuestIndices::new(&pre)?)
+        } else {
+            None
+        };
+        let type_builder = if pre
+            .component()
+            .get_export_index(None, "near-cm:codec/type-builder@0.1.0")
+            .is_some()
+        {
+            Some(type_builder::GuestIndices::new(&pre)?)
+        } else {
+            None
+        };
+        Ok(Self {
+            pre,
+            reflect,
+            deserializer,
+            stream_deserializer,
+            type_builder,
+        })
+    }
+
+    pub async fn instantiate_async(&self, mut store: &mut Store<T>) -> anyhow::Result<Codec> {
+        let instance = self.pre.instantiate_async(&mut store).await?;
+        let stream_deserializer = self
+            .stream_deserializer
+            .as_ref()
+            .map(|indices| indices.load(&mut store, &instance))
+            .transpose()?;
+        let type_builder = self
+            .type_builder
+            .as_ref()
+            .map(|indices| indices.load(&mut store, &instance))
+            .transpose()?;
+        Ok(Codec {
+            reflect: self.reflect.load(&mut store, &instance)?,
+            deserializer: self.deserializer.load(&mut store, &instance)?,
+            stream_deserializer,
+            type_builder,
+        })
+    }
+}
+
+/// Instantiated codec component
+pub struct Codec {
+    reflect: reflect::Guest,
+    deserializer: deserializer::Guest,
+    stream_deserializer: Option<stream_deserializer::Guest>,
+    type_builder: Option<type_builder::Guest>,
+}
+
+impl Codec {
+    /// Returns `true` if the codec supports incremental deserialization, see [`ParamsDecoder`].
+    pub fn is_streaming(&self) -> bool {
+        self.stream_deserializer.is_some()
+    }
+
+    /// Returns the message of deserialization error `err` and drops it.
+    async fn error_message<T: Send>(
+        &self,
+        store: &mut Store<T>,
+        err: ResourceAny,
+    ) -> wasmtime::Result<String> {
+        let msg = self
+            .deserializer
+            .error()
+            .call_to_string(&mut *store, err)
+            .await?;
+        err.resource_drop_async::<T>(store).await?;
+        Ok(msg)
+    }
+}
+
+/// Reflect type constructed within a codec instance
+pub struct ReflectType {
+    ty: reflect::TupleType,
+    /// Type resources owned by the host, including `ty`
+    owned: Vec<ResourceAny>,
+}
+
+impl ReflectType {
+    /// Drops the type resources once the type is not needed anymore.
+    async fn drop<T: Send>(self, store: &mut Store<T>) -> wasmtime::Result<()> {
+        drop_resources(store, self.owned).await
+    }
+}
+
+/// Drops resources `owned`.
+async fn drop_resources<T: Send>(
+    store: &mut Store<T>,
+    owned: impl IntoIterator<Item = ResourceAny>,
+) -> wasmtime::Result<()> {
+    for resource in owned {
+        resource.resource_drop_async::<T>(&mut *store).await?;
+    }
+    Ok(())
+}
+
+/// Drops the resources owned by value `v`, which was not unwrapped.
+///
+/// Values nested in dropped resources are dropped by the codec.
+async fn drop_value<T: Send>(store: &mut Store<T>, v: reflect::Value) -> wasmtime::Result<()> {
+    let owned = match v {
+        reflect::Value::Record(v)
+        | reflect::Value::Tuple(v)
+        | reflect::Value::Variant(v)
+        | reflect::Value::Option(v)
+        | reflect::Value::Result(v) => vec![v],
+        reflect::Value::List(
+            reflect::List::Record(vs)
+            | reflect::List::Tuple(vs)
+            | reflect::List::Variant(vs)
+            | reflect::List::Option(vs)
+            | reflect::List::Result(vs),
+        ) => vs,
+        _ => Vec::default(),
+    };
+    drop_resources(store, owned).await
+}
+
+/// Unwraps `values` of types `tys` with `what` describing the value at an index,
+/// dropping the values not unwrapped on failure.
+async fn unwrap_vals<T: Send>(
+    store: &mut Store<T>,
+    values: Vec<reflect::Value>,
+    instance: &reflect::Guest,
+    tys: impl ExactSizeIterator<Item = Type>,
+    what: impl Fn(usize) -> String,
+) -> wasmtime::Result<Vec<Val>> {
+    if values.len() != tys.len() {
+        let n = values.len();
+        for v in values {
+            drop_value(store, v).await?;
+        }
+        bail!("expected {} values, got {n}", tys.len());
+    }
+    let mut values = values.into_iter();
+    let mut vals = Vec::with_capacity(tys.len());
+    for ((i, ty), v) in zip(tys.enumerate(), values.by_ref()) {
+        match Box::pin(unwrap_val(store, v, instance, ty)).await {
+            Ok(v) => vals.push(v),
+            Err(err) => {
+                // The codec instance cannot be entered anymore, if it trapped
+                if !err.is::<Trap>() {
+                    for v in values {
+                        drop_value(store, v).await?;
+                    }
+                }
+                return Err(err.context(what(i)));
+            }
+        }
+    }
+    Ok(vals)
+}
+
+async fn unwrap_val<T: Send>(
+    mut store: &mut Store<T>,
+    v: reflect::Value,
+    instance: &reflect::Guest,
+    ty: Type,
+) -> wasmtime::Result<Val> {
+    match (v, ty) {
+        (reflect::Value::Bool(v), Type::Bool) => Ok(Val::Bool(v)),
+        (reflect::Value::S8(v), Type::S8) => Ok(Val::S8(v)),
+        (reflect::Value::U8(v), Type::U8) => Ok(Val::U8(v)),
+        (reflect::Value::U16(v), Type::U16) => Ok(Val::U16(v)),
+        (reflect::Value::S16(v), Type::S16) => Ok(Val::S16(v)),
+        (reflect::Value::U32(v), Type::U32 | Type::Own(..) | Type::Borrow(..)) => Ok(Val::U32(v)),
+        (reflect::Value::S32(v), Type::S32) => Ok(Val::S32(v)),
+        (reflect::Value::U64(v), Type::U64) => Ok(Val::U64(v)),
+        (reflect::Value::S64(v), Type::S64) => Ok(Val::S64(v)),
+        (reflect::Value::F32(v), Type::Float32) => Ok(Val::Float32(v)),
+        (reflect::Value::F64(v), Type::Float64) => Ok(Val::Float64(v)),
+        (reflect::Value::Char(v), Type::Char) => Ok(Val::Char(v)),
+        (reflect::Value::String(v), Type::String) => Ok(Val::String(v)),
+        (reflect::Value::Record(v), Type::Record(ty)) => {
+            let values = instance
+                .record_value()
+                .call_into_value(&mut store, v)
+                .await?;
+            let names = ty.fields().map(|field| field.name).collect::<Vec<_>>();
+            let values = unwrap_vals(
+                store,
+                values,
+                instance,
+                ty.fields().map(|field| field.ty),
+                |i| format!("failed to unwrap record field `{}`", names[i]),
+            )
+            .await?;
+            Ok(Val::Record(
+                zip(names, values)
+                    .map(|(name, v)| (name.into(), v))
+                    .collect(),
+            ))
+        }
+        (reflect::Value::List(v), Type::List(ty)) => {
+            let ty = ty.ty();
+            let elems = match (v, &ty) {
+                (reflect::List::Bool(v), Type::Bool) => v.into_iter().map(Val::Bool).collect(),
+                (reflect::List::S8(v), Type::S8) => v.into_iter().map(Val::S8).collect(),
+                (reflect::List::U8(v), Type::U8) => v.into_iter().map(Val::U8).collect(),
+                (reflect::List::S16(v), Type::S16) => v.into_iter().map(Val::S16).collect(),
+                (reflect::List::U16(v), Type::U16) => v.into_iter().map(Val::U16).collect(),
+                (reflect::List::S32(v), Type::S32) => v.into_iter().map(Val::S32).collect(),
+                (reflect::List::U32(v), Type::U32 | Type::Own(..) | Type::Borrow(..)) => {
+                    v.into_iter().map(Val::U32).collect()
+                }
+                (reflect::List::S64(v), Type::S64) => v.into_iter().map(Val::S64).collect(),
+                (reflect::List::U64(v), Type::U64) => v.into_iter().map(Val::U64).collect(),
+                (reflect::List::F32(v), Type::Float32) => v.into_iter().map(Val::Float32).collect(),
+                (reflect::List::F64(v), Type::Float64) => v.into_iter().map(Val::Float64).collect(),
+                (reflect::List::Char(v), Type::Char) => v.into_iter().map(Val::Char).collect(),
+                (reflect::List::String(v), Type::String) => {
+                    v.into_iter().map(Val::String).collect()
+                }
+                (v, _) => {
+                    // Elements of compound types are unwrapped like single values
+                    let values: Vec<_> = match v {
+                        reflect::List::Record(v) => {
+                            v.into_iter().map(reflect::Value::Record).collect()
+                        }
+                        reflect::List::Tuple(v) => {
+                            v.into_iter().map(reflect::Value::Tuple).collect()
+                        }
+                        reflect::List::Variant(v) => {
+                            v.into_iter().map(reflect::Value::Variant).collect()
+                        }
+                        reflect::List::Enum(v) => v.into_iter().map(reflect::Value::Enum).collect(),
+                        reflect::List::Option(v) => {
+                            v.into_iter().map(reflect::Value::Option).collect()
+                        }
+                        reflect::List::Result(v) => {
+                            v.into_iter().map(reflect::Value::Result).collect()
+                        }
+                        reflect::List::Flags(v) => {
+                            v.into_iter().map(reflect::Value::Flags).collect()
+                        }
+                        v => {
+                            drop_value(store, reflect::Value::List(v)).await?;
+                            bail!("list element type mismatch")
+                        }
+                    };
+                    let n = values.len();
+                    unwrap_vals(store, values, instance, iter::repeat_n(ty, n), |i| {
+                        format!("failed to unwrap list element `{i}`")
+                    })
+                    .await?
+                }
+            };
+            Ok(Val::List(elems))
+        }
+        (reflect::Value::Tuple(v), Type::Tuple(ty)) => {
+            let values = instance
+                .tuple_value()
+                .call_into_value(&mut store, v)
+                .await?;
+            let elems = unwrap_vals(store, values, instance, ty.types(), |i| {
+                format!("failed to unwrap tuple element `{i}`")
+            })
+            .await?;
+            Ok(Val::Tuple(elems))
+        }
+        (reflect::Value::Variant(v), Type::Variant(ty)) => {
+            let (case, v) = instance
+                .variant_value()
+                .call_into_value(&mut store, v)
+                .await?;
+            let Some(types::Case { name, ty }) = usize::try_from(case)
+                .ok()
+                .and_then(|case| ty.cases().nth(case))
+            else {
+                if let Some(v) = v {
+                    drop_value(store, v).await?;
+                }
+                bail!("variant case `{case}` out of range");
+            };
+            let v = match (v, ty) {
+                (None, None) => None,
+                (Some(v), Some(ty)) => {
+                    let v = Box::pin(unwrap_val(store, v, instance, ty))
+                        .await
+                        .with_context(|| format!("failed to unwrap variant case `{name}`"))?;
+                    Some(Box::new(v))
+                }
+                (v, _) => {
+                    if let Some(v) = v {
+                        drop_value(store, v).await?;
+                    }
+                    bail!("variant case `{name}` payload mismatch")
+                }
+            };
+            Ok(Val::Variant(name.into(), v))
+        }
+        (reflect::Value::Enum(case), Type::Enum(ty)) => {
+            let Some(name) = usize::try_from(case)
+                .ok()
+                .and_then(|case| ty.names().nth(case))
+            else {
+                bail!("enum case `{case}` out of range");
+            };
+            Ok(Val::Enum(name.into()))
+        }
+        (reflect::Value::Option(v), Type::Option(ty)) => {
+            let v = instance
+                .option_value()
+                .call_into_value(&mut store, v)
+                .await?;
+            let Some(v) = v else {
+                return Ok(Val::Option(None));
+            };
+            let v = Box::pin(unwrap_val(store, v, instance, ty.ty()))
+                .await
+                .context("failed to unwrap option payload")?;
+            Ok(Val::Option(Some(Box::new(v))))
+        }
+        (reflect::Value::Result(v), Type::Result(ty)) => {
+            let v = instance
+                .result_value()
+                .call_into_value(&mut store, v)
+                .await?;
+            let ok = v.is_ok();
+            let (v, ty, case) = match v {
+                Ok(v) => (v, ty.ok(), "ok"),
+                Err(v) => (v, ty.err(), "err"),
+            };
+            let v = match (v, ty) {
+                (None, None) => None,
+                (Some(v), Some(ty)) => {
+                    let v = Box::pin(unwrap_val(store, v, instance, ty))
+                        .await
+                        .with_context(|| format!("failed to unwrap result `{case}` payload"))?;
+                    Some(Box::new(v))
+                }
+                (v, _) => {
+                    if let Some(v) = v {
+                        drop_value(store, v).await?;
+                    }
+                    bail!("result `{case}` payload mismatch")
+                }
+            };
+            Ok(Val::Result(if ok { Ok(v) } else { Err(v) }))
+        }
+        (reflect::Value::Flags(bits), Type::Flags(ty)) => {
+            let names: Vec<_> = ty.names().collect();
+            ensure!(
+                u32::try_from(names.len()).is_ok_and(|n| n >= 32 || bits >> n == 0),
+                "unknown flags set"
+            );
+            let flags = names
+                .into_iter()
+                .enumerate()
+                .filter(|(i, _)| bits & (1 << i) != 0)
+                .map(|(_, name)| name.into())
+                .collect();
+            Ok(Val::Flags(flags))
+        }
+        (v, _) => {
+            drop_value(store, v).await?;
+            bail!("type mismatch")
+        }
+    }
+}
+
+/// Maximum number of flags, which are represented as a `u32` bit set by reflect values
+const MAX_FLAGS: usize = 32;
+
+/// Plan for constructing the reflect type of function parameters within a codec instance.
+///
+/// Plans are built from the [`TypeDescriptor`] of the parameter tuple. Codecs exporting
+/// `near-cm:codec/type-builder` construct the type from the descriptor in a single call,
+/// other codecs are replayed a constructor call per distinct compound type. Plans only
+/// depend on the function type, which avoids walking the type tree on every invocation.
+#[derive(Debug)]
+pub struct TypePlan {
+    /// Nodes in construction order, the last node is the parameter tuple
+    descriptor: TypeDescriptor,
+}
+
+impl TypePlan {
+    /// Builds a plan for the parameters of function type `ty`.
+    pub fn new(ty: &types::ComponentFunc) -> anyhow::Result<Self> {
+        let descriptor = TypeDescriptor::from_params(ty)?;
+        // Reject what reflect types cannot represent before any codec is asked
+        for node in &descriptor.nodes {
+            match node {
+                Node::List(i) if matches!(descriptor.nodes[*i as usize], Node::List(..)) => {
+                    bail!("lists of lists not supported")
+                }
+                Node::Flags(names) if names.len() > MAX_FLAGS => {
+                    bail!("flags with more than {MAX_FLAGS} names not supported")
+                }
+                _ => {}
+            }
+        }
+        Ok(Self { descriptor })
+    }
+
+    /// Constructs the parameter tuple type within `codec`.
+    pub async fn build<T: Send>(
+        &self,
+        store: &mut Store<T>,
+        codec: &Codec,
+    ) -> wasmtime::Result<ReflectType> {
+        let Some(builder) = &codec.type_builder else {
+            let mut owned = Vec::default();
+            return match self.replay(store, &codec.reflect, &mut owned).await {
+                Ok(ty) => Ok(ReflectType { ty, owned }),
+                Err(err) => {
+                    // The codec instance cannot be entered anymore, if it trapped
+                    if !err.is::<Trap>() {
+                        drop_resources(store, owned).await?;
+                    }
+                    Err(err)
+                }
+            };
+        };
+        match builder
+            .call_build_tuple(&mut *store, &self.descriptor)
+            .await?
+        {
+            Ok(ty) => Ok(ReflectType {
+                ty,
+                owned: vec![ty],
+            }),
+            Err(err) => bail!(err),
+        }
+    }
+
+    /// Constructs the parameter tuple type within codec `instance` node by node,
+    /// collecting the constructed type resources in `owned`.
+    async fn replay<T: Send>(
+        &self,
+        mut store: &mut Store<T>,
+        instance: &reflect::Guest,
+        owned: &mut Vec<ResourceAny>,
+    ) -> wasmtime::Result<reflect::TupleType> {
+        let nodes = &self.descriptor.nodes;
+        let mut tys: Vec<reflect::Type> = Vec::with_capacity(nodes.len());
+        for node in nodes {
+            let ty = |i: &NodeIndex| tys[*i as usize].clone();
+            let ty = match node {
+                Node::Bool => reflect::Type::Bool,
+                Node::U8 => reflect::Type::U8,
+                Node::U16 => reflect::Type::U16,
+                Node::U32 => reflect::Type::U32,
+                Node::U64 => reflect::Type::U64,
+                Node::S8 => reflect::Type::S8,
+                Node::S16 => reflect::Type::S16,
+                Node::S32 => reflect::Type::S32,
+                Node::S64 => reflect::Type::S64,
+                Node::F32 => reflect::Type::F32,
+                Node::F64 => reflect::Type::F64,
+                Node::Char => reflect::Type::Char,
+                Node::String => reflect::Type::String,
+                Node::List(i) => reflect::Type::List(match ty(i) {
+                    reflect::Type::Bool => reflect::ListType::Bool,
+                    reflect::Type::U8 => reflect::ListType::U8,
+                    reflect::Type::U16 => reflect::ListType::U16,
+                    reflect::Type::U32 => reflect::ListType::U32,
+                    reflect::Type::U64 => reflect::ListType::U64,
+                    reflect::Type::S8 => reflect::ListType::S8,
+                    reflect::Type::S16 => reflect::ListType::S16,
+                    reflect::Type::S32 => reflect::ListType::S32,
+                    reflect::Type::S64 => reflect::ListType::S64,
+                    reflect::Type::F32 => reflect::ListType::F32,
+                    reflect::Type::F64 => reflect::ListType::F64,
+                    reflect::Type::Char => reflect::ListType::Char,
+                    reflect::Type::String => reflect::ListType::String,
+                    reflect::Type::Record(ty) => reflect::ListType::Record(ty),
+                    reflect::Type::Tuple(ty) => reflect::ListType::Tuple(ty),
+                    reflect::Type::Variant(ty) => reflect::ListType::Variant(ty),
+                    reflect::Type::Enum(ty) => reflect::ListType::Enum(ty),
+                    reflect::Type::Option(ty) => reflect::ListType::Option(ty),
+                    reflect::Type::Result(ty) => reflect::ListType::Result(ty),
+                    reflect::Type::Flags(ty) => reflect::ListType::Flags(ty),
+                    reflect::Type::List(..) => bail!("lists of lists not supported"),
+                }),
+                Node::Record(fields) => {
+                    let fields = fields
+                        .iter()
+                        .map(|(name, i)| (name.clone(), ty(i)))
+                        .collect::<Vec<_>>();
+                    let ty = instance
+                        .record_type()
+                        .call_constructor(&mut store, &fields)
+                        .await?;
+                    owned.push(ty);
+                    reflect::Type::Record(ty)
+                }
+                Node::Tuple(types) => {
+                    let types = types.iter().map(ty).collect::<Vec<_>>();
+                    let ty = instance
+                        .tuple_type()
+                        .call_constructor(&mut store, &types)
+                        .await?;
+                    owned.push(ty);
+                    reflect::Type::Tuple(ty)
+                }
+                Node::Variant(cases) => {
+                    let cases = cases
+                        .iter()
+                        .map(|(name, i)| (name.clone(), i.as_ref().map(ty)))
+                        .collect::<Vec<_>>();
+                    let ty = instance
+                        .variant_type()
+                        .call_constructor(&mut store, &cases)
+                        .await?;
+                    owned.push(ty);
+                    reflect::Type::Variant(ty)
+                }
+                Node::Enum(names) => {
+                    let ty = instance
+                        .enum_type()
+                        .call_constructor(&mut store, names)
+                        .await?;
+                    owned.push(ty);
+                    reflect::Type::Enum(ty)
+                }
+                Node::Option(i) => {
+                    let ty = instance
+                        .option_type()
+                        .call_constructor(&mut store, ty(i))
+                        .await?;
+                    owned.push(ty);
+                    reflect::Type::Option(ty)
+                }
+                Node::Result((ok, err)) => {
+                    let (ok, err) = (ok.as_ref().map(ty), err.as_ref().map(ty));
+                    let ty = instance
+                        .result_type()
+                        .call_constructor(&mut store, ok, err)
+                        .await?;
+                    owned.push(ty);
+                    reflect::Type::Result(ty)
+                }
+                Node::Flags(names) => {
+                    let ty = instance
+                        .flags_type()
+                        .call_constructor(&mut store, names)
+                        .await?;
+                    owned.push(ty);
+                    reflect::Type::Flags(ty)
+                }
+            };
+            tys.push(ty);
+        }
+        let Some(reflect::Type::Tuple(ty)) = tys.pop() else {
+            bail!("type plan does not end with a tuple");
+        };
+        Ok(ty)
+    }
+}
+
+pub async fn deserialize_params<T: Send>(
+    mut store: &mut Store<T>,
+    codec: &Codec,
+    ty: &types::ComponentFunc,
+    plan: &TypePlan,
+    buf: &[u8],
+) -> wasmtime::Result<Vec<Val>> {
+    if ty.params().len() == 0 {
+        ensure!(buf.is_empty());
+        return Ok(Vec::default());
+    }
+
+    let reflect_ty = plan.build(store, codec).await?;
+    let v = codec
+        .deserializer
+        .call_from_list(&mut store, buf, reflect::Type::Tuple(reflect_ty.ty))
+        .await?;
+    reflect_ty.drop(store).await?;
+    let v = match v {
+        Ok(v) => v,
+        Err(err) => bail!(codec.error_message(store, err).await?),
+    };
+    unwrap_params(store, codec, ty, v).await
+}
+
+/// Converts deserialized parameter tuple `v` into parameters of function type `ty`.
+async fn unwrap_params<T: Send>(
+    mut store: &mut Store<T>,
+    codec: &Codec,
+    ty: &types::ComponentFunc,
+    v: reflect::Value,
+) -> wasmtime::Result<Vec<Val>> {
+    let values = match v {
+        reflect::Value::Tuple(values) => values,
+        v => {
+            drop_value(store, v).await?;
+            bail!("deserialized value is not a tuple");
+        }
+    };
+    let values = codec
+        .reflect
+        .tuple_value()
+        .call_into_value(&mut store, values)
+        .await?;
+    let names = ty.params().map(|(name, _)| name).collect::<Vec<_>>();
+    unwrap_vals(
+        store,
+        values,
+        &codec.reflect,
+        ty.params().map(|(_, ty)| ty),
+        |i| format!("failed to unwrap param `{}`", names[i]),
+    )
+    .await
+}
+
+/// Incremental deserializer of function parameters, which is pushed
+/// the encoding in chunks.
+pub struct ParamsDecoder<'a> {
+    codec: &'a Codec,
+    deserializer: &'a stream_deserializer::Guest,
+    decoder: ResourceAny,
+}
+
+impl<'a> ParamsDecoder<'a> {
+    /// Creates a decoder of parameters of a function with at least one parameter
+    /// using streaming `codec`, see [`Codec::is_streaming`].
+    pub async fn new<T: Send>(
+        store: &mut Store<T>,
+        codec: &'a Codec,
+        plan: &TypePlan,
+    ) -> wasmtime::Result<Self> {
+        let Some(deserializer) = &codec.stream_deserializer else {
+            bail!("codec does not support incremental deserialization");
+        };
+        let ty = plan.build(store, codec).await?;
+        let decoder = deserializer
+            .decoder()
+            .call_constructor(&mut *store, reflect::Type::Tuple(ty.ty))
+            .await?;
+        ty.drop(store).await?;
+        Ok(Self {
+            codec,
+            deserializer,
+            decoder,
+        })
+    }
+
+    /// Pushes the next chunk `buf` of the encoding.
+    pub async fn push<T: Send>(&self, store: &mut Store<T>, buf: &[u8]) -> wasmtime::Result<()> {
+        match self
+            .deserializer
+            .decoder()
+            .call_push(&mut *store, self.decoder, buf)
+            .await?
+        {
+            Ok(()) => Ok(()),
+            Err(err) => bail!(self.codec.error_message(store, err).await?),
+        }
+    }
+
+    /// Drops the decoder without deserializing, e.g. after a chunk failed to be pushed.
+    ///
+    /// Errors are ignored, the codec instance cannot be entered anymore after a trap anyway.
+    pub async fn abort<T: Send>(self, store: &mut Store<T>) {
+        _ = self.decoder.resource_drop_async::<T>(store).await;
+    }
+
+    /// Deserializes parameters of function type `ty` once the whole encoding has been pushed.
+    pub async fn finish<T: Send>(
+        self,
+        store: &mut Store<T>,
+        ty: &types::ComponentFunc,
+    ) -> wasmtime::Result<Vec<Val>> {
+        let v = match self
+            .deserializer
+            .decoder()
+            .call_finish(&mut *store, self.decoder)
+            .await?
+        {
+            Ok(v) => v,
+            Err(err) => bail!(self.codec.error_message(store, err).await?),
+        };
+        unwrap_params(store, self.codec, ty, v).await
+    }
+}
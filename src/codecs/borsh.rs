@@ -0,0 +1,537 @@
+//! [Borsh](https://borsh.io) codec, which decodes directly into [`Val`]
+//!
+//! Function parameters are encoded like a Borsh struct with a field per parameter,
+//! which matches how NEAR contracts accept Borsh-encoded arguments.
+//! Values are encoded as follows:
+//!
+//! - `bool`: a single `0` or `1` byte
+//! - integers and floats: little-endian, `NaN` is rejected like in Borsh
+//! - `char`: Unicode scalar value as little-endian `u32`
+//! - `string`: `u32` byte length followed by UTF-8 bytes
+//! - `list`: `u32` element count followed by the elements
+//! - `record` and `tuple`: fields or elements in declaration order
+//! - `variant` and `enum`: `u8` case index followed by the payload, if any
+//! - `option`: `0` for `none`, `1` followed by the value for `some`
+//! - `result`: `0` followed by the `err` payload, `1` followed by the `ok` payload,
+//!   matching Borsh encoding of Rust [`Result`]
+//! - `flags`: little-endian bit set of `ceil(n / 8)` bytes, where bit `i` corresponds
+//!   to the `i`-th flag. There is no Borsh equivalent, this matches fixed-size
+//!   byte arrays
+//! - `own` and `borrow`: `u32` handle ID
+//!
+//! Results are encoded like parameters. Batches of invocations are encoded like
+//! `Vec<(String, String, Vec<u8>)>` of contract name, function name and encoded parameters,
+//! their outcomes like `Vec<Result<(Vec<u8>, u64, Vec<String>), String>>` of encoded results,
+//! gas used and logged messages or error message.
+
+use anyhow::{Context as _, bail, ensure};
+use wasmtime::component::{Type, Val, types};
+
+use super::{BatchCall, BatchOutcome, HostCodec};
+
+/// Borsh codec
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Borsh;
+
+impl HostCodec for Borsh {
+    fn decode_params(&self, ty: &types::ComponentFunc, mut buf: &[u8]) -> anyhow::Result<Vec<Val>> {
+        let mut params = Vec::with_capacity(ty.params().len());
+        for (name, ty) in ty.params() {
+            let v =
+                read(&mut buf, &ty).with_context(|| format!("failed to decode param `{name}`"))?;
+            params.push(v);
+        }
+        ensure!(buf.is_empty(), "{} trailing bytes", buf.len());
+        Ok(params)
+    }
+
+    fn encode_value(&self, ty: &Type, v: &Val) -> anyhow::Result<Vec<u8>> {
+        encode(ty, v)
+    }
+
+    fn encode_results(
+        &self,
+        ty: &types::ComponentFunc,
+        results: &[Val],
+    ) -> anyhow::Result<Vec<u8>> {
+        ensure!(
+            ty.results().len() == results.len(),
+            "expected {} results, got {}",
+            ty.results().len(),
+            results.len()
+        );
+        let mut buf = Vec::default();
+        for (ty, v) in ty.results().zip(results) {
+            write(&mut buf, &ty, v)?;
+        }
+        Ok(buf)
+    }
+
+    fn decode_batch(&self, mut buf: &[u8]) -> anyhow::Result<Vec<BatchCall>> {
+        let n = read_len(&mut buf)?;
+        // Each invocation takes at least 12 bytes of length prefixes
+        let mut calls = Vec::with_capacity(n.min(buf.len() / 12));
+        for i in 0..n {
+            let call = read_call(&mut buf).with_context(|| format!("failed to decode call {i}"))?;
+            calls.push(call);
+        }
+        ensure!(buf.is_empty(), "{} trailing bytes", buf.len());
+        Ok(calls)
+    }
+
+    fn encode_batch(&self, outcomes: &[Result<BatchOutcome, String>]) -> anyhow::Result<Vec<u8>> {
+        let mut buf = Vec::default();
+        write_len(&mut buf, outcomes.len())?;
+        for outcome in outcomes {
+            match outcome {
+                Ok(BatchOutcome {
+                    results,
+                    gas_used,
+                    logs,
+                }) => {
+                    buf.push(1);
+                    write_len(&mut buf, results.len())?;
+                    buf.extend(results);
+                    buf.extend(gas_used.to_le_bytes());
+                    write_len(&mut buf, logs.len())?;
+                    for log in logs {
+                        write_string(&mut buf, log)?;
+                    }
+                }
+                Err(err) => {
+                    buf.push(0);
+                    write_string(&mut buf, err)?;
+                }
+            }
+        }
+        Ok(buf)
+    }
+}
+
+/// Decodes a value of type `ty` from Borsh `buf`.
+pub fn decode(ty: &Type, mut buf: &[u8]) -> anyhow::Result<Val> {
+    let v = read(&mut buf, ty)?;
+    ensure!(buf.is_empty(), "{} trailing bytes", buf.len());
+    Ok(v)
+}
+
+/// Encodes value `v` of type `ty` as Borsh.
+pub fn encode(ty: &Type, v: &Val) -> anyhow::Result<Vec<u8>> {
+    let mut buf = Vec::default();
+    write(&mut buf, ty, v)?;
+    Ok(buf)
+}
+
+fn take<'a>(buf: &mut &'a [u8], n: usize) -> anyhow::Result<&'a [u8]> {
+    let Some((head, tail)) = buf.split_at_checked(n) else {
+        bail!("unexpected end of input");
+    };
+    *buf = tail;
+    Ok(head)
+}
+
+fn take_array<const N: usize>(buf: &mut &[u8]) -> anyhow::Result<[u8; N]> {
+    let bytes = take(buf, N)?;
+    Ok(bytes.try_into().expect("length checked"))
+}
+
+fn read_u8(buf: &mut &[u8]) -> anyhow::Result<u8> {
+    let [b] = take_array(buf)?;
+    Ok(b)
+}
+
+fn read_len(buf: &mut &[u8]) -> anyhow::Result<usize> {
+    let n = u32::from_le_bytes(take_array(buf)?);
+    Ok(n.try_into().expect("u32 fits in usize"))
+}
+
+fn read_string(buf: &mut &[u8]) -> anyhow::Result<String> {
+    let n = read_len(buf)?;
+    let s = take(buf, n)?;
+    String::from_utf8(s.to_vec()).context("invalid UTF-8 string")
+}
+
+fn read_call(buf: &mut &[u8]) -> anyhow::Result<BatchCall> {
+    let contract = read_string(buf).context("failed to decode contract")?;
+    let func = read_string(buf).context("failed to decode function")?;
+    let n = read_len(buf)?;
+    let args = take(buf, n).context("failed to decode args")?.to_vec();
+    Ok(BatchCall {
+        contract,
+        func,
+        args,
+    })
+}
+
+fn read_payload(buf: &mut &[u8], ty: Option<Type>) -> anyhow::Result<Option<Box<Val>>> {
+    let Some(ty) = ty else {
+        return Ok(None);
+    };
+    read(buf, &ty).map(|v| Some(Box::new(v)))
+}
+
+fn read(buf: &mut &[u8], ty: &Type) -> anyhow::Result<Val> {
+    match ty {
+        Type::Bool => match read_u8(buf)? {
+            0 => Ok(Val::Bool(false)),
+            1 => Ok(Val::Bool(true)),
+            b => bail!("invalid bool `{b}`"),
+        },
+        Type::S8 => Ok(Val::S8(i8::from_le_bytes(take_array(buf)?))),
+        Type::U8 => Ok(Val::U8(read_u8(buf)?)),
+        Type::S16 => Ok(Val::S16(i16::from_le_bytes(take_array(buf)?))),
+        Type::U16 => Ok(Val::U16(u16::from_le_bytes(take_array(buf)?))),
+        Type::S32 => Ok(Val::S32(i32::from_le_bytes(take_array(buf)?))),
+        Type::U32 | Type::Own(..) | Type::Borrow(..) => {
+            Ok(Val::U32(u32::from_le_bytes(take_array(buf)?)))
+        }
+        Type::S64 => Ok(Val::S64(i64::from_le_bytes(take_array(buf)?))),
+        Type::U64 => Ok(Val::U64(u64::from_le_bytes(take_array(buf)?))),
+        Type::Float32 => {
+            let v = f32::from_le_bytes(take_array(buf)?);
+            ensure!(!v.is_nan(), "NaN not allowed");
+            Ok(Val::Float32(v))
+        }
+        Type::Float64 => {
+            let v = f64::from_le_bytes(take_array(buf)?);
+            ensure!(!v.is_nan(), "NaN not allowed");
+            Ok(Val::Float64(v))
+        }
+        Type::Char => {
+            let v = u32::from_le_bytes(take_array(buf)?);
+            let c = char::from_u32(v).with_context(|| format!("invalid char `{v:#x}`"))?;
+            Ok(Val::Char(c))
+        }
+        Type::String => read_string(buf).map(Val::String),
+        Type::List(ty) => {
+            let n = read_len(buf)?;
+            let ty = ty.ty();
+            // Do not trust the length prefix for preallocation, each element takes at least a byte
+            // except for empty records and tuples
+            let mut vs = Vec::with_capacity(n.min(buf.len()));
+            for _ in 0..n {
+                vs.push(read(buf, &ty)?);
+            }
+            Ok(Val::List(vs))
+        }
+        Type::Record(ty) => {
+            let mut fields = Vec::with_capacity(ty.fields().len());
+            for types::Field { name, ty } in ty.fields() {
+                let v =
+                    read(buf, &ty).with_context(|| format!("failed to decode field `{name}`"))?;
+                fields.push((name.into(), v));
+            }
+            Ok(Val::Record(fields))
+        }
+        Type::Tuple(ty) => {
+            let mut vs = Vec::with_capacity(ty.types().len());
+            for ty in ty.types() {
+                vs.push(read(buf, &ty)?);
+            }
+            Ok(Val::Tuple(vs))
+        }
+        Type::Variant(ty) => {
+            let i = read_u8(buf)?;
+            let Some(types::Case { name, ty }) = ty.cases().nth(i.into()) else {
+                bail!("invalid variant case index `{i}`");
+            };
+            let v =
+                read_payload(buf, ty).with_context(|| format!("failed to decode case `{name}`"))?;
+            Ok(Val::Variant(name.into(), v))
+        }
+        Type::Enum(ty) => {
+            let i = read_u8(buf)?;
+            let Some(name) = ty.names().nth(i.into()) else {
+                bail!("invalid enum case index `{i}`");
+            };
+            Ok(Val::Enum(name.into()))
+        }
+        Type::Option(ty) => match read_u8(buf)? {
+            0 => Ok(Val::Option(None)),
+            1 => {
+                let v = read(buf, &ty.ty())?;
+                Ok(Val::Option(Some(Box::new(v))))
+            }
+            b => bail!("invalid option tag `{b}`"),
+        },
+        Type::Result(ty) => match read_u8(buf)? {
+            0 => Ok(Val::Result(Err(read_payload(buf, ty.err())?))),
+            1 => Ok(Val::Result(Ok(read_payload(buf, ty.ok())?))),
+            b => bail!("invalid result tag `{b}`"),
+        },
+        Type::Flags(ty) => {
+            let names = ty.names().collect::<Vec<_>>();
+            let bits = take(buf, names.len().div_ceil(8))?;
+            let mut flags = Vec::default();
+            for (i, b) in bits.iter().enumerate() {
+                for j in 0..8 {
+                    if b & (1 << j) == 0 {
+                        continue;
+                    }
+                    let Some(name) = names.get(i * 8 + j) else {
+                        bail!("unknown flag bit `{}`", i * 8 + j);
+                    };
+                    flags.push((*name).into());
+                }
+            }
+            Ok(Val::Flags(flags))
+        }
+        Type::Future(..) => bail!("futures not supported"),
+        Type::Stream(..) => bail!("streams not supported"),
+        Type::ErrorContext => bail!("error context not supported"),
+    }
+}
+
+fn write_len(buf: &mut Vec<u8>, n: usize) -> anyhow::Result<()> {
+    let n = u32::try_from(n).context("length does not fit in u32")?;
+    buf.extend(n.to_le_bytes());
+    Ok(())
+}
+
+fn write_string(buf: &mut Vec<u8>, s: &str) -> anyhow::Result<()> {
+    write_len(buf, s.len())?;
+    buf.extend(s.as_bytes());
+    Ok(())
+}
+
+fn write_index(buf: &mut Vec<u8>, i: Option<usize>, name: &str) -> anyhow::Result<()> {
+    let i = i.with_context(|| format!("unknown case `{name}`"))?;
+    let i = u8::try_from(i).context("case index does not fit in u8")?;
+    buf.push(i);
+    Ok(())
+}
+
+fn write_payload(buf: &mut Vec<u8>, ty: Option<Type>, v: Option<&Val>) -> anyhow::Result<()> {
+    match (ty, v) {
+        (Some(ty), Some(v)) => write(buf, &ty, v),
+        (None, None) => Ok(()),
+        (Some(..), None) => bail!("missing payload"),
+        (None, Some(..)) => bail!("unexpected payload"),
+    }
+}
+
+fn write(buf: &mut Vec<u8>, ty: &Type, v: &Val) -> anyhow::Result<()> {
+    match (ty, v) {
+        (Type::Bool, Val::Bool(v)) => buf.push((*v).into()),
+        (Type::S8, Val::S8(v)) => buf.extend(v.to_le_bytes()),
+        (Type::U8, Val::U8(v)) => buf.push(*v),
+        (Type::S16, Val::S16(v)) => buf.extend(v.to_le_bytes()),
+        (Type::U16, Val::U16(v)) => buf.extend(v.to_le_bytes()),
+        (Type::S32, Val::S32(v)) => buf.extend(v.to_le_bytes()),
+        (Type::U32 | Type::Own(..) | Type::Borrow(..), Val::U32(v)) => buf.extend(v.to_le_bytes()),
+        (Type::S64, Val::S64(v)) => buf.extend(v.to_le_bytes()),
+        (Type::U64, Val::U64(v)) => buf.extend(v.to_le_bytes()),
+        (Type::Float32, Val::Float32(v)) => {
+            ensure!(!v.is_nan(), "NaN not allowed");
+            buf.extend(v.to_le_bytes());
+        }
+        (Type::Float64, Val::Float64(v)) => {
+            ensure!(!v.is_nan(), "NaN not allowed");
+            buf.extend(v.to_le_bytes());
+        }
+        (Type::Char, Val::Char(v)) => buf.extend(u32::from(*v).to_le_bytes()),
+        (Type::String, Val::String(v)) => write_string(buf, v)?,
+        (Type::List(ty), Val::List(vs)) => {
+            let ty = ty.ty();
+            write_len(buf, vs.len())?;
+            for v in vs {
+                write(buf, &ty, v)?;
+            }
+        }
+        (Type::Record(ty), Val::Record(vs)) => {
+            ensure!(ty.fields().len() == vs.len(), "record field count mismatch");
+            for (types::Field { name, ty }, (_, v)) in ty.fields().zip(vs) {
+                write(buf, &ty, v).with_context(|| format!("failed to encode field `{name}`"))?;
+            }
+        }
+        (Type::Tuple(ty), Val::Tuple(vs)) => {
+            ensure!(ty.types().len() == vs.len(), "tuple length mismatch");
+            for (ty, v) in ty.types().zip(vs) {
+                write(buf, &ty, v)?;
+            }
+        }
+        (Type::Variant(ty), Val::Variant(name, v)) => {
+            let i = ty.cases().position(|case| case.name == name);
+            write_index(buf, i, name)?;
+            let case = ty.cases().find(|case| case.name == name);
+            write_payload(buf, case.and_then(|case| case.ty), v.as_deref())
+                .with_context(|| format!("failed to encode case `{name}`"))?;
+        }
+        (Type::Enum(ty), Val::Enum(name)) => {
+            let i = ty.names().position(|case| case == name);
+            write_index(buf, i, name)?;
+        }
+        (Type::Option(..), Val::Option(None)) => buf.push(0),
+        (Type::Option(ty), Val::Option(Some(v))) => {
+            buf.push(1);
+            write(buf, &ty.ty(), v)?;
+        }
+        (Type::Result(ty), Val::Result(Err(v))) => {
+            buf.push(0);
+            write_payload(buf, ty.err(), v.as_deref())?;
+        }
+        (Type::Result(ty), Val::Result(Ok(v))) => {
+            buf.push(1);
+            write_payload(buf, ty.ok(), v.as_deref())?;
+        }
+        (Type::Flags(ty), Val::Flags(flags)) => {
+            let names = ty.names().collect::<Vec<_>>();
+            let mut bits = vec![0u8; names.len().div_ceil(8)];
+            for flag in flags {
+                let Some(i) = names.iter().position(|name| name == flag) else {
+                    bail!("unknown flag `{flag}`");
+                };
+                bits[i / 8] |= 1 << (i % 8);
+            }
+            buf.extend(bits);
+        }
+        _ => bail!("value does not match type"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    //! WIT to Borsh layout rules
+
+    use super::*;
+    use crate::codecs::tests::{ty, u8_u64_func};
+
+    #[track_caller]
+    fn assert_layout(ty: &Type, v: Val, buf: &[u8]) {
+        assert_eq!(decode(ty, buf).expect("failed to decode"), v);
+        assert_eq!(encode(ty, &v).expect("failed to encode"), buf);
+    }
+
+    #[test]
+    fn primitives() {
+        assert_layout(&ty("bool"), Val::Bool(true), &[1]);
+        assert_layout(&ty("s8"), Val::S8(-2), &[0xfe]);
+        assert_layout(&ty("u16"), Val::U16(0x0102), &[2, 1]);
+        assert_layout(&ty("s32"), Val::S32(-1), &[0xff; 4]);
+        assert_layout(&ty("u64"), Val::U64(42), &[42, 0, 0, 0, 0, 0, 0, 0]);
+        assert_layout(&ty("f32"), Val::Float32(1.0), &1f32.to_le_bytes());
+        assert_layout(&ty("char"), Val::Char('é'), &[0xe9, 0, 0, 0]);
+        assert!(decode(&ty("bool"), &[2]).is_err());
+        assert!(decode(&ty("f64"), &f64::NAN.to_le_bytes()).is_err());
+        assert!(decode(&ty("char"), &0xd800u32.to_le_bytes()).is_err());
+    }
+
+    #[test]
+    fn strings_and_lists_are_length_prefixed() {
+        assert_layout(
+            &ty("string"),
+            Val::String("hi".into()),
+            &[2, 0, 0, 0, b'h', b'i'],
+        );
+        assert_layout(
+            &ty("(list u16)"),
+            Val::List(vec![Val::U16(1), Val::U16(2)]),
+            &[2, 0, 0, 0, 1, 0, 2, 0],
+        );
+        assert!(decode(&ty("(list u8)"), &[2, 0, 0, 0, 1]).is_err());
+    }
+
+    #[test]
+    fn records_and_tuples_are_concatenated() {
+        assert_layout(
+            &ty(r#"(record (field "a" u8) (field "b" (tuple u8 u16)))"#),
+            Val::Record(vec![
+                ("a".into(), Val::U8(1)),
+                ("b".into(), Val::Tuple(vec![Val::U8(2), Val::U16(3)])),
+            ]),
+            &[1, 2, 3, 0],
+        );
+    }
+
+    #[test]
+    fn variants_and_enums_are_prefixed_by_case_index() {
+        let variant = ty(r#"(variant (case "a") (case "b" u32))"#);
+        assert_layout(&variant, Val::Variant("a".into(), None), &[0]);
+        assert_layout(
+            &variant,
+            Val::Variant("b".into(), Some(Box::new(Val::U32(7)))),
+            &[1, 7, 0, 0, 0],
+        );
+        assert!(decode(&variant, &[2]).is_err());
+        assert_layout(&ty(r#"(enum "x" "y")"#), Val::Enum("y".into()), &[1]);
+    }
+
+    #[test]
+    fn options_and_results() {
+        let option = ty("(option u8)");
+        assert_layout(&option, Val::Option(None), &[0]);
+        assert_layout(&option, Val::Option(Some(Box::new(Val::U8(5)))), &[1, 5]);
+
+        // `err` comes first, like in Borsh encoding of Rust `Result`
+        let result = ty("(result u8 (error string))");
+        assert_layout(
+            &result,
+            Val::Result(Ok(Some(Box::new(Val::U8(5))))),
+            &[1, 5],
+        );
+        assert_layout(
+            &result,
+            Val::Result(Err(Some(Box::new(Val::String("e".into()))))),
+            &[0, 1, 0, 0, 0, b'e'],
+        );
+        assert_layout(&ty("(result)"), Val::Result(Ok(None)), &[1]);
+    }
+
+    #[test]
+    fn flags_are_bit_sets() {
+        let flags = ty(r#"(flags "a" "b" "c" "d" "e" "f" "g" "h" "i")"#);
+        assert_layout(
+            &flags,
+            Val::Flags(vec!["b".into(), "i".into()]),
+            &[0b0000_0010, 0b0000_0001],
+        );
+        assert!(decode(&flags, &[0, 0b0000_0010]).is_err());
+    }
+
+    #[test]
+    fn params_are_concatenated() {
+        let ty = u8_u64_func();
+        let params = Borsh
+            .decode_params(&ty, &[1, 2, 0, 0, 0, 0, 0, 0, 0])
+            .expect("failed to decode");
+        assert_eq!(params, [Val::U8(1), Val::U64(2)]);
+        assert!(
+            Borsh
+                .decode_params(&ty, &[1, 2, 0, 0, 0, 0, 0, 0, 0, 0])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn batches_are_vecs_of_tuples() {
+        let mut buf = vec![1, 0, 0, 0];
+        buf.extend([1, 0, 0, 0, b'c', 1, 0, 0, 0, b'f', 2, 0, 0, 0, 3, 5]);
+        let calls = Borsh.decode_batch(&buf).expect("failed to decode");
+        assert_eq!(
+            calls,
+            [BatchCall {
+                contract: "c".into(),
+                func: "f".into(),
+                args: vec![3, 5],
+            }]
+        );
+        assert!(Borsh.decode_batch(&buf[..buf.len() - 1]).is_err());
+
+        let buf = Borsh
+            .encode_batch(&[
+                Ok(BatchOutcome {
+                    results: vec![8],
+                    gas_used: 2,
+                    logs: vec!["l".into()],
+                }),
+                Err("e".into()),
+            ])
+            .expect("failed to encode");
+        let mut expected = vec![2, 0, 0, 0];
+        expected.extend([1, 1, 0, 0, 0, 8, 2, 0, 0, 0, 0, 0, 0, 0]);
+        expected.extend([1, 0, 0, 0, 1, 0, 0, 0, b'l']);
+        expected.extend([0, 1, 0, 0, 0, b'e']);
+        assert_eq!(buf, expected);
+    }
+}
@@ -0,0 +1,657 @@
+//! JSON codec based on `serde_json`, which decodes directly into [`Val`]
+//!
+//! Function parameters are encoded as a JSON array. Values are encoded as follows:
+//!
+//! - records: objects keyed by field names, `snake_case` field names are accepted as well.
+//!   Missing `option` fields are decoded as `none`
+//! - lists and tuples: arrays
+//! - variants: `{"<case>": <payload>}` or `"<case>"` for cases without payload
+//! - enums: case name strings
+//! - options: `null` or the value. Options of options are not supported, since `null`
+//!   would be ambiguous
+//! - results: `{"ok": <payload>}` or `{"err": <payload>}`, payload is `null` if absent
+//! - flags: arrays of flag name strings
+//! - floats: numbers, or the strings `"NaN"`, `"Infinity"` and `"-Infinity"`, which JSON
+//!   numbers cannot represent
+//! - chars: single-character strings
+//! - resources: `u32` handle IDs
+//!
+//! Results are encoded as a JSON array following the same rules, using WIT field names.
+//! Batches of invocations are encoded as an array of `{"contract": <name>, "func": <name>,
+//! "args": <params>}` objects, where `args` defaults to `[]`, their outcomes as an array of
+//! `{"ok": <results>, "gas_used": <gas>, "logs": [<message>...]}` or `{"err": <message>}`
+//! objects.
+
+use core::fmt;
+
+use std::io::Write as _;
+
+use anyhow::{Context as _, bail, ensure};
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Unexpected, Visitor};
+use serde::ser::{self, Serialize, Serializer};
+use wasmtime::component::{Type, Val, types};
+
+use super::{BatchCall, BatchOutcome, HostCodec};
+
+/// JSON codec
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Json;
+
+impl HostCodec for Json {
+    fn decode_params(&self, ty: &types::ComponentFunc, buf: &[u8]) -> anyhow::Result<Vec<Val>> {
+        let tys = ty.params().map(|(_, ty)| ty).collect::<Vec<_>>();
+        if tys.is_empty() && buf.iter().all(u8::is_ascii_whitespace) {
+            return Ok(Vec::default());
+        }
+        let mut de = serde_json::Deserializer::from_slice(buf);
+        let params = Elements(&tys).deserialize(&mut de)?;
+        de.end()?;
+        Ok(params)
+    }
+
+    fn encode_value(&self, _ty: &Type, v: &Val) -> anyhow::Result<Vec<u8>> {
+        encode(v)
+    }
+
+    fn encode_results(
+        &self,
+        ty: &types::ComponentFunc,
+        results: &[Val],
+    ) -> anyhow::Result<Vec<u8>> {
+        ensure!(
+            ty.results().len() == results.len(),
+            "expected {} results, got {}",
+            ty.results().len(),
+            results.len()
+        );
+        let results = results.iter().map(Serialized).collect::<Vec<_>>();
+        serde_json::to_vec(&results).context("failed to encode results")
+    }
+
+    fn decode_batch(&self, buf: &[u8]) -> anyhow::Result<Vec<BatchCall>> {
+        let calls: Vec<serde_json::Map<String, serde_json::Value>> = serde_json::from_slice(buf)?;
+        calls
+            .into_iter()
+            .enumerate()
+            .map(|(i, mut call)| {
+                let mut field = |name: &str| match call.remove(name) {
+                    Some(serde_json::Value::String(v)) => Ok(v),
+                    Some(..) => bail!("field `{name}` of call {i} is not a string"),
+                    None => bail!("field `{name}` of call {i} missing"),
+                };
+                let contract = field("contract")?;
+                let func = field("func")?;
+                let args = call
+                    .remove("args")
+                    .unwrap_or_else(|| serde_json::Value::Array(Vec::default()));
+                if let Some(key) = call.keys().next() {
+                    bail!("unknown field `{key}` of call {i}");
+                }
+                Ok(BatchCall {
+                    contract,
+                    func,
+                    args: serde_json::to_vec(&args)?,
+                })
+            })
+            .collect()
+    }
+
+    fn encode_batch(&self, outcomes: &[Result<BatchOutcome, String>]) -> anyhow::Result<Vec<u8>> {
+        let mut buf = vec![b'['];
+        for (i, outcome) in outcomes.iter().enumerate() {
+            if i > 0 {
+                buf.push(b',');
+            }
+            match outcome {
+                // Results are already encoded as JSON
+                Ok(BatchOutcome {
+                    results,
+                    gas_used,
+                    logs,
+                }) => {
+                    buf.extend(br#"{"ok":"#);
+                    buf.extend(results);
+                    write!(buf, r#","gas_used":{gas_used},"logs":"#)?;
+                    serde_json::to_writer(&mut buf, logs)?;
+                    buf.push(b'}');
+                }
+                Err(err) => {
+                    buf.extend(br#"{"err":"#);
+                    serde_json::to_writer(&mut buf, err)?;
+                    buf.push(b'}');
+                }
+            }
+        }
+        buf.push(b']');
+        Ok(buf)
+    }
+}
+
+/// Decodes a value of type `ty` from JSON `buf`.
+pub fn decode(ty: &Type, buf: &[u8]) -> anyhow::Result<Val> {
+    let mut de = serde_json::Deserializer::from_slice(buf);
+    let v = Value(ty).deserialize(&mut de)?;
+    de.end()?;
+    Ok(v)
+}
+
+/// Encodes value `v` as JSON.
+pub fn encode(v: &Val) -> anyhow::Result<Vec<u8>> {
+    serde_json::to_vec(&Serialized(v)).context("failed to encode value")
+}
+
+/// Returns whether WIT name `name` matches JSON key `key`.
+fn name_matches(name: &str, key: &str) -> bool {
+    name.len() == key.len()
+        && name
+            .bytes()
+            .zip(key.bytes())
+            .all(|(n, k)| n == k || n == b'-' && k == b'_')
+}
+
+/// Returns the string encoding of non-finite float `v`.
+fn non_finite_name(v: f64) -> &'static str {
+    if v.is_nan() {
+        "NaN"
+    } else if v.is_sign_positive() {
+        "Infinity"
+    } else {
+        "-Infinity"
+    }
+}
+
+/// Returns the non-finite float encoded as string `v`.
+fn non_finite_value(v: &str) -> Option<f64> {
+    match v {
+        "NaN" => Some(f64::NAN),
+        "Infinity" => Some(f64::INFINITY),
+        "-Infinity" => Some(f64::NEG_INFINITY),
+        _ => None,
+    }
+}
+
+fn type_name(ty: &Type) -> &'static str {
+    match ty {
+        Type::Bool => "bool",
+        Type::S8 => "s8",
+        Type::U8 => "u8",
+        Type::S16 => "s16",
+        Type::U16 => "u16",
+        Type::S32 => "s32",
+        Type::U32 => "u32",
+        Type::S64 => "s64",
+        Type::U64 => "u64",
+        Type::Float32 => "f32",
+        Type::Float64 => "f64",
+        Type::Char => "char",
+        Type::String => "string",
+        Type::List(..) => "list",
+        Type::Record(..) => "record",
+        Type::Tuple(..) => "tuple",
+        Type::Variant(..) => "variant",
+        Type::Enum(..) => "enum",
+        Type::Option(..) => "option",
+        Type::Result(..) => "result",
+        Type::Flags(..) => "flags",
+        Type::Own(..) | Type::Borrow(..) => "resource",
+        Type::Future(..) => "future",
+        Type::Stream(..) => "stream",
+        Type::ErrorContext => "error-context",
+    }
+}
+
+/// Fixed-length sequence of values of types `.0`
+struct Elements<'a>(&'a [Type]);
+
+impl<'de> DeserializeSeed<'de> for Elements<'_> {
+    type Value = Vec<Val>;
+
+    fn deserialize<D: Deserializer<'de>>(self, de: D) -> Result<Self::Value, D::Error> {
+        de.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Elements<'_> {
+    type Value = Vec<Val>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "an array of {} elements", self.0.len())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut vs = Vec::with_capacity(self.0.len());
+        for (i, ty) in self.0.iter().enumerate() {
+            let Some(v) = seq.next_element_seed(Value(ty))? else {
+                return Err(de::Error::invalid_length(i, &self));
+            };
+            vs.push(v);
+        }
+        if seq.next_element::<de::IgnoredAny>()?.is_some() {
+            return Err(de::Error::invalid_length(self.0.len() + 1, &self));
+        }
+        Ok(vs)
+    }
+}
+
+/// Value of type `.0`
+struct Value<'a>(&'a Type);
+
+impl<'de> DeserializeSeed<'de> for Value<'_> {
+    type Value = Val;
+
+    fn deserialize<D: Deserializer<'de>>(self, de: D) -> Result<Self::Value, D::Error> {
+        match self.0 {
+            Type::Option(ty) if matches!(ty.ty(), Type::Option(..)) => {
+                Err(de::Error::custom("options of options not supported"))
+            }
+            Type::Option(..) => de.deserialize_option(self),
+            Type::Future(..) | Type::Stream(..) | Type::ErrorContext => Err(de::Error::custom(
+                format_args!("{} values not supported", type_name(self.0)),
+            )),
+            _ => de.deserialize_any(self),
+        }
+    }
+}
+
+impl<'de> Visitor<'de> for Value<'_> {
+    type Value = Val;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a value of type `{}`", type_name(self.0))
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Self::Value, E> {
+        match self.0 {
+            Type::Bool => Ok(Val::Bool(v)),
+            _ => Err(E::invalid_type(Unexpected::Bool(v), &self)),
+        }
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        let err = || E::invalid_value(Unexpected::Unsigned(v), &self);
+        match self.0 {
+            Type::U8 => v.try_into().map(Val::U8).map_err(|_| err()),
+            Type::U16 => v.try_into().map(Val::U16).map_err(|_| err()),
+            Type::U32 | Type::Own(..) | Type::Borrow(..) => {
+                v.try_into().map(Val::U32).map_err(|_| err())
+            }
+            Type::U64 => Ok(Val::U64(v)),
+            Type::S8 => v.try_into().map(Val::S8).map_err(|_| err()),
+            Type::S16 => v.try_into().map(Val::S16).map_err(|_| err()),
+            Type::S32 => v.try_into().map(Val::S32).map_err(|_| err()),
+            Type::S64 => v.try_into().map(Val::S64).map_err(|_| err()),
+            Type::Float32 => Ok(Val::Float32(v as _)),
+            Type::Float64 => Ok(Val::Float64(v as _)),
+            _ => Err(E::invalid_type(Unexpected::Unsigned(v), &self)),
+        }
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        let err = || E::invalid_value(Unexpected::Signed(v), &self);
+        match self.0 {
+            Type::U8 => v.try_into().map(Val::U8).map_err(|_| err()),
+            Type::U16 => v.try_into().map(Val::U16).map_err(|_| err()),
+            Type::U32 | Type::Own(..) | Type::Borrow(..) => {
+                v.try_into().map(Val::U32).map_err(|_| err())
+            }
+            Type::U64 => v.try_into().map(Val::U64).map_err(|_| err()),
+            Type::S8 => v.try_into().map(Val::S8).map_err(|_| err()),
+            Type::S16 => v.try_into().map(Val::S16).map_err(|_| err()),
+            Type::S32 => v.try_into().map(Val::S32).map_err(|_| err()),
+            Type::S64 => Ok(Val::S64(v)),
+            Type::Float32 => Ok(Val::Float32(v as _)),
+            Type::Float64 => Ok(Val::Float64(v as _)),
+            _ => Err(E::invalid_type(Unexpected::Signed(v), &self)),
+        }
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+        match self.0 {
+            Type::Float32 => Ok(Val::Float32(v as _)),
+            Type::Float64 => Ok(Val::Float64(v)),
+            _ => Err(E::invalid_type(Unexpected::Float(v), &self)),
+        }
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        match self.0 {
+            Type::String => Ok(Val::String(v.into())),
+            Type::Char => {
+                let mut cs = v.chars();
+                match (cs.next(), cs.next()) {
+                    (Some(c), None) => Ok(Val::Char(c)),
+                    _ => Err(E::invalid_value(Unexpected::Str(v), &self)),
+                }
+            }
+            Type::Float32 | Type::Float64 => match (non_finite_value(v), self.0) {
+                (Some(v), Type::Float32) => Ok(Val::Float32(v as _)),
+                (Some(v), _) => Ok(Val::Float64(v)),
+                (None, _) => Err(E::invalid_value(Unexpected::Str(v), &self)),
+            },
+            Type::Enum(ty) => {
+                if ty.names().any(|name| name == v) {
+                    Ok(Val::Enum(v.into()))
+                } else {
+                    Err(E::unknown_variant(v, &[]))
+                }
+            }
+            Type::Variant(ty) => match ty.cases().find(|case| case.name == v) {
+                Some(types::Case { ty: None, .. }) => Ok(Val::Variant(v.into(), None)),
+                Some(types::Case { ty: Some(..), .. }) => Err(E::custom(format_args!(
+                    "variant case `{v}` requires a payload"
+                ))),
+                None => Err(E::unknown_variant(v, &[])),
+            },
+            _ => Err(E::invalid_type(Unexpected::Str(v), &self)),
+        }
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Self::Value, E> {
+        match self.0 {
+            Type::String => Ok(Val::String(v)),
+            _ => self.visit_str(&v),
+        }
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        match self.0 {
+            Type::Option(..) => Ok(Val::Option(None)),
+            _ => Err(E::invalid_type(Unexpected::Option, &self)),
+        }
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        self.visit_none()
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, de: D) -> Result<Self::Value, D::Error> {
+        match self.0 {
+            Type::Option(ty) => {
+                let v = Value(&ty.ty()).deserialize(de)?;
+                Ok(Val::Option(Some(Box::new(v))))
+            }
+            _ => Err(de::Error::invalid_type(Unexpected::Option, &self)),
+        }
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        match self.0 {
+            Type::List(ty) => {
+                let ty = ty.ty();
+                let mut vs = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+                while let Some(v) = seq.next_element_seed(Value(&ty))? {
+                    vs.push(v);
+                }
+                Ok(Val::List(vs))
+            }
+            Type::Tuple(ty) => {
+                let tys = ty.types().collect::<Vec<_>>();
+                Elements(&tys).visit_seq(seq).map(Val::Tuple)
+            }
+            Type::Flags(ty) => {
+                let mut flags = Vec::default();
+                while let Some(flag) = seq.next_element::<String>()? {
+                    if !ty.names().any(|name| name == flag) {
+                        return Err(de::Error::custom(format_args!("unknown flag `{flag}`")));
+                    }
+                    flags.push(flag);
+                }
+                Ok(Val::Flags(flags))
+            }
+            _ => Err(de::Error::invalid_type(Unexpected::Seq, &self)),
+        }
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        match self.0 {
+            Type::Record(ty) => {
+                let fields = ty.fields().collect::<Vec<_>>();
+                let mut vs = vec![None; fields.len()];
+                while let Some(key) = map.next_key::<String>()? {
+                    let Some(i) = fields.iter().position(|f| name_matches(f.name, &key)) else {
+                        return Err(de::Error::custom(format_args!("unknown field `{key}`")));
+                    };
+                    if vs[i].is_some() {
+                        return Err(de::Error::custom(format_args!("duplicate field `{key}`")));
+                    }
+                    vs[i] = Some(map.next_value_seed(Value(&fields[i].ty))?);
+                }
+                let mut record = Vec::with_capacity(fields.len());
+                for (types::Field { name, ty }, v) in fields.into_iter().zip(vs) {
+                    let v = match (v, ty) {
+                        (Some(v), _) => v,
+                        (None, Type::Option(..)) => Val::Option(None),
+                        (None, _) => {
+                            return Err(de::Error::custom(format_args!("missing field `{name}`")));
+                        }
+                    };
+                    record.push((name.into(), v));
+                }
+                Ok(Val::Record(record))
+            }
+            Type::Variant(ty) => {
+                let Some(key) = map.next_key::<String>()? else {
+                    return Err(de::Error::invalid_length(0, &self));
+                };
+                let Some(case) = ty.cases().find(|case| case.name == key) else {
+                    return Err(de::Error::unknown_variant(&key, &[]));
+                };
+                let v = if let Some(ty) = case.ty {
+                    Some(Box::new(map.next_value_seed(Value(&ty))?))
+                } else {
+                    map.next_value::<()>()?;
+                    None
+                };
+                if map.next_key::<de::IgnoredAny>()?.is_some() {
+                    return Err(de::Error::invalid_length(2, &self));
+                }
+                Ok(Val::Variant(key, v))
+            }
+            Type::Result(ty) => {
+                let Some(key) = map.next_key::<String>()? else {
+                    return Err(de::Error::invalid_length(0, &self));
+                };
+                let payload = |map: &mut A, ty: Option<Type>| {
+                    if let Some(ty) = ty {
+                        map.next_value_seed(Value(&ty)).map(|v| Some(Box::new(v)))
+                    } else {
+                        map.next_value::<()>().map(|()| None)
+                    }
+                };
+                let v = match key.as_str() {
+                    "ok" => Ok(payload(&mut map, ty.ok())?),
+                    "err" => Err(payload(&mut map, ty.err())?),
+                    _ => return Err(de::Error::unknown_variant(&key, &["ok", "err"])),
+                };
+                if map.next_key::<de::IgnoredAny>()?.is_some() {
+                    return Err(de::Error::invalid_length(2, &self));
+                }
+                Ok(Val::Result(v))
+            }
+            _ => Err(de::Error::invalid_type(Unexpected::Map, &self)),
+        }
+    }
+}
+
+/// Serialization of value `.0`
+struct Serialized<'a>(&'a Val);
+
+impl Serialize for Serialized<'_> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            Val::Bool(v) => s.serialize_bool(*v),
+            Val::S8(v) => s.serialize_i8(*v),
+            Val::U8(v) => s.serialize_u8(*v),
+            Val::S16(v) => s.serialize_i16(*v),
+            Val::U16(v) => s.serialize_u16(*v),
+            Val::S32(v) => s.serialize_i32(*v),
+            Val::U32(v) => s.serialize_u32(*v),
+            Val::S64(v) => s.serialize_i64(*v),
+            Val::U64(v) => s.serialize_u64(*v),
+            Val::Float32(v) if !v.is_finite() => s.serialize_str(non_finite_name((*v).into())),
+            Val::Float64(v) if !v.is_finite() => s.serialize_str(non_finite_name(*v)),
+            Val::Float32(v) => s.serialize_f32(*v),
+            Val::Float64(v) => s.serialize_f64(*v),
+            Val::Char(v) => s.serialize_char(*v),
+            Val::String(v) => s.serialize_str(v),
+            Val::List(vs) | Val::Tuple(vs) => s.collect_seq(vs.iter().map(Serialized)),
+            Val::Record(fields) => s.collect_map(fields.iter().map(|(k, v)| (k, Serialized(v)))),
+            Val::Variant(name, None) | Val::Enum(name) => s.serialize_str(name),
+            Val::Variant(name, Some(v)) => s.collect_map([(name, Serialized(v))]),
+            Val::Option(Some(v)) if matches!(**v, Val::Option(..)) => {
+                Err(ser::Error::custom("options of options not supported"))
+            }
+            Val::Option(None) => s.serialize_none(),
+            Val::Option(Some(v)) => s.serialize_some(&Serialized(v)),
+            Val::Result(Ok(v)) => s.collect_map([("ok", v.as_deref().map(Serialized))]),
+            Val::Result(Err(v)) => s.collect_map([("err", v.as_deref().map(Serialized))]),
+            Val::Flags(names) => s.collect_seq(names),
+            Val::Resource(..) => Err(ser::Error::custom("resources not supported")),
+            Val::Future(..) => Err(ser::Error::custom("future values not supported")),
+            Val::Stream(..) => Err(ser::Error::custom("stream values not supported")),
+            Val::ErrorContext(..) => Err(ser::Error::custom("error-context values not supported")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codecs::tests::{ty, u8_u64_func};
+
+    #[track_caller]
+    fn assert_roundtrip(ty: &Type, v: Val, json: &str) {
+        assert_eq!(encode(&v).expect("failed to encode"), json.as_bytes());
+        assert_eq!(decode(ty, json.as_bytes()).expect("failed to decode"), v);
+    }
+
+    #[test]
+    fn encoding_matches_decoding() {
+        assert_roundtrip(
+            &ty(r#"(record (field "a-b" u8) (field "c" (option string)))"#),
+            Val::Record(vec![
+                ("a-b".into(), Val::U8(1)),
+                ("c".into(), Val::Option(None)),
+            ]),
+            r#"{"a-b":1,"c":null}"#,
+        );
+        let variant = ty(r#"(variant (case "a") (case "b" (tuple u32 char)))"#);
+        assert_roundtrip(&variant, Val::Variant("a".into(), None), r#""a""#);
+        assert_roundtrip(
+            &variant,
+            Val::Variant(
+                "b".into(),
+                Some(Box::new(Val::Tuple(vec![Val::U32(7), Val::Char('x')]))),
+            ),
+            r#"{"b":[7,"x"]}"#,
+        );
+        assert_roundtrip(
+            &ty("(result (error string))"),
+            Val::Result(Ok(None)),
+            r#"{"ok":null}"#,
+        );
+        assert_roundtrip(
+            &ty(r#"(flags "x" "y")"#),
+            Val::Flags(vec!["y".into()]),
+            r#"["y"]"#,
+        );
+    }
+
+    #[test]
+    fn params() {
+        let ty = u8_u64_func();
+        assert_eq!(
+            Json.decode_params(&ty, b" [1, 18446744073709551615] ")
+                .expect("failed to decode"),
+            [Val::U8(1), Val::U64(u64::MAX)]
+        );
+        assert!(Json.decode_params(&ty, b"[1]").is_err());
+        assert!(Json.decode_params(&ty, b"[1, 2, 3]").is_err());
+        assert!(Json.decode_params(&ty, b"[256, 2]").is_err());
+        assert!(Json.decode_params(&ty, b"[1, 2] x").is_err());
+        assert!(Json.decode_params(&ty, b"").is_err());
+        assert_eq!(
+            Json.encode_results(&ty, &[]).expect("failed to encode"),
+            b"[]"
+        );
+    }
+
+    #[test]
+    fn non_finite_floats() {
+        let f64_ty = ty("f64");
+        assert_roundtrip(&f64_ty, Val::Float64(f64::INFINITY), r#""Infinity""#);
+        assert_roundtrip(&f64_ty, Val::Float64(f64::NEG_INFINITY), r#""-Infinity""#);
+        assert_roundtrip(&ty("f32"), Val::Float32(f32::INFINITY), r#""Infinity""#);
+        assert_eq!(
+            encode(&Val::Float32(f32::NAN)).expect("failed to encode"),
+            br#""NaN""#
+        );
+        assert!(matches!(
+            decode(&f64_ty, br#""NaN""#).expect("failed to decode"),
+            Val::Float64(v) if v.is_nan()
+        ));
+        assert!(decode(&f64_ty, br#""nan""#).is_err());
+        assert!(decode(&f64_ty, b"null").is_err());
+    }
+
+    #[test]
+    fn options_of_options_are_rejected() {
+        let nested = ty("(option (option u8))");
+        for json in ["null", "1", "[null]"] {
+            assert!(decode(&nested, json.as_bytes()).is_err(), "{json}");
+        }
+        let v = Val::Option(Some(Box::new(Val::Option(None))));
+        assert!(encode(&v).is_err());
+        // Options nested in other types are unambiguous
+        assert_roundtrip(
+            &ty("(option (list (option u8)))"),
+            Val::Option(Some(Box::new(Val::List(vec![
+                Val::Option(None),
+                Val::Option(Some(Box::new(Val::U8(1)))),
+            ])))),
+            "[null,1]",
+        );
+    }
+
+    #[test]
+    fn batches() {
+        let calls = Json
+            .decode_batch(br#"[{"contract": "c", "func": "f", "args": [3, 5]}, {"contract": "c", "func": "g"}]"#)
+            .expect("failed to decode");
+        assert_eq!(
+            calls,
+            [
+                BatchCall {
+                    contract: "c".into(),
+                    func: "f".into(),
+                    args: b"[3,5]".to_vec(),
+                },
+                BatchCall {
+                    contract: "c".into(),
+                    func: "g".into(),
+                    args: b"[]".to_vec(),
+                },
+            ]
+        );
+        assert!(Json.decode_batch(br#"[{"contract": "c"}]"#).is_err());
+        assert!(
+            Json.decode_batch(br#"[{"contract": "c", "func": "f", "gas": 1}]"#)
+                .is_err()
+        );
+
+        let buf = Json
+            .encode_batch(&[
+                Ok(BatchOutcome {
+                    results: b"[8]".to_vec(),
+                    gas_used: 2,
+                    logs: vec!["l".into()],
+                }),
+                Err("\"e\"".into()),
+            ])
+            .expect("failed to encode");
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            r#"[{"ok":[8],"gas_used":2,"logs":["l"]},{"err":"\"e\""}]"#
+        );
+    }
+}
@@ -0,0 +1,130 @@
+//! Codecs decoding function parameters natively on the host
+
+#[cfg(feature = "borsh")]
+pub mod borsh;
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "raw")]
+pub mod raw;
+
+use std::sync::Arc;
+
+use anyhow::bail;
+use wasmtime::component::{Type, Val, types};
+
+/// Invocation within a batch, see [`HostCodec::decode_batch`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BatchCall {
+    /// Name of the invoked contract
+    pub contract: String,
+    /// Name of the invoked function
+    pub func: String,
+    /// Parameters encoded using the codec
+    pub args: Vec<u8>,
+}
+
+/// Outcome of a successful invocation within a batch, see [`HostCodec::encode_batch`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BatchOutcome {
+    /// Results encoded using the codec, see [`HostCodec::encode_results`]
+    pub results: Vec<u8>,
+    /// Amount of gas used by the invoked function
+    pub gas_used: u64,
+    /// Messages logged during the invocation
+    pub logs: Vec<String>,
+}
+
+/// Codec decoding function parameters natively on the host,
+/// which avoids instantiating a codec component.
+pub trait HostCodec: Send + Sync {
+    /// Decodes parameters of function type `ty` from `buf`.
+    fn decode_params(&self, ty: &types::ComponentFunc, buf: &[u8]) -> anyhow::Result<Vec<Val>>;
+
+    /// Encodes value `v` of type `ty`.
+    fn encode_value(&self, ty: &Type, v: &Val) -> anyhow::Result<Vec<u8>> {
+        _ = (ty, v);
+        bail!("encoding values is not supported by the codec")
+    }
+
+    /// Encodes results `results` of function type `ty`.
+    fn encode_results(
+        &self,
+        ty: &types::ComponentFunc,
+        results: &[Val],
+    ) -> anyhow::Result<Vec<u8>> {
+        _ = (ty, results);
+        bail!("encoding results is not supported by the codec")
+    }
+
+    /// Decodes a batch of invocations from `buf`.
+    fn decode_batch(&self, buf: &[u8]) -> anyhow::Result<Vec<BatchCall>> {
+        _ = buf;
+        bail!("batches are not supported by the codec")
+    }
+
+    /// Encodes outcomes or error messages of a batch of invocations.
+    fn encode_batch(&self, outcomes: &[Result<BatchOutcome, String>]) -> anyhow::Result<Vec<u8>> {
+        _ = outcomes;
+        bail!("batches are not supported by the codec")
+    }
+}
+
+/// Returns all built-in codecs enabled at compile time by name.
+#[allow(clippy::vec_init_then_push, reason = "codecs are feature-gated")]
+pub fn builtin() -> Vec<(&'static str, Arc<dyn HostCodec>)> {
+    #[allow(unused_mut, reason = "codecs are feature-gated")]
+    let mut codecs: Vec<(&'static str, Arc<dyn HostCodec>)> = Vec::default();
+    #[cfg(feature = "borsh")]
+    codecs.push(("borsh", Arc::new(borsh::Borsh)));
+    #[cfg(feature = "json")]
+    codecs.push(("json", Arc::new(json::Json)));
+    #[cfg(feature = "raw")]
+    codecs.push(("raw", Arc::new(raw::Raw)));
+    codecs
+}
+
+/// Test helpers shared by the codecs
+#[cfg(all(test, any(feature = "borsh", feature = "json", feature = "raw")))]
+mod tests {
+    use wasmtime::Engine;
+    use wasmtime::component::Component;
+
+    use super::*;
+
+    /// Returns type `ty` given in component model text format.
+    pub(super) fn ty(ty: &str) -> Type {
+        let engine = Engine::default();
+        let component = Component::new(
+            &engine,
+            format!(r#"(component (type $t {ty}) (export "t" (type $t)))"#),
+        )
+        .expect("failed to compile component");
+        let Some(types::ComponentItem::Type(ty)) =
+            component.component_type().get_export(&engine, "t")
+        else {
+            panic!("type not exported");
+        };
+        ty
+    }
+
+    /// Returns the type of a function with parameters `a: u8` and `b: u64`.
+    pub(super) fn u8_u64_func() -> types::ComponentFunc {
+        let engine = Engine::default();
+        let component = Component::new(
+            &engine,
+            r#"(component
+                (core module $m (func (export "f") (param i32 i64)))
+                (core instance $i (instantiate $m))
+                (func (export "f") (param "a" u8) (param "b" u64)
+                    (canon lift (core func $i "f")))
+            )"#,
+        )
+        .expect("failed to compile component");
+        let Some(types::ComponentItem::ComponentFunc(ty)) =
+            component.component_type().get_export(&engine, "f")
+        else {
+            panic!("function not exported");
+        };
+        ty
+    }
+}
@@ -0,0 +1,603 @@
+//! Canonical ABI codec, which lifts parameters from their lowered representation
+//!
+//! The buffer holds the parameter tuple laid out in memory according to the
+//! [canonical ABI](https://github.com/WebAssembly/component-model/blob/main/design/mvp/CanonicalABI.md)
+//! starting at offset `0`. Pointers of strings and lists are offsets into the buffer itself,
+//! strings are UTF-8 encoded. Resource handles are `u32` handle IDs in place of table indices.
+//! Values are lifted like the canonical ABI does, except that
+//! invalid pointers, discriminants and `char` values fail decoding instead of trapping.
+//! Strings and lists may not lift more bytes than the buffer holds in total, each list
+//! element counting at least one byte, so that aliased pointers cannot amplify
+//! the memory used for decoding.
+//!
+//! Results are lowered like parameters, batches of invocations are not supported.
+//!
+//! No parsing is involved, which makes this the fastest codec for trusted callers able to
+//! lower parameters themselves, for example using [`encode`].
+
+use anyhow::{Context as _, bail, ensure};
+use wasmtime::component::{Type, Val, types};
+
+use super::HostCodec;
+
+/// Canonical ABI codec
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Raw;
+
+impl HostCodec for Raw {
+    fn decode_params(&self, ty: &types::ComponentFunc, buf: &[u8]) -> anyhow::Result<Vec<Val>> {
+        let tys = ty.params().map(|(_, ty)| ty).collect::<Vec<_>>();
+        let layout = Layout::fields(&tys)?;
+        ensure!(
+            buf.len() >= layout.size,
+            "buffer too short for parameters of size {}",
+            layout.size
+        );
+        let mut offset = 0;
+        let mut budget = buf.len();
+        let mut params = Vec::with_capacity(tys.len());
+        for ((name, _), ty) in ty.params().zip(&tys) {
+            let field = Layout::of(ty)?;
+            offset = field.align_offset(offset);
+            let v = load(buf, &mut budget, ty, offset)
+                .with_context(|| format!("failed to decode param `{name}`"))?;
+            params.push(v);
+            offset += field.size;
+        }
+        Ok(params)
+    }
+
+    fn encode_value(&self, ty: &Type, v: &Val) -> anyhow::Result<Vec<u8>> {
+        encode(ty, v)
+    }
+
+    fn encode_results(
+        &self,
+        ty: &types::ComponentFunc,
+        results: &[Val],
+    ) -> anyhow::Result<Vec<u8>> {
+        let tys = ty.results().collect::<Vec<_>>();
+        ensure!(
+            tys.len() == results.len(),
+            "expected {} results, got {}",
+            tys.len(),
+            results.len()
+        );
+        encode_fields(&tys, results)
+    }
+}
+
+/// Lifts a value of type `ty` stored at offset `0` of `buf`.
+pub fn decode(ty: &Type, buf: &[u8]) -> anyhow::Result<Val> {
+    load(buf, &mut buf.len(), ty, 0)
+}
+
+/// Lowers value `v` of type `ty` into a buffer, in which it is stored at offset `0`.
+pub fn encode(ty: &Type, v: &Val) -> anyhow::Result<Vec<u8>> {
+    let mut buf = vec![0; Layout::of(ty)?.size];
+    store(&mut buf, ty, v, 0)?;
+    Ok(buf)
+}
+
+/// Lowers parameters `params` of function type `ty`.
+pub fn encode_params(ty: &types::ComponentFunc, params: &[Val]) -> anyhow::Result<Vec<u8>> {
+    let tys = ty.params().map(|(_, ty)| ty).collect::<Vec<_>>();
+    ensure!(
+        tys.len() == params.len(),
+        "expected {} parameters, got {}",
+        tys.len(),
+        params.len()
+    );
+    encode_fields(&tys, params)
+}
+
+/// Lowers values `vs` of types `tys` as a tuple.
+fn encode_fields(tys: &[Type], vs: &[Val]) -> anyhow::Result<Vec<u8>> {
+    let mut buf = vec![0; Layout::fields(tys)?.size];
+    let mut offset = 0;
+    for (ty, v) in tys.iter().zip(vs) {
+        let field = Layout::of(ty)?;
+        offset = field.align_offset(offset);
+        store(&mut buf, ty, v, offset)?;
+        offset += field.size;
+    }
+    Ok(buf)
+}
+
+/// Size and alignment of a type in linear memory
+#[derive(Clone, Copy, Debug)]
+struct Layout {
+    size: usize,
+    align: usize,
+}
+
+impl Layout {
+    const fn new(size: usize, align: usize) -> Self {
+        Self { size, align }
+    }
+
+    /// Rounds `offset` up to the alignment.
+    fn align_offset(self, offset: usize) -> usize {
+        offset.next_multiple_of(self.align)
+    }
+
+    /// Layout of a record or tuple with fields of types `tys`.
+    fn fields<'a>(tys: impl IntoIterator<Item = &'a Type>) -> anyhow::Result<Self> {
+        let mut size = 0;
+        let mut align = 1;
+        for ty in tys {
+            let field = Self::of(ty)?;
+            size = field.align_offset(size) + field.size;
+            align = align.max(field.align);
+        }
+        Ok(Self::new(size.next_multiple_of(align), align))
+    }
+
+    /// Layout of a variant with `n` cases with payloads of types `tys`,
+    /// and the offset of the payload.
+    fn variant(n: usize, tys: impl IntoIterator<Item = Type>) -> anyhow::Result<(Self, usize)> {
+        let disc = Self::discriminant(n);
+        let mut size = 0;
+        let mut align = 1;
+        for ty in tys {
+            let case = Self::of(&ty)?;
+            size = size.max(case.size);
+            align = align.max(case.align);
+        }
+        let offset = disc.size.next_multiple_of(align);
+        let align = align.max(disc.align);
+        Ok((
+            Self::new((offset + size).next_multiple_of(align), align),
+            offset,
+        ))
+    }
+
+    /// Layout of the discriminant of a variant with `n` cases.
+    fn discriminant(n: usize) -> Self {
+        if n <= 1 << 8 {
+            Self::new(1, 1)
+        } else if n <= 1 << 16 {
+            Self::new(2, 2)
+        } else {
+            Self::new(4, 4)
+        }
+    }
+
+    /// Layout of flags with `n` flags.
+    fn flags(n: usize) -> Self {
+        match n {
+            0 => Self::new(0, 1),
+            1..=8 => Self::new(1, 1),
+            9..=16 => Self::new(2, 2),
+            _ => Self::new(4 * n.div_ceil(32), 4),
+        }
+    }
+
+    fn of(ty: &Type) -> anyhow::Result<Self> {
+        match ty {
+            Type::Bool | Type::S8 | Type::U8 => Ok(Self::new(1, 1)),
+            Type::S16 | Type::U16 => Ok(Self::new(2, 2)),
+            Type::S32 | Type::U32 | Type::Float32 | Type::Char => Ok(Self::new(4, 4)),
+            Type::Own(..) | Type::Borrow(..) => Ok(Self::new(4, 4)),
+            Type::S64 | Type::U64 | Type::Float64 => Ok(Self::new(8, 8)),
+            Type::String | Type::List(..) => Ok(Self::new(8, 4)),
+            Type::Record(ty) => Self::fields(&ty.fields().map(|f| f.ty).collect::<Vec<_>>()),
+            Type::Tuple(ty) => Self::fields(&ty.types().collect::<Vec<_>>()),
+            Type::Variant(ty) => {
+                Self::variant(ty.cases().len(), ty.cases().filter_map(|c| c.ty)).map(|(l, _)| l)
+            }
+            Type::Enum(ty) => Ok(Self::discriminant(ty.names().len())),
+            Type::Option(ty) => Self::variant(2, [ty.ty()]).map(|(l, _)| l),
+            Type::Result(ty) => {
+                Self::variant(2, ty.ok().into_iter().chain(ty.err())).map(|(l, _)| l)
+            }
+            Type::Flags(ty) => Ok(Self::flags(ty.names().len())),
+            Type::Future(..) => bail!("futures not supported"),
+            Type::Stream(..) => bail!("streams not supported"),
+            Type::ErrorContext => bail!("error context not supported"),
+        }
+    }
+}
+
+fn load_bytes(buf: &[u8], offset: usize, n: usize) -> anyhow::Result<&[u8]> {
+    offset
+        .checked_add(n)
+        .and_then(|end| buf.get(offset..end))
+        .with_context(|| format!("{n} bytes at offset {offset} out of bounds"))
+}
+
+fn load_array<const N: usize>(buf: &[u8], offset: usize) -> anyhow::Result<[u8; N]> {
+    let bytes = load_bytes(buf, offset, N)?;
+    Ok(bytes.try_into().expect("length checked"))
+}
+
+fn load_u32(buf: &[u8], offset: usize) -> anyhow::Result<usize> {
+    let v = u32::from_le_bytes(load_array(buf, offset)?);
+    Ok(v.try_into().expect("u32 fits in usize"))
+}
+
+fn load_discriminant(buf: &[u8], offset: usize, n: usize) -> anyhow::Result<usize> {
+    let i = match Layout::discriminant(n).size {
+        1 => u8::from_le_bytes(load_array(buf, offset)?).into(),
+        2 => u16::from_le_bytes(load_array(buf, offset)?).into(),
+        _ => load_u32(buf, offset)?,
+    };
+    ensure!(i < n, "invalid discriminant `{i}`");
+    Ok(i)
+}
+
+/// Loads pointer and length of a string or list stored at `offset`, charging its size
+/// to `budget`.
+fn load_slice(
+    buf: &[u8],
+    budget: &mut usize,
+    offset: usize,
+    layout: Layout,
+) -> anyhow::Result<(usize, usize)> {
+    let ptr = load_u32(buf, offset)?;
+    let len = load_u32(buf, offset + 4)?;
+    ensure!(ptr % layout.align == 0, "unaligned pointer `{ptr}`");
+    let size = len
+        .checked_mul(layout.size)
+        .context("list size overflows")?;
+    load_bytes(buf, ptr, size)?;
+    *budget = len
+        .checked_mul(layout.size.max(1))
+        .and_then(|size| budget.checked_sub(size))
+        .context("strings and lists exceed the buffer size, pointers may not alias")?;
+    Ok((ptr, len))
+}
+
+fn load_payload(
+    buf: &[u8],
+    budget: &mut usize,
+    ty: Option<Type>,
+    offset: usize,
+) -> anyhow::Result<Option<Box<Val>>> {
+    let Some(ty) = ty else {
+        return Ok(None);
+    };
+    load(buf, budget, &ty, offset).map(|v| Some(Box::new(v)))
+}
+
+/// Lifts a value of type `ty` stored at `offset`, charging strings and lists to `budget`.
+fn load(buf: &[u8], budget: &mut usize, ty: &Type, offset: usize) -> anyhow::Result<Val> {
+    match ty {
+        Type::Bool => Ok(Val::Bool(load_array::<1>(buf, offset)? != [0])),
+        Type::S8 => Ok(Val::S8(i8::from_le_bytes(load_array(buf, offset)?))),
+        Type::U8 => Ok(Val::U8(u8::from_le_bytes(load_array(buf, offset)?))),
+        Type::S16 => Ok(Val::S16(i16::from_le_bytes(load_array(buf, offset)?))),
+        Type::U16 => Ok(Val::U16(u16::from_le_bytes(load_array(buf, offset)?))),
+        Type::S32 => Ok(Val::S32(i32::from_le_bytes(load_array(buf, offset)?))),
+        Type::U32 | Type::Own(..) | Type::Borrow(..) => {
+            Ok(Val::U32(u32::from_le_bytes(load_array(buf, offset)?)))
+        }
+        Type::S64 => Ok(Val::S64(i64::from_le_bytes(load_array(buf, offset)?))),
+        Type::U64 => Ok(Val::U64(u64::from_le_bytes(load_array(buf, offset)?))),
+        Type::Float32 => Ok(Val::Float32(f32::from_le_bytes(load_array(buf, offset)?))),
+        Type::Float64 => Ok(Val::Float64(f64::from_le_bytes(load_array(buf, offset)?))),
+        Type::Char => {
+            let v = u32::from_le_bytes(load_array(buf, offset)?);
+            let c = char::from_u32(v).with_context(|| format!("invalid char `{v:#x}`"))?;
+            Ok(Val::Char(c))
+        }
+        Type::String => {
+            let (ptr, len) = load_slice(buf, budget, offset, Layout::new(1, 1))?;
+            let s = str::from_utf8(&buf[ptr..ptr + len]).context("invalid UTF-8 string")?;
+            Ok(Val::String(s.into()))
+        }
+        Type::List(ty) => {
+            let ty = ty.ty();
+            let layout = Layout::of(&ty)?;
+            let (ptr, len) = load_slice(buf, budget, offset, layout)?;
+            let mut vs = Vec::with_capacity(len);
+            for i in 0..len {
+                vs.push(load(buf, budget, &ty, ptr + i * layout.size)?);
+            }
+            Ok(Val::List(vs))
+        }
+        Type::Record(ty) => {
+            let mut offset = offset;
+            let mut fields = Vec::with_capacity(ty.fields().len());
+            for types::Field { name, ty } in ty.fields() {
+                let layout = Layout::of(&ty)?;
+                offset = layout.align_offset(offset);
+                let v = load(buf, budget, &ty, offset)
+                    .with_context(|| format!("failed to decode field `{name}`"))?;
+                fields.push((name.into(), v));
+                offset += layout.size;
+            }
+            Ok(Val::Record(fields))
+        }
+        Type::Tuple(ty) => {
+            let mut offset = offset;
+            let mut vs = Vec::with_capacity(ty.types().len());
+            for ty in ty.types() {
+                let layout = Layout::of(&ty)?;
+                offset = layout.align_offset(offset);
+                vs.push(load(buf, budget, &ty, offset)?);
+                offset += layout.size;
+            }
+            Ok(Val::Tuple(vs))
+        }
+        Type::Variant(ty) => {
+            let (_, payload) = Layout::variant(ty.cases().len(), ty.cases().filter_map(|c| c.ty))?;
+            let i = load_discriminant(buf, offset, ty.cases().len())?;
+            let types::Case { name, ty } = ty.cases().nth(i).expect("discriminant checked");
+            let v = load_payload(buf, budget, ty, offset + payload)
+                .with_context(|| format!("failed to decode case `{name}`"))?;
+            Ok(Val::Variant(name.into(), v))
+        }
+        Type::Enum(ty) => {
+            let i = load_discriminant(buf, offset, ty.names().len())?;
+            Ok(Val::Enum(
+                ty.names().nth(i).expect("discriminant checked").into(),
+            ))
+        }
+        Type::Option(ty) => {
+            let ty = ty.ty();
+            let (_, payload) = Layout::variant(2, [ty.clone()])?;
+            match load_discriminant(buf, offset, 2)? {
+                0 => Ok(Val::Option(None)),
+                _ => {
+                    let v = load(buf, budget, &ty, offset + payload)?;
+                    Ok(Val::Option(Some(Box::new(v))))
+                }
+            }
+        }
+        Type::Result(ty) => {
+            let (_, payload) = Layout::variant(2, ty.ok().into_iter().chain(ty.err()))?;
+            match load_discriminant(buf, offset, 2)? {
+                0 => Ok(Val::Result(Ok(load_payload(
+                    buf,
+                    budget,
+                    ty.ok(),
+                    offset + payload,
+                )?))),
+                _ => Ok(Val::Result(Err(load_payload(
+                    buf,
+                    budget,
+                    ty.err(),
+                    offset + payload,
+                )?))),
+            }
+        }
+        Type::Flags(ty) => {
+            let n = ty.names().len();
+            let bits = load_bytes(buf, offset, Layout::flags(n).size)?;
+            // Like in the canonical ABI, bits not corresponding to any flag are ignored
+            let flags = ty
+                .names()
+                .enumerate()
+                .filter(|(i, _)| bits[i / 8] & (1 << (i % 8)) != 0)
+                .map(|(_, name)| name.into())
+                .collect();
+            Ok(Val::Flags(flags))
+        }
+        Type::Future(..) => bail!("futures not supported"),
+        Type::Stream(..) => bail!("streams not supported"),
+        Type::ErrorContext => bail!("error context not supported"),
+    }
+}
+
+fn store_bytes(buf: &mut [u8], offset: usize, bytes: &[u8]) {
+    buf[offset..offset + bytes.len()].copy_from_slice(bytes);
+}
+
+fn store_u32(buf: &mut [u8], offset: usize, v: usize) -> anyhow::Result<()> {
+    let v = u32::try_from(v).context("buffer exceeds 4 GiB")?;
+    store_bytes(buf, offset, &v.to_le_bytes());
+    Ok(())
+}
+
+fn store_discriminant(buf: &mut [u8], offset: usize, n: usize, i: usize) {
+    match Layout::discriminant(n).size {
+        1 => buf[offset] = i as u8,
+        2 => store_bytes(buf, offset, &(i as u16).to_le_bytes()),
+        _ => store_bytes(buf, offset, &(i as u32).to_le_bytes()),
+    }
+}
+
+/// Appends `layout.size * n` zeroed bytes aligned to `layout.align` and returns their offset.
+fn alloc(buf: &mut Vec<u8>, layout: Layout, n: usize) -> anyhow::Result<usize> {
+    let ptr = layout.align_offset(buf.len());
+    let size = layout.size.checked_mul(n).context("list size overflows")?;
+    buf.resize(ptr + size, 0);
+    Ok(ptr)
+}
+
+fn store_payload(
+    buf: &mut Vec<u8>,
+    ty: Option<Type>,
+    v: Option<&Val>,
+    offset: usize,
+) -> anyhow::Result<()> {
+    match (ty, v) {
+        (Some(ty), Some(v)) => store(buf, &ty, v, offset),
+        (None, None) => Ok(()),
+        (Some(..), None) => bail!("missing payload"),
+        (None, Some(..)) => bail!("unexpected payload"),
+    }
+}
+
+fn store(buf: &mut Vec<u8>, ty: &Type, v: &Val, offset: usize) -> anyhow::Result<()> {
+    match (ty, v) {
+        (Type::Bool, Val::Bool(v)) => buf[offset] = (*v).into(),
+        (Type::S8, Val::S8(v)) => store_bytes(buf, offset, &v.to_le_bytes()),
+        (Type::U8, Val::U8(v)) => buf[offset] = *v,
+        (Type::S16, Val::S16(v)) => store_bytes(buf, offset, &v.to_le_bytes()),
+        (Type::U16, Val::U16(v)) => store_bytes(buf, offset, &v.to_le_bytes()),
+        (Type::S32, Val::S32(v)) => store_bytes(buf, offset, &v.to_le_bytes()),
+        (Type::U32 | Type::Own(..) | Type::Borrow(..), Val::U32(v)) => {
+            store_bytes(buf, offset, &v.to_le_bytes())
+        }
+        (Type::S64, Val::S64(v)) => store_bytes(buf, offset, &v.to_le_bytes()),
+        (Type::U64, Val::U64(v)) => store_bytes(buf, offset, &v.to_le_bytes()),
+        (Type::Float32, Val::Float32(v)) => store_bytes(buf, offset, &v.to_le_bytes()),
+        (Type::Float64, Val::Float64(v)) => store_bytes(buf, offset, &v.to_le_bytes()),
+        (Type::Char, Val::Char(v)) => store_bytes(buf, offset, &u32::from(*v).to_le_bytes()),
+        (Type::String, Val::String(v)) => {
+            let ptr = alloc(buf, Layout::new(1, 1), v.len())?;
+            store_bytes(buf, ptr, v.as_bytes());
+            store_u32(buf, offset, ptr)?;
+            store_u32(buf, offset + 4, v.len())?;
+        }
+        (Type::List(ty), Val::List(vs)) => {
+            let ty = ty.ty();
+            let layout = Layout::of(&ty)?;
+            let ptr = alloc(buf, layout, vs.len())?;
+            for (i, v) in vs.iter().enumerate() {
+                store(buf, &ty, v, ptr + i * layout.size)?;
+            }
+            store_u32(buf, offset, ptr)?;
+            store_u32(buf, offset + 4, vs.len())?;
+        }
+        (Type::Record(ty), Val::Record(vs)) => {
+            ensure!(ty.fields().len() == vs.len(), "record field count mismatch");
+            let mut offset = offset;
+            for (types::Field { name, ty }, (_, v)) in ty.fields().zip(vs) {
+                let layout = Layout::of(&ty)?;
+                offset = layout.align_offset(offset);
+                store(buf, &ty, v, offset)
+                    .with_context(|| format!("failed to encode field `{name}`"))?;
+                offset += layout.size;
+            }
+        }
+        (Type::Tuple(ty), Val::Tuple(vs)) => {
+            ensure!(ty.types().len() == vs.len(), "tuple length mismatch");
+            let mut offset = offset;
+            for (ty, v) in ty.types().zip(vs) {
+                let layout = Layout::of(&ty)?;
+                offset = layout.align_offset(offset);
+                store(buf, &ty, v, offset)?;
+                offset += layout.size;
+            }
+        }
+        (Type::Variant(ty), Val::Variant(name, v)) => {
+            let n = ty.cases().len();
+            let (_, payload) = Layout::variant(n, ty.cases().filter_map(|c| c.ty))?;
+            let Some(i) = ty.cases().position(|case| case.name == name) else {
+                bail!("unknown case `{name}`");
+            };
+            store_discriminant(buf, offset, n, i);
+            let case = ty.cases().nth(i).expect("case found");
+            store_payload(buf, case.ty, v.as_deref(), offset + payload)
+                .with_context(|| format!("failed to encode case `{name}`"))?;
+        }
+        (Type::Enum(ty), Val::Enum(name)) => {
+            let Some(i) = ty.names().position(|case| case == name) else {
+                bail!("unknown case `{name}`");
+            };
+            store_discriminant(buf, offset, ty.names().len(), i);
+        }
+        (Type::Option(..), Val::Option(None)) => store_discriminant(buf, offset, 2, 0),
+        (Type::Option(ty), Val::Option(Some(v))) => {
+            let ty = ty.ty();
+            let (_, payload) = Layout::variant(2, [ty.clone()])?;
+            store_discriminant(buf, offset, 2, 1);
+            store(buf, &ty, v, offset + payload)?;
+        }
+        (Type::Result(ty), Val::Result(v)) => {
+            let (_, payload) = Layout::variant(2, ty.ok().into_iter().chain(ty.err()))?;
+            match v {
+                Ok(v) => {
+                    store_discriminant(buf, offset, 2, 0);
+                    store_payload(buf, ty.ok(), v.as_deref(), offset + payload)?;
+                }
+                Err(v) => {
+                    store_discriminant(buf, offset, 2, 1);
+                    store_payload(buf, ty.err(), v.as_deref(), offset + payload)?;
+                }
+            }
+        }
+        (Type::Flags(ty), Val::Flags(flags)) => {
+            for flag in flags {
+                let Some(i) = ty.names().position(|name| name == flag) else {
+                    bail!("unknown flag `{flag}`");
+                };
+                buf[offset + i / 8] |= 1 << (i % 8);
+            }
+        }
+        _ => bail!("value does not match type"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codecs::tests::{ty, u8_u64_func};
+
+    #[track_caller]
+    fn assert_layout(ty: &Type, v: Val, buf: &[u8]) {
+        assert_eq!(encode(ty, &v).expect("failed to encode"), buf);
+        assert_eq!(decode(ty, buf).expect("failed to decode"), v);
+    }
+
+    #[test]
+    fn records_are_aligned() {
+        assert_layout(
+            &ty(r#"(record (field "a" u8) (field "b" u32) (field "c" u16))"#),
+            Val::Record(vec![
+                ("a".into(), Val::U8(1)),
+                ("b".into(), Val::U32(2)),
+                ("c".into(), Val::U16(3)),
+            ]),
+            &[1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0],
+        );
+    }
+
+    #[test]
+    fn strings_and_lists_point_into_buffer() {
+        assert_layout(
+            &ty("(tuple string (list u16))"),
+            Val::Tuple(vec![
+                Val::String("hi".into()),
+                Val::List(vec![Val::U16(1), Val::U16(2)]),
+            ]),
+            &[
+                16, 0, 0, 0, 2, 0, 0, 0, // string
+                18, 0, 0, 0, 2, 0, 0, 0, // list
+                b'h', b'i', 1, 0, 2, 0,
+            ],
+        );
+        assert!(decode(&ty("string"), &[8, 0, 0, 0, 1, 0, 0, 0]).is_err());
+        assert!(decode(&ty("(list u16)"), &[9, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn aliased_pointers_are_limited() {
+        let ty = ty("(list string)");
+        let v = Val::List(vec![Val::String("abcdefgh".into()); 3]);
+        let buf = encode(&ty, &v).expect("failed to encode");
+        assert_eq!(decode(&ty, &buf).expect("failed to decode"), v);
+
+        // All elements point at the same string
+        let mut buf = vec![8, 0, 0, 0, 3, 0, 0, 0];
+        for _ in 0..3 {
+            buf.extend_from_slice(&[32, 0, 0, 0, 8, 0, 0, 0]);
+        }
+        buf.extend_from_slice(b"abcdefgh");
+        assert!(decode(&ty, &buf).is_err());
+    }
+
+    #[test]
+    fn variants_align_payload() {
+        let ty = ty("(result u64 (error u8))");
+        assert_layout(
+            &ty,
+            Val::Result(Ok(Some(Box::new(Val::U64(5))))),
+            &[0, 0, 0, 0, 0, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0],
+        );
+        assert!(decode(&ty, &[2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn params_are_a_tuple() {
+        let ty = u8_u64_func();
+        let buf = encode_params(&ty, &[Val::U8(1), Val::U64(2)]).expect("failed to encode");
+        assert_eq!(buf, [1, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0]);
+        let params = Raw.decode_params(&ty, &buf).expect("failed to decode");
+        assert_eq!(params, [Val::U8(1), Val::U64(2)]);
+    }
+}
@@ -0,0 +1,103 @@
+//! Wasmtime engine configuration profiles
+
+use wasmtime::Config;
+
+/// Maximum size of the Wasm stack when executing asynchronously
+const ASYNC_MAX_WASM_STACK: usize = 1 << 20;
+
+/// Size of the native stack allocated for each asynchronous execution,
+/// must be larger than [`ASYNC_MAX_WASM_STACK`]
+const ASYNC_STACK_SIZE: usize = 2 << 20;
+
+/// Returns a [`Config`] equivalent to the one used by `nearcore`, which uses the pooling
+/// allocator, copy-on-write memory images and signals-based bounds checks.
+///
+/// The returned [`Config`] does not support async execution, see [`with_async_support`].
+// NOTE: This is adapted from current nearcore `main`
+pub fn new_wasmtime_config(
+    max_memory_pages: u32,
+    max_tables_per_contract: u32,
+    max_elements_per_contract_table: usize,
+) -> Config {
+    /// The maximum amount of concurrent calls this engine can handle.
+    /// If this limit is reached, invocations will block until an execution slot is available.
+    ///
+    /// Wasmtime will use this value to pre-allocate and pool resources internally.
+    /// Wasmtime defaults to `1_000`
+    const MAX_CONCURRENCY: u32 = 1_000;
+
+    /// Value used for [PoolingAllocationConfig::decommit_batch_size]
+    ///
+    /// Wasmtime defaults to `1`
+    const DECOMMIT_BATCH_SIZE: usize = MAX_CONCURRENCY as usize / 2;
+
+    /// Guest page size, in bytes
+    const GUEST_PAGE_SIZE: usize = 1 << 16;
+
+    fn guest_memory_size(pages: u32) -> Option<usize> {
+        let pages = usize::try_from(pages).ok()?;
+        pages.checked_mul(GUEST_PAGE_SIZE)
+    }
+
+    let max_memory_size = guest_memory_size(max_memory_pages).unwrap_or(usize::MAX);
+    let max_tables = MAX_CONCURRENCY.saturating_mul(max_tables_per_contract);
+
+    let mut pooling_config = wasmtime::PoolingAllocationConfig::default();
+    pooling_config
+        .decommit_batch_size(DECOMMIT_BATCH_SIZE)
+        .max_memory_size(max_memory_size)
+        .table_elements(max_elements_per_contract_table)
+        .total_component_instances(MAX_CONCURRENCY)
+        .total_core_instances(MAX_CONCURRENCY)
+        .total_memories(MAX_CONCURRENCY)
+        .total_tables(max_tables)
+        .max_memories_per_module(1)
+        .max_tables_per_module(max_tables_per_contract)
+        .table_keep_resident(max_elements_per_contract_table);
+
+    let mut config = Config::default();
+    config
+        .allocation_strategy(wasmtime::InstanceAllocationStrategy::Pooling(
+            pooling_config,
+        ))
+        // From official documentation:
+        // > Note that systems loading many modules may wish to disable this
+        // > configuration option instead of leaving it on-by-default.
+        // > Some platforms exhibit quadratic behavior when registering/unregistering
+        // > unwinding information which can greatly slow down the module loading/unloading process.
+        // https://docs.rs/wasmtime/latest/wasmtime/struct.Config.html#method.native_unwind_info
+        .native_unwind_info(false)
+        .wasm_backtrace(false)
+        .wasm_backtrace_details(wasmtime::WasmBacktraceDetails::Disable)
+        // Enable copy-on-write heap images.
+        .memory_init_cow(true)
+        // Wasm stack metering is implemented by instrumentation, we don't want wasmtime to trap before that
+        .max_wasm_stack(1024 * 1024 * 1024)
+        // Enable the Cranelift optimizing compiler.
+        .strategy(wasmtime::Strategy::Cranelift)
+        // Enable signals-based traps. This is required to elide explicit bounds-checking.
+        .signals_based_traps(true)
+        // Configure linear memories such that explicit bounds-checking can be elided.
+        .force_memory_init_memfd(true)
+        .memory_guaranteed_dense_image_size(max_memory_size.try_into().unwrap_or(u64::MAX))
+        .guard_before_linear_memory(false)
+        .memory_guard_size(0)
+        .memory_may_move(false)
+        .memory_reservation(max_memory_size.try_into().unwrap_or(u64::MAX))
+        .memory_reservation_for_growth(0)
+        .compiler_inlining(true)
+        .cranelift_nan_canonicalization(true)
+        .wasm_wide_arithmetic(true);
+    config
+}
+
+/// Enables async support in `config`.
+///
+/// Async execution runs on fibers, which stacks must fit the whole Wasm stack,
+/// therefore this also limits the maximum Wasm stack size.
+pub fn with_async_support(config: &mut Config) -> &mut Config {
+    config
+        .async_support(true)
+        .max_wasm_stack(ASYNC_MAX_WASM_STACK)
+        .async_stack_size(ASYNC_STACK_SIZE)
+}
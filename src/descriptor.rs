@@ -0,0 +1,186 @@
+//! Flattened type descriptors, see `near-cm:host/descriptor` interface
+
+use std::collections::HashMap;
+
+use anyhow::{Context as _, bail};
+use wasmtime::component::{Type, types};
+
+pub(crate) mod bindings {
+    wasmtime::component::bindgen!({
+        path: "host/wit",
+        world: "descriptor-types",
+        additional_derives: [Hash, Eq, PartialEq],
+    });
+}
+
+pub use bindings::near_cm::host::descriptor::{Node, NodeIndex, TypeDescriptor};
+
+/// [`TypeDescriptor`] builder, which deduplicates identical nodes.
+#[derive(Debug, Default)]
+pub struct Builder {
+    nodes: Vec<Node>,
+    indices: HashMap<Node, NodeIndex>,
+    /// Index returned by the last [`Builder::push`]
+    last: Option<NodeIndex>,
+}
+
+impl Builder {
+    /// Appends `node`, unless an identical node is already present,
+    /// and returns its index.
+    pub fn push(&mut self, node: Node) -> NodeIndex {
+        if let Some(i) = self.indices.get(&node) {
+            self.last = Some(*i);
+            return *i;
+        }
+        let i = self
+            .nodes
+            .len()
+            .try_into()
+            .expect("too many type descriptor nodes");
+        self.nodes.push(node.clone());
+        self.indices.insert(node, i);
+        self.last = Some(i);
+        i
+    }
+
+    /// Appends nodes describing `ty` and returns the index of the root node.
+    pub fn push_type(&mut self, ty: &Type) -> anyhow::Result<NodeIndex> {
+        let node = match ty {
+            Type::Bool => Node::Bool,
+            Type::S8 => Node::S8,
+            Type::U8 => Node::U8,
+            Type::S16 => Node::S16,
+            Type::U16 => Node::U16,
+            Type::S32 => Node::S32,
+            // Resource handles are passed as `u32` handle IDs
+            Type::U32 | Type::Own(..) | Type::Borrow(..) => Node::U32,
+            Type::S64 => Node::S64,
+            Type::U64 => Node::U64,
+            Type::Float32 => Node::F32,
+            Type::Float64 => Node::F64,
+            Type::Char => Node::Char,
+            Type::String => Node::String,
+            Type::List(ty) => Node::List(self.push_type(&ty.ty())?),
+            Type::Record(ty) => {
+                let mut fields = Vec::with_capacity(ty.fields().len());
+                for types::Field { name, ty } in ty.fields() {
+                    let ty = self
+                        .push_type(&ty)
+                        .with_context(|| format!("failed to describe field `{name}`"))?;
+                    fields.push((name.into(), ty));
+                }
+                Node::Record(fields)
+            }
+            Type::Tuple(ty) => {
+                let mut tys = Vec::with_capacity(ty.types().len());
+                for ty in ty.types() {
+                    tys.push(self.push_type(&ty)?);
+                }
+                Node::Tuple(tys)
+            }
+            Type::Variant(ty) => {
+                let mut cases = Vec::with_capacity(ty.cases().len());
+                for types::Case { name, ty } in ty.cases() {
+                    let ty = ty
+                        .map(|ty| self.push_type(&ty))
+                        .transpose()
+                        .with_context(|| format!("failed to describe case `{name}`"))?;
+                    cases.push((name.into(), ty));
+                }
+                Node::Variant(cases)
+            }
+            Type::Enum(ty) => Node::Enum(ty.names().map(Into::into).collect()),
+            Type::Option(ty) => Node::Option(self.push_type(&ty.ty())?),
+            Type::Result(ty) => {
+                let ok = ty.ok().map(|ty| self.push_type(&ty)).transpose()?;
+                let err = ty.err().map(|ty| self.push_type(&ty)).transpose()?;
+                Node::Result((ok, err))
+            }
+            Type::Flags(ty) => Node::Flags(ty.names().map(Into::into).collect()),
+            Type::Future(..) => bail!("futures not supported"),
+            Type::Stream(..) => bail!("streams not supported"),
+            Type::ErrorContext => bail!("error context not supported"),
+        };
+        Ok(self.push(node))
+    }
+
+    /// Returns the [`TypeDescriptor`] of the type described by the last pushed node.
+    pub fn finish(mut self) -> TypeDescriptor {
+        if let Some(last) = self.last {
+            // The root must be the last node, even if it was deduplicated
+            if last as usize != self.nodes.len() - 1 {
+                let node = self.nodes[last as usize].clone();
+                self.nodes.push(node);
+            }
+        }
+        TypeDescriptor { nodes: self.nodes }
+    }
+}
+
+impl TypeDescriptor {
+    /// Returns the descriptor of `ty`.
+    pub fn from_type(ty: &Type) -> anyhow::Result<Self> {
+        let mut b = Builder::default();
+        b.push_type(ty)?;
+        Ok(b.finish())
+    }
+
+    /// Returns the descriptor of the tuple of parameters of function type `ty`.
+    pub fn from_params(ty: &types::ComponentFunc) -> anyhow::Result<Self> {
+        let mut b = Builder::default();
+        let mut tys = Vec::with_capacity(ty.params().len());
+        for (name, ty) in ty.params() {
+            let ty = b
+                .push_type(&ty)
+                .with_context(|| format!("failed to describe param `{name}`"))?;
+            tys.push(ty);
+        }
+        b.push(Node::Tuple(tys));
+        Ok(b.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_nodes_are_deduplicated() {
+        let mut b = Builder::default();
+        let a = b.push(Node::U32);
+        let list = b.push(Node::List(a));
+        assert_eq!(b.push(Node::U32), a);
+        assert_eq!(b.push(Node::List(a)), list);
+        let s = b.push(Node::String);
+        b.push(Node::Record(vec![
+            ("a".into(), list),
+            ("b".into(), list),
+            ("c".into(), s),
+        ]));
+        assert_eq!(
+            b.finish().nodes,
+            [
+                Node::U32,
+                Node::List(0),
+                Node::String,
+                Node::Record(vec![("a".into(), 1), ("b".into(), 1), ("c".into(), 2)]),
+            ]
+        );
+    }
+
+    #[test]
+    fn root_is_last() {
+        let mut b = Builder::default();
+        let a = b.push(Node::U32);
+        b.push(Node::Option(a));
+        // The root was deduplicated to a node preceding its parent
+        b.push(Node::U32);
+        assert_eq!(b.finish().nodes, [Node::U32, Node::Option(0), Node::U32]);
+
+        let mut b = Builder::default();
+        let a = b.push(Node::U32);
+        b.push(Node::Tuple(vec![a, a]));
+        assert_eq!(b.finish().nodes, [Node::U32, Node::Tuple(vec![0, 0])]);
+        assert_eq!(Builder::default().finish().nodes, []);
+    }
+}
@@ -0,0 +1,207 @@
+//! Events emitted by contracts using the `near-cm:host/events` interface, following the
+//! [NEP-297](https://nomicon.io/Standards/EventsFormat) event format
+//!
+//! Events emitted during an invocation are buffered and published to [`Events`]
+//! subscribers only once the invocation is committed. Events emitted by a cross-contract
+//! call, which fails, are discarded along with its state changes.
+
+use core::fmt::Write as _;
+
+use std::sync::Arc;
+
+use tokio::sync::broadcast;
+use wasmtime::StoreContextMut;
+use wasmtime::component::Linker;
+
+/// Default number of events buffered for subscribers, which are lagging behind
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+/// Event emitted by a contract
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    /// Name of the emitting contract
+    pub contract: Arc<str>,
+    /// Name of the standard, e.g. `nep171`
+    pub standard: String,
+    /// Version of the standard, e.g. `1.0.0`
+    pub version: String,
+    /// Name of the event, e.g. `nft_mint`, which is the topic of the event
+    pub event: String,
+    /// Event data encoded as a JSON value
+    pub data: String,
+}
+
+impl Event {
+    /// Returns the NEP-297 JSON object of the event, extended by the `contract` field.
+    pub fn to_json(&self) -> String {
+        let Self {
+            contract,
+            standard,
+            version,
+            event,
+            data,
+        } = self;
+        let mut out = String::with_capacity(64 + data.len());
+        out.push_str(r#"{"contract":"#);
+        push_json_string(&mut out, contract);
+        out.push_str(r#","standard":"#);
+        push_json_string(&mut out, standard);
+        out.push_str(r#","version":"#);
+        push_json_string(&mut out, version);
+        out.push_str(r#","event":"#);
+        push_json_string(&mut out, event);
+        out.push_str(r#","data":"#);
+        out.push_str(data);
+        out.push('}');
+        out
+    }
+}
+
+/// Appends `s` to `out` as a JSON string.
+fn push_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str(r#"\""#),
+            '\\' => out.push_str(r"\\"),
+            '\n' => out.push_str(r"\n"),
+            '\r' => out.push_str(r"\r"),
+            '\t' => out.push_str(r"\t"),
+            c if c.is_control() => {
+                write!(out, r"\u{:04x}", u32::from(c)).expect("failed to write to string");
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Subscription filter, unset fields match any event
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Filter {
+    /// Name of the emitting contract
+    pub contract: Option<Box<str>>,
+    /// Topic, i.e. [`Event::event`]
+    pub topic: Option<Box<str>>,
+}
+
+impl Filter {
+    /// Returns `true` if `event` matches the filter.
+    pub fn matches(&self, event: &Event) -> bool {
+        self.contract
+            .as_deref()
+            .is_none_or(|contract| *event.contract == *contract)
+            && self
+                .topic
+                .as_deref()
+                .is_none_or(|topic| event.event == topic)
+    }
+}
+
+/// Publisher of committed events to subscribers
+///
+/// Subscribers, which lag behind by more than the capacity, miss the oldest events.
+pub struct Events(broadcast::Sender<Arc<Event>>);
+
+impl Events {
+    /// Creates a publisher buffering at most `capacity` events for lagging subscribers.
+    pub fn new(capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity.max(1));
+        Self(tx)
+    }
+
+    /// Subscribes to all events published from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<Event>> {
+        self.0.subscribe()
+    }
+
+    /// Publishes committed `events` in order.
+    pub fn publish(&self, events: impl IntoIterator<Item = Event>) {
+        for event in events {
+            // Events are dropped if there are no subscribers
+            let _ = self.0.send(Arc::new(event));
+        }
+    }
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+/// Defines `near-cm:host/events` in `linker`, attributing all events to `contract`.
+///
+/// Emitted events are appended to the buffer returned by `get`, emitting fails if it
+/// returns `None`, e.g. in view calls.
+pub fn add_to_linker<T: 'static>(
+    linker: &mut Linker<T>,
+    contract: &str,
+    get: fn(&mut T) -> Option<&mut Vec<Event>>,
+) -> wasmtime::Result<()> {
+    let contract = Arc::<str>::from(contract);
+    let mut linker = linker.instance("near-cm:host/events@0.1.0")?;
+    linker.func_wrap(
+        "emit",
+        move |mut store: StoreContextMut<'_, T>,
+              (standard, version, event, data): (String, String, String, String)| {
+            if let Err(err) = serde_json::from_str::<serde::de::IgnoredAny>(&data) {
+                anyhow::bail!("data of event `{event}` is not valid JSON: {err}");
+            }
+            let Some(events) = get(store.data_mut()) else {
+                anyhow::bail!("events cannot be emitted in view calls");
+            };
+            events.push(Event {
+                contract: Arc::clone(&contract),
+                standard,
+                version,
+                event,
+                data,
+            });
+            Ok(())
+        },
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_nep297_json() {
+        let event = Event {
+            contract: "nft".into(),
+            standard: "nep171".into(),
+            version: "1.0.0".into(),
+            event: "nft_\"mint\"\n".into(),
+            data: r#"[{"owner_id":"alice","token_ids":["1"]}]"#.into(),
+        };
+        assert_eq!(
+            event.to_json(),
+            r#"{"contract":"nft","standard":"nep171","version":"1.0.0","event":"nft_\"mint\"\n","data":[{"owner_id":"alice","token_ids":["1"]}]}"#
+        );
+        assert!(Filter::default().matches(&event));
+        assert!(
+            Filter {
+                contract: Some("nft".into()),
+                topic: Some("nft_\"mint\"\n".into()),
+            }
+            .matches(&event)
+        );
+        assert!(
+            !Filter {
+                contract: Some("nft".into()),
+                topic: Some("nft_burn".into()),
+            }
+            .matches(&event)
+        );
+        assert!(
+            !Filter {
+                contract: Some("ft".into()),
+                topic: None,
+            }
+            .matches(&event)
+        );
+    }
+}
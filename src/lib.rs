@@ -0,0 +1,41 @@
+//! NEAR contract runtime based on the Wasm component model
+
+mod bindings {
+    wasmtime::component::bindgen!({
+        world: "codec",
+        additional_derives: [Clone],
+        with: {
+            "near-cm:host/descriptor": crate::descriptor::bindings::near_cm::host::descriptor,
+        },
+        exports: {
+            default: async,
+        }
+    });
+}
+
+mod codec;
+pub mod codecs;
+pub mod config;
+pub mod descriptor;
+pub mod events;
+pub mod limits;
+pub mod log;
+pub mod metrics;
+#[cfg(feature = "otlp")]
+pub mod otlp;
+pub mod panic;
+pub mod print;
+mod resources;
+#[cfg(feature = "rpc")]
+pub mod rpc;
+mod runtime;
+pub mod sessions;
+pub mod storage;
+pub mod websocket;
+
+pub use runtime::{
+    BatchTooLarge, BodyTooLarge, ContractOptions, DEFAULT_BATCH_CONCURRENCY, DEFAULT_MAX_BATCH_GAS,
+    DEFAULT_MAX_BATCH_LEN, DEFAULT_MAX_BODY_SIZE, DEFAULT_MAX_GAS,
+    DEFAULT_MAX_POOLED_TABLE_ELEMENTS, DEFAULT_MAX_POOLED_USES, DEFAULT_POOL_SIZE, DEFAULT_TIMEOUT,
+    Error, InvokeOptions, Options, Outcome, Runtime, Session, SessionRequired,
+};
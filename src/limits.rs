@@ -0,0 +1,302 @@
+use core::fmt;
+
+/// Resource limits applied to every [`Store`](wasmtime::Store).
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    /// Maximum size of a linear memory in bytes
+    pub memory_size: usize,
+    /// Maximum number of tables in a store
+    pub tables: usize,
+    /// Maximum number of elements in a table
+    pub table_elements: usize,
+    /// Maximum number of instances in a store
+    pub instances: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            // 2048 Wasm pages, same as `nearcore`
+            memory_size: 2_048 << 16,
+            // Each component consists of multiple core instances and tables
+            tables: 100,
+            table_elements: 10_000,
+            instances: 100,
+        }
+    }
+}
+
+/// Error returned on attempt to grow a resource beyond its [`Limits`]
+/// or to instantiate more instances or tables than allowed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LimitExceeded {
+    MemorySize { desired: usize, maximum: usize },
+    TableElements { desired: usize, maximum: usize },
+    Instances { maximum: usize },
+    Tables { maximum: usize },
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MemorySize { desired, maximum } => write!(
+                f,
+                "memory size of {desired} bytes exceeds the limit of {maximum} bytes"
+            ),
+            Self::TableElements { desired, maximum } => write!(
+                f,
+                "table size of {desired} elements exceeds the limit of {maximum} elements"
+            ),
+            Self::Instances { maximum } => {
+                write!(f, "number of instances exceeds the limit of {maximum}")
+            }
+            Self::Tables { maximum } => {
+                write!(f, "number of tables exceeds the limit of {maximum}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+impl wasmtime::ResourceLimiter for Limits {
+    fn memory_growing(
+        &mut self,
+        _current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> wasmtime::Result<bool> {
+        if desired > self.memory_size {
+            return Err(LimitExceeded::MemorySize {
+                desired,
+                maximum: self.memory_size,
+            }
+            .into());
+        }
+        Ok(true)
+    }
+
+    fn table_growing(
+        &mut self,
+        _current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> wasmtime::Result<bool> {
+        if desired > self.table_elements {
+            return Err(LimitExceeded::TableElements {
+                desired,
+                maximum: self.table_elements,
+            }
+            .into());
+        }
+        Ok(true)
+    }
+
+    fn instances(&self) -> usize {
+        self.instances
+    }
+
+    fn tables(&self) -> usize {
+        self.tables
+    }
+}
+
+/// [`wasmtime::ResourceLimiter`] enforcing [`Limits`], which tracks the largest sizes
+/// of linear memories and tables in the store.
+#[derive(Clone, Copy, Debug)]
+pub struct Limiter {
+    limits: Limits,
+    memory_high_water: usize,
+    table_high_water: usize,
+}
+
+impl Limiter {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            memory_high_water: 0,
+            table_high_water: 0,
+        }
+    }
+
+    /// Returns the largest size of a linear memory in the store in bytes.
+    pub fn memory_high_water(&self) -> usize {
+        self.memory_high_water
+    }
+
+    /// Returns the largest number of elements of a table in the store.
+    pub fn table_high_water(&self) -> usize {
+        self.table_high_water
+    }
+
+    /// Returns error `err` of a failed instantiation with [`LimitExceeded`] attached,
+    /// if it was caused by the number of instances or tables exceeding the limits.
+    ///
+    /// Wasmtime checks these numbers on instantiation without consulting the limiter
+    /// and reports violations as untyped errors.
+    pub fn instantiation_error(&self, err: anyhow::Error) -> anyhow::Error {
+        let exceeded = err.chain().find_map(|err| {
+            let err = err.to_string();
+            let desc = err.strip_prefix("resource limit exceeded: ")?;
+            if desc.starts_with("instance count too high") {
+                Some(LimitExceeded::Instances {
+                    maximum: self.limits.instances,
+                })
+            } else if desc.starts_with("table count too high") {
+                Some(LimitExceeded::Tables {
+                    maximum: self.limits.tables,
+                })
+            } else {
+                None
+            }
+        });
+        match exceeded {
+            Some(exceeded) => err.context(exceeded),
+            None => err,
+        }
+    }
+}
+
+impl wasmtime::ResourceLimiter for Limiter {
+    fn memory_growing(
+        &mut self,
+        current: usize,
+        desired: usize,
+        maximum: Option<usize>,
+    ) -> wasmtime::Result<bool> {
+        let ok = self.limits.memory_growing(current, desired, maximum)?;
+        if ok {
+            self.memory_high_water = self.memory_high_water.max(desired);
+        }
+        Ok(ok)
+    }
+
+    fn table_growing(
+        &mut self,
+        current: usize,
+        desired: usize,
+        maximum: Option<usize>,
+    ) -> wasmtime::Result<bool> {
+        let ok = self.limits.table_growing(current, desired, maximum)?;
+        if ok {
+            self.table_high_water = self.table_high_water.max(desired);
+        }
+        Ok(ok)
+    }
+
+    fn instances(&self) -> usize {
+        self.limits.instances()
+    }
+
+    fn tables(&self) -> usize {
+        self.limits.tables()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasmtime::{Engine, Instance, Module, Store};
+
+    use super::*;
+
+    fn store(engine: &Engine, limits: Limits) -> Store<Limiter> {
+        let mut store = Store::new(engine, Limiter::new(limits));
+        store.limiter(|limiter| limiter);
+        store
+    }
+
+    fn module(engine: &Engine, wat: &str) -> Module {
+        Module::new(engine, wat).expect("failed to compile module")
+    }
+
+    fn exceeded(err: &anyhow::Error) -> Option<LimitExceeded> {
+        err.downcast_ref().copied()
+    }
+
+    #[test]
+    fn growing_beyond_limits_fails() {
+        let engine = Engine::default();
+        let limits = Limits {
+            memory_size: 2 << 16,
+            table_elements: 10,
+            ..Limits::default()
+        };
+        let module = module(
+            &engine,
+            r#"(module
+                (memory 1)
+                (table 1 funcref)
+                (func (export "grow-memory") (param i32) (result i32)
+                    (memory.grow (local.get 0)))
+                (func (export "grow-table") (param i32) (result i32)
+                    (table.grow (ref.null func) (local.get 0))))"#,
+        );
+        let mut store = store(&engine, limits);
+        let instance = Instance::new(&mut store, &module, &[]).expect("failed to instantiate");
+        let grow_memory = instance
+            .get_typed_func::<u32, i32>(&mut store, "grow-memory")
+            .expect("function not found");
+        let grow_table = instance
+            .get_typed_func::<u32, i32>(&mut store, "grow-table")
+            .expect("function not found");
+
+        assert_eq!(grow_memory.call(&mut store, 1).expect("failed to grow"), 1);
+        assert_eq!(store.data().memory_high_water(), 2 << 16);
+        let err = grow_memory.call(&mut store, 1).expect_err("memory grew");
+        assert_eq!(
+            exceeded(&err),
+            Some(LimitExceeded::MemorySize {
+                desired: 3 << 16,
+                maximum: 2 << 16
+            })
+        );
+        assert_eq!(store.data().memory_high_water(), 2 << 16);
+
+        assert_eq!(store.data().table_high_water(), 1);
+        assert_eq!(grow_table.call(&mut store, 9).expect("failed to grow"), 1);
+        assert_eq!(store.data().table_high_water(), 10);
+        let err = grow_table.call(&mut store, 1).expect_err("table grew");
+        assert_eq!(
+            exceeded(&err),
+            Some(LimitExceeded::TableElements {
+                desired: 11,
+                maximum: 10
+            })
+        );
+    }
+
+    #[test]
+    fn instantiating_beyond_limits_fails() {
+        let engine = Engine::default();
+        let limits = Limits {
+            instances: 2,
+            tables: 2,
+            ..Limits::default()
+        };
+        let empty = module(&engine, "(module)");
+        let mut store = store(&engine, limits);
+        Instance::new(&mut store, &empty, &[]).expect("failed to instantiate");
+        Instance::new(&mut store, &empty, &[]).expect("failed to instantiate");
+        let err = Instance::new(&mut store, &empty, &[]).expect_err("instantiated");
+        let err = store.data().instantiation_error(err);
+        assert_eq!(
+            exceeded(&err),
+            Some(LimitExceeded::Instances { maximum: 2 })
+        );
+
+        let tables = module(
+            &engine,
+            "(module (table 0 funcref) (table 0 funcref) (table 0 funcref))",
+        );
+        let mut store = self::store(&engine, limits);
+        let err = Instance::new(&mut store, &tables, &[]).expect_err("instantiated");
+        let err = store.data().instantiation_error(err);
+        assert_eq!(exceeded(&err), Some(LimitExceeded::Tables { maximum: 2 }));
+
+        // Other errors are left as they are
+        let err = store
+            .data()
+            .instantiation_error(anyhow::anyhow!("unrelated"));
+        assert_eq!(exceeded(&err), None);
+    }
+}
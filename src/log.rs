@@ -0,0 +1,127 @@
+//! Messages logged by contracts using the `near-cm:host/log` interface
+//!
+//! Messages are emitted as [`tracing`] events with the logging `contract` and the invoked
+//! `func` as fields and recorded per invocation, so that they can be returned to the caller
+//! like `logs` of NEAR call results. Like in NEAR, the number and total length of messages
+//! logged by a single invocation are limited.
+
+use std::sync::Arc;
+
+use anyhow::ensure;
+use wasmtime::StoreContextMut;
+use wasmtime::component::{ComponentType, Lift, Linker};
+
+/// Maximum number of messages logged by a single invocation
+pub const MAX_LOGS: usize = 100;
+
+/// Maximum total length of messages logged by a single invocation in bytes
+pub const MAX_TOTAL_LOG_LENGTH: usize = 16 << 10;
+
+/// Severity of a message
+#[derive(ComponentType, Lift, Clone, Copy, Debug, PartialEq, Eq)]
+#[component(enum)]
+#[repr(u8)]
+pub enum Level {
+    #[component(name = "trace")]
+    Trace,
+    #[component(name = "debug")]
+    Debug,
+    #[component(name = "info")]
+    Info,
+    #[component(name = "warn")]
+    Warn,
+    #[component(name = "error")]
+    Error,
+}
+
+/// Messages logged during a single invocation
+#[derive(Debug, Default)]
+pub struct Logs {
+    /// Name of the invoked function, unset during instantiation
+    func: Option<Arc<str>>,
+    messages: Vec<String>,
+    len: usize,
+}
+
+impl Logs {
+    /// Sets the name of the invoked function, which subsequent messages are attributed to.
+    pub fn set_func(&mut self, func: &str) {
+        self.func = Some(func.into());
+    }
+
+    /// Records `message` logged by `contract` at `level`.
+    fn push(&mut self, contract: &str, level: Level, message: String) -> anyhow::Result<()> {
+        ensure!(
+            self.messages.len() < MAX_LOGS,
+            "number of logs exceeds the limit of {MAX_LOGS}"
+        );
+        self.len = self.len.saturating_add(message.len());
+        ensure!(
+            self.len <= MAX_TOTAL_LOG_LENGTH,
+            "total length of logs exceeds the limit of {MAX_TOTAL_LOG_LENGTH} bytes"
+        );
+        let func = self.func.as_deref().unwrap_or_default();
+        match level {
+            Level::Trace => tracing::trace!(contract, func, "{message}"),
+            Level::Debug => tracing::debug!(contract, func, "{message}"),
+            Level::Info => tracing::info!(contract, func, "{message}"),
+            Level::Warn => tracing::warn!(contract, func, "{message}"),
+            Level::Error => tracing::error!(contract, func, "{message}"),
+        }
+        self.messages.push(message);
+        Ok(())
+    }
+
+    /// Returns all recorded messages and resets the log for the next invocation.
+    pub fn take(&mut self) -> Vec<String> {
+        let Self {
+            func,
+            messages,
+            len,
+        } = self;
+        *func = None;
+        *len = 0;
+        core::mem::take(messages)
+    }
+}
+
+/// Defines `near-cm:host/log` in `linker`, attributing all messages to `contract`.
+///
+/// Messages are recorded in the [`Logs`] returned by `get`.
+pub fn add_to_linker<T: 'static>(
+    linker: &mut Linker<T>,
+    contract: &str,
+    get: fn(&mut T) -> &mut Logs,
+) -> wasmtime::Result<()> {
+    let contract = Arc::<str>::from(contract);
+    let mut linker = linker.instance("near-cm:host/log@0.1.0")?;
+    linker.func_wrap(
+        "log",
+        move |mut store: StoreContextMut<'_, T>, (level, message): (Level, String)| {
+            get(store.data_mut()).push(&contract, level, message)
+        },
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn logs_are_limited() {
+        let mut logs = Logs::default();
+        logs.set_func("f");
+        for _ in 0..MAX_LOGS {
+            logs.push("c", Level::Info, "x".into()).unwrap();
+        }
+        assert!(logs.push("c", Level::Info, "x".into()).is_err());
+        assert_eq!(logs.take().len(), MAX_LOGS);
+
+        logs.push("c", Level::Warn, "x".repeat(MAX_TOTAL_LOG_LENGTH))
+            .unwrap();
+        assert!(logs.push("c", Level::Warn, "x".into()).is_err());
+        assert_eq!(logs.take().len(), 1);
+        assert!(logs.take().is_empty());
+    }
+}
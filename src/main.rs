@@ -1,398 +1,596 @@
-mod bindings {
-    wasmtime::component::bindgen!({
-        world: "format",
-        exports: {
-            default: async,
-        }
-    });
-}
-
-use core::{
-    iter::zip,
-    ops::{Deref, DerefMut},
-};
+use core::str::FromStr;
+use core::time::Duration;
 
-use std::collections::BTreeMap;
+use std::io::IsTerminal as _;
 use std::sync::Arc;
 
-use anyhow::{Context as _, bail, ensure};
+use anyhow::{Context as _, bail};
 use bytes::{Buf, Bytes};
 use http_body_util::BodyExt as _;
 use hyper::body::Incoming;
 use hyper_util::rt::TokioIo;
+use near_cm::events::{Event, Filter};
+use near_cm::print::print_func_ty;
+use near_cm::websocket::{self, opcode, status};
+use near_cm::{
+    ContractOptions, InvokeOptions, Runtime, config, limits, metrics, sessions, storage,
+};
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::TcpListener;
-use wasmtime::component::{Component, Instance, InstancePre, Linker, Type, Val, types};
-use wasmtime::{Engine, Store};
-use wit_component::ComponentEncoder;
+use tokio::sync::{broadcast, mpsc};
+use tracing::{Instrument as _, debug_span, info, info_span, warn};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::prelude::*;
 
-use bindings::exports::cosmonic::reflect::reflect;
+/// Maximum payload size of frames sent by event subscribers
+const MAX_SUBSCRIBER_FRAME_SIZE: usize = 4 << 10;
 
-pub struct Error;
+fn build_http_response<T>(
+    code: http::StatusCode,
+    body: impl Into<T>,
+) -> anyhow::Result<http::Response<http_body_util::Full<T>>>
+where
+    T: Buf + Sync + Send + 'static,
+{
+    http::Response::builder()
+        .status(code)
+        .body(http_body_util::Full::new(body.into()))
+        .context("failed to build response")
+}
 
-async fn unwrap_val<T: Send>(
-    mut store: &mut Store<T>,
-    v: reflect::Value,
-    instance: &reflect::Guest,
-    ty: Type,
-) -> wasmtime::Result<Val> {
-    match (v, ty) {
-        (reflect::Value::Bool(v), Type::Bool) => Ok(Val::Bool(v)),
-        (reflect::Value::S8(v), Type::S8) => Ok(Val::S8(v)),
-        (reflect::Value::U8(v), Type::U8) => Ok(Val::U8(v)),
-        (reflect::Value::U16(v), Type::U16) => Ok(Val::U16(v)),
-        (reflect::Value::S16(v), Type::S16) => Ok(Val::S16(v)),
-        (reflect::Value::U32(v), Type::U32) => Ok(Val::U32(v)),
-        (reflect::Value::S32(v), Type::S32) => Ok(Val::S32(v)),
-        (reflect::Value::U64(v), Type::U64) => Ok(Val::U64(v)),
-        (reflect::Value::S64(v), Type::S64) => Ok(Val::S64(v)),
-        (reflect::Value::F32(v), Type::Float32) => Ok(Val::Float32(v)),
-        (reflect::Value::F64(v), Type::Float64) => Ok(Val::Float64(v)),
-        (reflect::Value::Char(v), Type::Char) => Ok(Val::Char(v)),
-        (reflect::Value::String(v), Type::String) => Ok(Val::String(v)),
-        (reflect::Value::Record(v), Type::Record(ty)) => {
-            let values = instance
-                .record_value()
-                .call_into_value(&mut store, v)
-                .await?;
-            ensure!(values.len() == ty.fields().len());
+fn header_str(v: &http::HeaderValue) -> anyhow::Result<&str> {
+    v.to_str().context("header value is not valid UTF-8")
+}
 
-            let mut fields = Vec::with_capacity(values.len());
-            for (types::Field { name, ty }, v) in zip(ty.fields(), values) {
-                let v = Box::pin(unwrap_val(store, v, instance, ty))
-                    .await
-                    .with_context(|| format!("failed to unwrap record field `{name}`"))?;
-                fields.push((name.into(), v));
+fn env_var(name: &str) -> anyhow::Result<Option<String>> {
+    match std::env::var(name) {
+        Ok(v) => Ok(Some(v)),
+        Err(std::env::VarError::NotPresent) => Ok(None),
+        Err(err) => bail!("failed to read `{name}`: {err}"),
+    }
+}
+
+fn parse_env<T>(name: &str) -> anyhow::Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    let Some(v) = env_var(name)? else {
+        return Ok(None);
+    };
+    v.parse()
+        .map(Some)
+        .with_context(|| format!("failed to parse `{name}` value `{v}`"))
+}
+
+/// Initializes the tracing subscriber, which logs events and closed spans with their
+/// durations to stderr, filtered by `NEAR_CM_LOG` (defaults to `info`), and exports spans
+/// to the OpenTelemetry collector at `OTEL_EXPORTER_OTLP_ENDPOINT`, if set.
+fn init_tracing() -> anyhow::Result<()> {
+    let filter: Targets = env_var("NEAR_CM_LOG")?
+        .as_deref()
+        .unwrap_or("info")
+        .parse()
+        .context("failed to parse `NEAR_CM_LOG`")?;
+    let fmt = tracing_subscriber::fmt::layer()
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal())
+        .with_span_events(FmtSpan::CLOSE)
+        .with_filter(filter);
+    let subscriber = tracing_subscriber::registry().with(fmt);
+    #[cfg(feature = "otlp")]
+    let subscriber = {
+        let otlp = match env_var("OTEL_EXPORTER_OTLP_ENDPOINT")? {
+            Some(..) => {
+                let layer = near_cm::otlp::layer(env!("CARGO_PKG_NAME"))?;
+                // Spans of all pipeline phases are exported regardless of `NEAR_CM_LOG`
+                Some(
+                    layer.with_filter(Targets::new().with_target("near_cm", tracing::Level::DEBUG)),
+                )
             }
-            Ok(Val::Record(fields))
-        }
-        #[expect(unused, reason = "incomplete")]
-        (reflect::Value::Variant(v), Type::Variant(ty)) => todo!(),
-        #[expect(unused, reason = "incomplete")]
-        (reflect::Value::List(v), Type::List(ty)) => todo!(),
-        (reflect::Value::Tuple(v), Type::Tuple(ty)) => {
-            let values = instance
-                .tuple_value()
-                .call_into_value(&mut store, v)
-                .await?;
-            ensure!(values.len() == ty.types().len());
+            None => None,
+        };
+        subscriber.with(otlp)
+    };
+    subscriber
+        .try_init()
+        .context("failed to initialize tracing subscriber")
+}
 
-            let mut elems = Vec::with_capacity(values.len());
-            for ((i, ty), v) in zip(ty.types().enumerate(), values) {
-                let v = Box::pin(unwrap_val(store, v, instance, ty))
-                    .await
-                    .with_context(|| format!("failed to unwrap tuple element `{i}`"))?;
-                elems.push(v);
+/// Appends messages logged during an invocation as `X-Log` headers in order,
+/// with control characters escaped.
+fn append_logs(headers: &mut http::HeaderMap, logs: &[String]) {
+    for log in logs {
+        let mut escaped = String::with_capacity(log.len());
+        for c in log.chars() {
+            if c.is_control() {
+                escaped.extend(c.escape_default());
+            } else {
+                escaped.push(c);
             }
-            Ok(Val::Tuple(elems))
         }
-        #[expect(unused, reason = "incomplete")]
-        (reflect::Value::Flags(v), Type::Flags(ty)) => todo!(),
-        #[expect(unused, reason = "incomplete")]
-        (reflect::Value::Enum(v), Type::Enum(ty)) => todo!(),
-        #[expect(unused, reason = "incomplete")]
-        (reflect::Value::Option(v), Type::Option(ty)) => todo!(),
-        #[expect(unused, reason = "incomplete")]
-        (reflect::Value::Result(v), Type::Result(ty)) => todo!(),
-        _ => bail!("type mismatch"),
+        let v = http::HeaderValue::from_bytes(escaped.as_bytes())
+            .expect("header value contains no control characters");
+        headers.append("X-Log", v);
+    }
+}
+
+/// Returns the response status code for session error `err`.
+fn session_error_status(err: &sessions::Error) -> http::StatusCode {
+    match err {
+        sessions::Error::NotFound => http::StatusCode::NOT_FOUND,
+        sessions::Error::Id(..) => http::StatusCode::INTERNAL_SERVER_ERROR,
+        sessions::Error::Limit { .. } => http::StatusCode::SERVICE_UNAVAILABLE,
+        sessions::Error::Contract { .. } => http::StatusCode::CONFLICT,
+        sessions::Error::Runtime(err) => error_status(err),
     }
 }
 
-async fn make_reflect_ty<T: Send>(
-    store: &mut Store<T>,
-    instance: &reflect::Guest,
-    ty: Type,
-) -> wasmtime::Result<reflect::Type> {
-    match ty {
-        Type::Bool => Ok(reflect::Type::Bool),
-        Type::S8 => Ok(reflect::Type::S8),
-        Type::U8 => Ok(reflect::Type::U8),
-        Type::S16 => Ok(reflect::Type::S16),
-        Type::U16 => Ok(reflect::Type::U16),
-        Type::S32 => Ok(reflect::Type::S32),
-        Type::U32 => Ok(reflect::Type::U32),
-        Type::S64 => Ok(reflect::Type::S64),
-        Type::U64 => Ok(reflect::Type::U64),
-        Type::Float32 => Ok(reflect::Type::F32),
-        Type::Float64 => Ok(reflect::Type::F64),
-        Type::Char => Ok(reflect::Type::Char),
-        Type::String => Ok(reflect::Type::String),
-        Type::List(ty) => {
-            match ty.ty() {
-                Type::Bool => Ok(reflect::Type::List(reflect::ListType::Bool)),
-                Type::S8 => Ok(reflect::Type::List(reflect::ListType::S8)),
-                Type::U8 => Ok(reflect::Type::List(reflect::ListType::U8)),
-                Type::S16 => Ok(reflect::Type::List(reflect::ListType::S16)),
-                Type::U16 => Ok(reflect::Type::List(reflect::ListType::U16)),
-                Type::S32 => Ok(reflect::Type::List(reflect::ListType::S32)),
-                Type::U32 => Ok(reflect::Type::List(reflect::ListType::U32)),
-                Type::S64 => Ok(reflect::Type::List(reflect::ListType::S64)),
-                Type::U64 => Ok(reflect::Type::List(reflect::ListType::U64)),
-                Type::Float32 => Ok(reflect::Type::List(reflect::ListType::F32)),
-                Type::Float64 => Ok(reflect::Type::List(reflect::ListType::F64)),
-                Type::Char => Ok(reflect::Type::List(reflect::ListType::Char)),
-                Type::String => Ok(reflect::Type::List(reflect::ListType::String)),
-                //Type::List(list) => Ok(reflect::Type::List(reflect::ListType::List)),
-                //Type::Record(record) => Ok(reflect::Type::List(reflect::ListType::Record)),
-                //Type::Tuple(tuple) => Ok(reflect::Type::List(reflect::ListType::Tuple)),
-                //Type::Variant(variant) => Ok(reflect::Type::List(reflect::ListType::Variant)),
-                //Type::Enum(_) => Ok(reflect::Type::List(reflect::ListType::Enum)),
-                //Type::Option(option_type) => Ok(reflect::Type::List(reflect::ListType::Option)),
-                //Type::Result(result_type) => Ok(reflect::Type::List(reflect::ListType::Result)),
-                //Type::Flags(flags) => Ok(reflect::Type::List(reflect::ListType::Flags)),
-                Type::Own(..) | Type::Borrow(..) => bail!("resources not supported"),
-                Type::Future(..) => bail!("futures not supported"),
-                Type::Stream(..) => bail!("streams not supported"),
-                Type::ErrorContext => bail!("error context not supported"),
-                _ => todo!(),
+/// Returns the response status code for invocation error `err`.
+fn error_status(err: &near_cm::Error) -> http::StatusCode {
+    if err.is_body_too_large() || err.is_batch_too_large() {
+        http::StatusCode::PAYLOAD_TOO_LARGE
+    } else if err.is_limit_exceeded() {
+        http::StatusCode::INSUFFICIENT_STORAGE
+    } else if err.is_out_of_gas() {
+        http::StatusCode::UNPROCESSABLE_ENTITY
+    } else if err.is_timeout() {
+        http::StatusCode::GATEWAY_TIMEOUT
+    } else if err.is_conflict() {
+        http::StatusCode::CONFLICT
+    } else {
+        match err {
+            near_cm::Error::NotFound(..) | near_cm::Error::Decode(..) => {
+                http::StatusCode::NOT_FOUND
             }
-        }
-        Type::Record(ty) => {
-            let mut fields = Vec::with_capacity(ty.fields().len());
-            for types::Field { name, ty } in ty.fields() {
-                let ty = Box::pin(make_reflect_ty(store, instance, ty)).await?;
-                fields.push((name.into(), ty))
+            near_cm::Error::Instantiate { .. } | near_cm::Error::Call { .. } => {
+                http::StatusCode::BAD_REQUEST
             }
-            let ty = instance
-                .record_type()
-                .call_constructor(store, &fields)
-                .await?;
-            Ok(reflect::Type::Record(ty))
-        }
-        Type::Tuple(ty) => {
-            let mut tys = Vec::with_capacity(ty.types().len());
-            for ty in ty.types() {
-                let ty = Box::pin(make_reflect_ty(store, instance, ty)).await?;
-                tys.push(ty)
+            near_cm::Error::Timeout => http::StatusCode::GATEWAY_TIMEOUT,
+            near_cm::Error::Commit(..) | near_cm::Error::Encode(..) => {
+                http::StatusCode::INTERNAL_SERVER_ERROR
             }
-            let ty = instance.tuple_type().call_constructor(store, &tys).await?;
-            Ok(reflect::Type::Tuple(ty))
         }
-        #[expect(unused, reason = "incomplete")]
-        Type::Variant(ty) => todo!(),
-        #[expect(unused, reason = "incomplete")]
-        Type::Enum(ty) => todo!(),
-        #[expect(unused, reason = "incomplete")]
-        Type::Option(ty) => todo!(),
-        #[expect(unused, reason = "incomplete")]
-        Type::Result(ty) => todo!(),
-        #[expect(unused, reason = "incomplete")]
-        Type::Flags(ty) => todo!(),
-        #[expect(unused, reason = "incomplete")]
-        Type::Own(ty) => todo!(),
-        #[expect(unused, reason = "incomplete")]
-        Type::Borrow(ty) => todo!(),
-        #[expect(unused, reason = "incomplete")]
-        Type::Future(ty) => todo!(),
-        #[expect(unused, reason = "incomplete")]
-        Type::Stream(ty) => todo!(),
-        Type::ErrorContext => todo!(),
     }
 }
 
-async fn deserialize_params<T: Send>(
-    mut store: &mut Store<T>,
-    instance: &bindings::Format,
-    ty: &types::ComponentFunc,
-    body: hyper::body::Incoming,
-) -> wasmtime::Result<Vec<Val>> {
-    let tys = ty.params();
-    let num_params = tys.len();
-    if num_params == 0 {
-        let body = body.collect().await?;
-        ensure!(body.to_bytes().is_empty());
-        return Ok(Vec::default());
-    }
-
-    let mut reflect_tys = Vec::with_capacity(ty.params().len());
-    for (_, ty) in ty.params() {
-        let ty = make_reflect_ty(store, instance.cosmonic_reflect_reflect(), ty).await?;
-        reflect_tys.push(ty);
-    }
-    let reflect_ty = instance
-        .cosmonic_reflect_reflect()
-        .tuple_type()
-        .call_constructor(&mut store, &reflect_tys)
-        .await?;
+/// Returns the value of optional header `name`.
+fn optional_header<'a>(
+    headers: &'a http::HeaderMap,
+    name: &str,
+) -> anyhow::Result<Option<&'a str>> {
+    headers
+        .get(name)
+        .map(header_str)
+        .transpose()
+        .with_context(|| format!("failed to parse `{name}` header value"))
+}
 
-    let body = body.collect().await?;
-    let values = match instance
-        .cosmonic_serde_deserializer()
-        .call_from_list(
-            &mut store,
-            &body.to_bytes(),
-            reflect::Type::Tuple(reflect_ty),
-        )
-        .await?
-    {
-        Ok(value) => value,
-        Err(err) => {
-            let err = instance
-                .cosmonic_serde_deserializer()
-                .error()
-                .call_to_string(store, err)
-                .await?;
-            bail!(err)
-        }
-    };
-    let reflect::Value::Tuple(values) = values else {
-        bail!("deserialized value is not a tuple");
+/// Parses the value of optional header `name`.
+fn parse_header<T>(headers: &http::HeaderMap, name: &str) -> anyhow::Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    let Some(v) = optional_header(headers, name)? else {
+        return Ok(None);
     };
-    let values = instance
-        .cosmonic_reflect_reflect()
-        .tuple_value()
-        .call_into_value(&mut store, values)
-        .await?;
-    ensure!(values.len() == num_params);
-
-    let mut params = Vec::with_capacity(num_params);
-    for ((name, ty), v) in zip(tys, values) {
-        let v = unwrap_val(store, v, instance.cosmonic_reflect_reflect(), ty)
-            .await
-            .with_context(|| format!("failed to unwrap param `{name}`"))?;
-        params.push(v);
-    }
-    Ok(params)
+    v.parse()
+        .map(Some)
+        .with_context(|| format!("failed to parse `{name}` header value `{v}`"))
 }
 
-struct Ctx(Option<Instance>);
-
-impl Deref for Ctx {
-    type Target = Option<Instance>;
+/// Returns the codec, whether to invoke in parallel and invocation options of a batch.
+fn batch_headers(headers: &http::HeaderMap) -> anyhow::Result<(&str, bool, InvokeOptions<'_>)> {
+    let codec = optional_header(headers, "X-Codec")?.context("`X-Codec` header missing")?;
+    let parallel = parse_header(headers, "X-Parallel")?.unwrap_or(false);
+    let opts = InvokeOptions {
+        target: optional_header(headers, "X-Target")?,
+        gas_limit: parse_header(headers, "X-Gas-Limit")?,
+        ..InvokeOptions::default()
+    };
+    Ok((codec, parallel, opts))
+}
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
+/// Reads `body` of at most `max` bytes, returns the error response on failure.
+async fn read_body(
+    body: Incoming,
+    max: usize,
+) -> Result<Bytes, anyhow::Result<http::Response<http_body_util::Full<Bytes>>>> {
+    match http_body_util::Limited::new(body, max).collect().await {
+        Ok(body) => Ok(body.to_bytes()),
+        Err(err) if err.is::<http_body_util::LengthLimitError>() => Err(build_http_response(
+            http::StatusCode::PAYLOAD_TOO_LARGE,
+            err.to_string(),
+        )),
+        Err(err) => Err(build_http_response(
+            http::StatusCode::BAD_REQUEST,
+            format!("Failed to read body: {err}"),
+        )),
     }
 }
 
-impl DerefMut for Ctx {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+/// Serves a NEAR JSON-RPC request, see [`near_cm::rpc`].
+#[cfg(feature = "rpc")]
+async fn serve_rpc(
+    runtime: &Runtime,
+    method: http::Method,
+    headers: &http::HeaderMap,
+    body: Incoming,
+    max_body_size: usize,
+) -> anyhow::Result<http::Response<http_body_util::Full<Bytes>>> {
+    if method != http::Method::POST {
+        return build_http_response(
+            http::StatusCode::METHOD_NOT_ALLOWED,
+            format!("Method `{method}` not supported"),
+        );
     }
+    let codec = match optional_header(headers, "X-Codec") {
+        Ok(codec) => codec.unwrap_or("json"),
+        Err(err) => return build_http_response(http::StatusCode::BAD_REQUEST, format!("{err:#}")),
+    };
+    let buf = match read_body(body, max_body_size).await {
+        Ok(buf) => buf,
+        Err(res) => return res,
+    };
+    let res = near_cm::rpc::handle(runtime, codec, &buf).await;
+    http::Response::builder()
+        .header(http::header::CONTENT_TYPE, "application/json")
+        .body(http_body_util::Full::new(Bytes::from(res)))
+        .context("failed to build response")
 }
 
-struct Workload {
-    pre: InstancePre<Ctx>,
-    ty: types::Component,
+/// Parses subscription filter from URI query `q` of form `contract=<contract>&topic=<topic>`.
+fn parse_filter(q: Option<&str>) -> anyhow::Result<Filter> {
+    let mut filter = Filter::default();
+    for (k, v) in url::form_urlencoded::parse(q.unwrap_or_default().as_bytes()) {
+        match k.as_ref() {
+            "contract" => filter.contract = Some(v.into()),
+            "topic" => filter.topic = Some(v.into()),
+            _ => bail!("URI query parameter `{k}` not supported"),
+        }
+    }
+    if filter.contract.is_none() {
+        bail!("URI query parameter `contract` missing");
+    }
+    Ok(filter)
 }
 
-fn build_http_response<T>(
-    code: http::StatusCode,
-    body: impl Into<T>,
-) -> anyhow::Result<http::Response<http_body_util::Full<T>>>
-where
-    T: Buf + Sync + Send + 'static,
-{
+/// Upgrades `req` to a WebSocket connection, which receives committed events
+/// matching the filter given by the URI query, see [`parse_filter`].
+fn serve_events(
+    runtime: &Runtime,
+    mut req: http::Request<Incoming>,
+) -> anyhow::Result<http::Response<http_body_util::Full<Bytes>>> {
+    if req.method() != http::Method::GET {
+        return build_http_response(
+            http::StatusCode::METHOD_NOT_ALLOWED,
+            format!("Method `{}` not supported", req.method()),
+        );
+    }
+    let filter = match parse_filter(req.uri().query()) {
+        Ok(filter) => filter,
+        Err(err) => return build_http_response(http::StatusCode::BAD_REQUEST, format!("{err:#}")),
+    };
+    if let Some(contract) = filter.contract.as_deref()
+        && !runtime.contracts().any(|name| name == contract)
+    {
+        return build_http_response(
+            http::StatusCode::NOT_FOUND,
+            format!("Contract `{contract}` not found"),
+        );
+    }
+    let accept = match websocket::accept(req.headers()) {
+        Ok(accept) => accept,
+        Err(err) => {
+            return build_http_response(
+                http::StatusCode::UPGRADE_REQUIRED,
+                format!("Invalid WebSocket handshake: {err:#}"),
+            );
+        }
+    };
+    // Events committed after the handshake are not missed
+    let events = runtime.events().subscribe();
+    let upgrade = hyper::upgrade::on(&mut req);
+    tokio::spawn(async move {
+        let res = match upgrade.await {
+            Ok(io) => stream_events(TokioIo::new(io), events, filter).await,
+            Err(err) => Err(err.into()),
+        };
+        if let Err(err) = res {
+            warn!("failed to stream events: {err:#}");
+        }
+    });
     http::Response::builder()
-        .status(code)
-        .body(http_body_util::Full::new(body.into()))
+        .status(http::StatusCode::SWITCHING_PROTOCOLS)
+        .header(http::header::CONNECTION, "Upgrade")
+        .header(http::header::UPGRADE, "websocket")
+        .header(http::header::SEC_WEBSOCKET_ACCEPT, accept)
+        .body(http_body_util::Full::default())
         .context("failed to build response")
 }
 
-fn print_func_ty(out: &mut String, ty: types::ComponentFunc) {
-    out.push_str("func(");
-    let mut params = ty.params();
-    if let Some((name, ty)) = params.next() {
-        out.push_str(name);
-        out.push_str(": ");
-        print_ty(out, ty);
-        for (name, ty) in params {
-            out.push_str(", ");
-            out.push_str(name);
-            out.push_str(": ");
-            print_ty(out, ty);
+/// Sends `events` matching `filter` as NEP-297 JSON text messages over WebSocket
+/// connection `io` until the client closes it.
+async fn stream_events(
+    io: impl AsyncRead + AsyncWrite + Send + 'static,
+    mut events: broadcast::Receiver<Arc<Event>>,
+    filter: Filter,
+) -> anyhow::Result<()> {
+    let (mut r, mut w) = tokio::io::split(io);
+    // Frames are read by a separate task, since reading them is not cancellation safe
+    let (frames_tx, mut frames) = mpsc::channel(1);
+    let reader = tokio::spawn(async move {
+        loop {
+            let frame = websocket::read_frame(&mut r, MAX_SUBSCRIBER_FRAME_SIZE).await;
+            let done = !matches!(&frame, Ok(frame) if frame.opcode != opcode::CLOSE);
+            if frames_tx.send(frame).await.is_err() || done {
+                return;
+            }
         }
-    }
-    out.push_str(")");
-    let mut results = ty.results();
-    if let Some(ty) = results.next() {
-        out.push_str(" -> ");
-        print_ty(out, ty);
-        for ty in results {
-            out.push_str(", ");
-            print_ty(out, ty);
+    });
+    let res = async {
+        loop {
+            tokio::select! {
+                frame = frames.recv() => match frame {
+                    Some(Ok(frame)) if frame.opcode == opcode::PING => {
+                        websocket::write_frame(&mut w, opcode::PONG, &frame.payload).await?;
+                    }
+                    Some(Ok(frame)) if frame.opcode == opcode::CLOSE => {
+                        websocket::write_close(&mut w, status::NORMAL, "").await?;
+                        return Ok(());
+                    }
+                    // Messages sent by subscribers are ignored
+                    Some(Ok(..)) => {}
+                    Some(Err(err)) => {
+                        let code = if err.kind() == std::io::ErrorKind::InvalidData {
+                            status::PROTOCOL_ERROR
+                        } else {
+                            status::GOING_AWAY
+                        };
+                        // The connection may be gone already
+                        let _ = websocket::write_close(&mut w, code, &err.to_string()).await;
+                        return Ok(());
+                    }
+                    None => return Ok(()),
+                },
+                event = events.recv() => match event {
+                    Ok(event) if filter.matches(&event) => {
+                        websocket::write_frame(&mut w, opcode::TEXT, event.to_json().as_bytes())
+                            .await?;
+                    }
+                    Ok(..) => {}
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        let reason = format!("subscriber lagged behind by {n} events");
+                        websocket::write_close(&mut w, status::TRY_AGAIN_LATER, &reason).await?;
+                        return Ok(());
+                    }
+                    Err(broadcast::error::RecvError::Closed) => {
+                        websocket::write_close(&mut w, status::GOING_AWAY, "").await?;
+                        return Ok(());
+                    }
+                },
+            }
         }
     }
+    .await;
+    reader.abort();
+    res
 }
 
-fn print_ty(out: &mut String, ty: Type) {
-    #[expect(unused)]
-    match ty {
-        Type::Bool => out.push_str("bool"),
-        Type::S8 => out.push_str("s8"),
-        Type::U8 => out.push_str("u8"),
-        Type::S16 => out.push_str("s16"),
-        Type::U16 => out.push_str("u16"),
-        Type::S32 => out.push_str("s32"),
-        Type::U32 => out.push_str("u32"),
-        Type::S64 => out.push_str("s64"),
-        Type::U64 => out.push_str("u64"),
-        Type::Float32 => out.push_str("float32"),
-        Type::Float64 => out.push_str("float64"),
-        Type::Char => out.push_str("char"),
-        Type::String => out.push_str("string"),
-        Type::List(ty) => {
-            out.push_str("list<");
-            print_ty(out, ty.ty());
-            out.push_str(">");
-        }
-        Type::Record(ty) => {
-            out.push_str("record{");
-            let mut fields = ty.fields();
-            if let Some(types::Field { name, ty }) = fields.next() {
-                out.push_str(name);
-                out.push_str(": ");
-                print_ty(out, ty);
-                for types::Field { name, ty } in fields {
-                    out.push_str(", ");
-                    out.push_str(name);
-                    out.push_str(": ");
-                    print_ty(out, ty);
+/// Serves a batch of invocations, see [`Runtime::invoke_batch`].
+async fn serve_batch(
+    runtime: &Arc<Runtime>,
+    method: http::Method,
+    headers: &http::HeaderMap,
+    body: Incoming,
+    max_body_size: usize,
+) -> anyhow::Result<http::Response<http_body_util::Full<Bytes>>> {
+    if method != http::Method::POST {
+        return build_http_response(
+            http::StatusCode::METHOD_NOT_ALLOWED,
+            format!("Method `{method}` not supported"),
+        );
+    }
+    let (codec, parallel, opts) = match batch_headers(headers) {
+        Ok(v) => v,
+        Err(err) => return build_http_response(http::StatusCode::BAD_REQUEST, format!("{err:#}")),
+    };
+    let buf = match read_body(body, max_body_size).await {
+        Ok(buf) => buf,
+        Err(res) => return res,
+    };
+    match runtime.invoke_batch(codec, &buf, opts, parallel).await {
+        Ok(buf) => Ok(http::Response::new(http_body_util::Full::new(Bytes::from(
+            buf,
+        )))),
+        Err(err) => build_http_response(error_status(&err), err.to_string()),
+    }
+}
+
+/// Creates a session on `POST` and ends it on `DELETE`, see [`sessions::Sessions`].
+/// `DELETE` with `X-Resource` drops the resource with that handle ID instead.
+async fn serve_sessions(
+    runtime: &Runtime,
+    sessions: &sessions::Sessions,
+    method: http::Method,
+    headers: &http::HeaderMap,
+) -> anyhow::Result<http::Response<http_body_util::Full<Bytes>>> {
+    match method {
+        http::Method::POST => {
+            let (contract, target) = match optional_header(headers, "X-Contract")
+                .and_then(|contract| contract.context("`X-Contract` header missing"))
+                .and_then(|contract| Ok((contract, optional_header(headers, "X-Target")?)))
+            {
+                Ok(v) => v,
+                Err(err) => {
+                    return build_http_response(http::StatusCode::BAD_REQUEST, format!("{err:#}"));
                 }
+            };
+            match sessions.create(runtime, contract, target).await {
+                Ok(id) => http::Response::builder()
+                    .status(http::StatusCode::CREATED)
+                    .header("X-Session", &*id)
+                    .body(http_body_util::Full::new(Bytes::from(id.into_string())))
+                    .context("failed to build response"),
+                Err(err) => build_http_response(session_error_status(&err), err.to_string()),
             }
-            out.push_str("}");
         }
-        Type::Tuple(ty) => {
-            out.push_str("tuple<");
-            let mut tys = ty.types();
-            if let Some(ty) = tys.next() {
-                print_ty(out, ty);
-                for ty in tys {
-                    out.push_str(", ");
-                    print_ty(out, ty);
+        http::Method::DELETE => {
+            let session = match optional_header(headers, "X-Session")
+                .and_then(|session| session.context("`X-Session` header missing"))
+            {
+                Ok(session) => session,
+                Err(err) => {
+                    return build_http_response(http::StatusCode::BAD_REQUEST, format!("{err:#}"));
                 }
+            };
+            let (resource, gas_limit) = match parse_header::<u32>(headers, "X-Resource")
+                .and_then(|resource| Ok((resource, parse_header(headers, "X-Gas-Limit")?)))
+            {
+                Ok(v) => v,
+                Err(err) => {
+                    return build_http_response(http::StatusCode::BAD_REQUEST, format!("{err:#}"));
+                }
+            };
+            if let Some(resource) = resource {
+                let mut session = match sessions.acquire(session, None).await {
+                    Ok(session) => session,
+                    Err(err) => {
+                        return build_http_response(session_error_status(&err), err.to_string());
+                    }
+                };
+                return match runtime
+                    .drop_resource(&mut session, resource, gas_limit)
+                    .await
+                {
+                    Ok(Some(outcome)) => {
+                        let mut res =
+                            build_http_response(http::StatusCode::NO_CONTENT, Bytes::new())?;
+                        res.headers_mut()
+                            .insert("X-Gas-Used", outcome.gas_used.into());
+                        append_logs(res.headers_mut(), &outcome.logs);
+                        Ok(res)
+                    }
+                    Ok(None) => build_http_response(
+                        http::StatusCode::NOT_FOUND,
+                        format!("Resource `{resource}` not found"),
+                    ),
+                    Err(err) => {
+                        let mut res = build_http_response(error_status(&err), err.to_string())?;
+                        if let near_cm::Error::Call { gas_used, logs, .. } = err {
+                            // The contract instance cannot be entered anymore after a trap
+                            session.discard();
+                            res.headers_mut().insert("X-Gas-Used", gas_used.into());
+                            append_logs(res.headers_mut(), &logs);
+                        }
+                        Ok(res)
+                    }
+                };
             }
-            out.push_str(">");
+            if !sessions.remove(session) {
+                return build_http_response(
+                    http::StatusCode::NOT_FOUND,
+                    format!("Session `{session}` not found"),
+                );
+            }
+            build_http_response(http::StatusCode::NO_CONTENT, Bytes::new())
         }
-        Type::Variant(variant) => out.push_str("variant"),
-        Type::Enum(_) => out.push_str("enum"),
-        Type::Option(option_type) => out.push_str("option"),
-        Type::Result(result_type) => out.push_str("result"),
-        Type::Flags(flags) => out.push_str("flags"),
-        Type::Own(resource_type) => out.push_str("own"),
-        Type::Borrow(resource_type) => out.push_str("borrow"),
-        Type::Future(future_type) => out.push_str("future"),
-        Type::Stream(stream_type) => out.push_str("stream"),
-        Type::ErrorContext => out.push_str("error-context"),
+        method => build_http_response(
+            http::StatusCode::METHOD_NOT_ALLOWED,
+            format!("Method `{method}` not supported"),
+        ),
     }
 }
 
-fn compile_component(engine: &Engine, buf: &[u8]) -> anyhow::Result<Component> {
-    let mut enc = ComponentEncoder::default().module(&buf)?;
-    let buf = enc.encode()?;
-    Component::new(engine, buf).context("failed to compile component")
-}
-
-fn header_str(v: &http::HeaderValue) -> anyhow::Result<&str> {
-    v.to_str().context("header value is not valid UTF-8")
+/// Serves runtime and session metrics, see [`near_cm::metrics`].
+fn serve_metrics(
+    runtime: &Runtime,
+    sessions: &sessions::Sessions,
+    method: http::Method,
+) -> anyhow::Result<http::Response<http_body_util::Full<Bytes>>> {
+    if method != http::Method::GET {
+        return build_http_response(
+            http::StatusCode::METHOD_NOT_ALLOWED,
+            format!("Method `{method}` not supported"),
+        );
+    }
+    let mut out = String::default();
+    runtime.encode_metrics(&mut out);
+    metrics::encode_gauge(
+        &mut out,
+        "near_cm_sessions",
+        "Number of open sessions",
+        sessions.len(),
+    );
+    http::Response::builder()
+        .header(http::header::CONTENT_TYPE, metrics::CONTENT_TYPE)
+        .body(http_body_util::Full::new(Bytes::from(out)))
+        .context("failed to build response")
 }
 
 #[tokio::main]
 async fn main() -> wasmtime::Result<()> {
+    init_tracing()?;
     let mut args = std::env::args();
     let exe = args.next().context("executable name missing")?;
 
-    let engine = Engine::new(wasmtime::Config::new().async_support(true))?;
-    let mut components: BTreeMap<Box<str>, _> = BTreeMap::default();
+    let storage = storage::open(env_var("NEAR_CM_STORAGE")?.as_deref().unwrap_or("memory"))?;
+    let defaults = near_cm::Options::default();
+    let limits = limits::Limits {
+        memory_size: parse_env("NEAR_CM_MAX_MEMORY")?.unwrap_or(defaults.limits.memory_size),
+        tables: parse_env("NEAR_CM_MAX_TABLES")?.unwrap_or(defaults.limits.tables),
+        table_elements: parse_env("NEAR_CM_MAX_TABLE_ELEMENTS")?
+            .unwrap_or(defaults.limits.table_elements),
+        instances: parse_env("NEAR_CM_MAX_INSTANCES")?.unwrap_or(defaults.limits.instances),
+    };
+    let options = near_cm::Options {
+        max_gas: parse_env("NEAR_CM_MAX_GAS")?.unwrap_or(defaults.max_gas),
+        timeout: parse_env("NEAR_CM_TIMEOUT_MS")?
+            .map(Duration::from_millis)
+            .unwrap_or(defaults.timeout),
+        limits,
+        max_body_size: parse_env("NEAR_CM_MAX_BODY_SIZE")?.unwrap_or(defaults.max_body_size),
+        pool_size: parse_env("NEAR_CM_POOL_SIZE")?.unwrap_or(defaults.pool_size),
+        max_pooled_uses: parse_env("NEAR_CM_MAX_POOLED_USES")?.unwrap_or(defaults.max_pooled_uses),
+        max_pooled_table_elements: parse_env("NEAR_CM_MAX_POOLED_TABLE_ELEMENTS")?
+            .unwrap_or(defaults.max_pooled_table_elements),
+        max_batch_len: parse_env("NEAR_CM_MAX_BATCH_LEN")?.unwrap_or(defaults.max_batch_len),
+        batch_concurrency: parse_env("NEAR_CM_BATCH_CONCURRENCY")?
+            .unwrap_or(defaults.batch_concurrency),
+        max_batch_gas: parse_env("NEAR_CM_MAX_BATCH_GAS")?.unwrap_or(defaults.max_batch_gas),
+        debug: parse_env("NEAR_CM_DEBUG")?.unwrap_or(defaults.debug),
+    };
+    let stateless = env_var("NEAR_CM_STATELESS")?.unwrap_or_default();
+    let stateless: Vec<_> = stateless
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect();
+
+    let session_defaults = sessions::Options::default();
+    let session_options = sessions::Options {
+        idle_timeout: parse_env("NEAR_CM_SESSION_IDLE_TIMEOUT_MS")?
+            .map(Duration::from_millis)
+            .unwrap_or(session_defaults.idle_timeout),
+        max_sessions: parse_env("NEAR_CM_MAX_SESSIONS")?.unwrap_or(session_defaults.max_sessions),
+    };
+
+    let config = match env_var("NEAR_CM_PROFILE")?.as_deref() {
+        None | Some("default") => wasmtime::Config::new(),
+        Some("nearcore") => config::new_wasmtime_config(
+            limits
+                .memory_size
+                .div_ceil(1 << 16)
+                .try_into()
+                .unwrap_or(u32::MAX),
+            limits.tables.try_into().unwrap_or(u32::MAX),
+            limits.table_elements,
+        ),
+        Some(profile) => bail!("unknown profile `{profile}`, expected `default` or `nearcore`"),
+    };
+    let mut runtime = Runtime::new(&config, storage, options)?;
     for dir in args {
         let dir = std::fs::read_dir(dir)?;
         for entry in dir {
@@ -406,50 +604,49 @@ async fn main() -> wasmtime::Result<()> {
                 continue;
             };
             let wasm = std::fs::read(entry.path())?;
-            let component = compile_component(&engine, &wasm)?;
-            let mut linker = Linker::new(&engine);
-            for (name, ty) in component.component_type().imports(&engine) {
-                let types::ComponentItem::ComponentFunc(..) = ty else {
-                    continue;
-                };
-                let name = Arc::from(name);
-                linker.root().func_new_async(&name, {
-                    let name = Arc::clone(&name);
-                    move |mut store, params, results| {
-                        let name = Arc::clone(&name);
-                        Box::new(async move {
-                            let Ctx(Some(target)) = store.data() else {
-                                bail!("target component missing");
-                            };
-                            let target = *target;
-                            let f = target
-                                .get_func(&mut store, name.as_ref())
-                                .context("function not found")?;
-                            f.call_async(&mut store, params, results).await?;
-                            f.post_return_async(store).await?;
-                            Ok(())
-                        })
-                    }
-                })?;
-            }
-            let ty = linker.substituted_component_type(&component)?;
-            let pre = linker.instantiate_pre(&component)?;
-            components.insert(name.into(), Workload { pre, ty });
+            let opts = ContractOptions {
+                stateless: stateless.contains(&name),
+            };
+            runtime.load_contract_with(name, &wasm, opts)?;
         }
     }
-    if components.is_empty() {
+    if runtime.contracts().next().is_none() {
         bail!(
             r#"No Wasm components found.
 Usage: {exe} [dirs...]"#
         )
     }
-    let components = Arc::new(components);
+    let runtime = Arc::new(runtime);
+    let max_body_size = options.max_body_size;
+    let sessions = Arc::new(sessions::Sessions::new(session_options));
+    tokio::spawn({
+        let sessions = Arc::clone(&sessions);
+        async move {
+            let mut interval =
+                tokio::time::interval(session_options.idle_timeout.max(Duration::from_secs(1)));
+            loop {
+                interval.tick().await;
+                sessions.evict_idle();
+            }
+        }
+    });
     let srv = hyper::server::conn::http1::Builder::new();
     let lis = TcpListener::bind("[::1]:8080").await?;
+    info!("listening on {}", lis.local_addr()?);
     let svc = hyper::service::service_fn({
         move |req: http::Request<Incoming>| {
-            let components = Arc::clone(&components);
-            async move {
+            let runtime = Arc::clone(&runtime);
+            let sessions = Arc::clone(&sessions);
+            let span = info_span!(
+                "request",
+                method = %req.method(),
+                path = req.uri().path(),
+                status = tracing::field::Empty,
+            );
+            let res = async move {
+                if req.uri().path() == "/events" {
+                    return serve_events(&runtime, req);
+                }
                 let (
                     http::request::Parts {
                         headers,
@@ -460,24 +657,50 @@ Usage: {exe} [dirs...]"#
                     body,
                 ) = req.into_parts();
 
-                if uri.path() != "/" {
+                if let Some(q) = uri.query() {
                     return build_http_response(
                         http::StatusCode::BAD_REQUEST,
-                        format!("URI path `{}` not supported", uri.path()),
+                        format!("URI query parameters `{q}` not supported"),
                     );
                 }
-                if let Some(q) = uri.query() {
+                #[cfg(feature = "rpc")]
+                if uri.path() == "/rpc" {
+                    return serve_rpc(&runtime, method, &headers, body, max_body_size).await;
+                }
+                if uri.path() == "/batch" {
+                    return serve_batch(&runtime, method, &headers, body, max_body_size).await;
+                }
+                if uri.path() == "/metrics" {
+                    return serve_metrics(&runtime, &sessions, method);
+                }
+                if uri.path() == "/sessions" {
+                    return serve_sessions(&runtime, &sessions, method, &headers).await;
+                }
+                if uri.path() != "/" {
                     return build_http_response(
                         http::StatusCode::BAD_REQUEST,
-                        format!("URI query parameters `{q}` not supported"),
+                        format!("URI path `{}` not supported", uri.path()),
                     );
                 }
 
+                let session = if let Some(session) = headers.get("X-Session") {
+                    match header_str(session) {
+                        Ok(session) => Some(session),
+                        Err(err) => {
+                            return build_http_response(
+                                http::StatusCode::BAD_REQUEST,
+                                format!("Failed to parse `X-Session` header value: {err:#}"),
+                            );
+                        }
+                    }
+                } else {
+                    None
+                };
                 let Some(contract) = headers.get("X-Contract") else {
                     if method == http::Method::GET {
                         let mut out = String::new();
                         out.push_str("[\n");
-                        for name in components.keys() {
+                        for name in runtime.contracts() {
                             out.push_str("  ");
                             out.push_str(name);
                             out.push_str(",\n");
@@ -501,40 +724,26 @@ Usage: {exe} [dirs...]"#
                         );
                     }
                 };
-                let Some(Workload { pre, ty }) = components.get(contract) else {
-                    return build_http_response(
-                        http::StatusCode::NOT_FOUND,
-                        format!("Contract `{contract}` not found"),
-                    );
-                };
-                let engine = pre.engine();
 
                 match method {
                     http::Method::GET => {
+                        let Some(funcs) = runtime.functions(contract) else {
+                            return build_http_response(
+                                http::StatusCode::NOT_FOUND,
+                                format!("Contract `{contract}` not found"),
+                            );
+                        };
+                        let resources = runtime.resources(contract).unwrap_or_default();
                         let mut out = String::new();
-                        for (name, ty) in ty.exports(engine) {
-                            match ty {
-                                types::ComponentItem::ComponentFunc(ty) => {
-                                    out.push_str(name);
-                                    out.push_str(": ");
-                                    print_func_ty(&mut out, ty);
-                                    out.push('\n');
-                                }
-                                types::ComponentItem::ComponentInstance(ty) => {
-                                    let instance = name;
-                                    for (name, ty) in ty.exports(engine) {
-                                        if let types::ComponentItem::ComponentFunc(ty) = ty {
-                                            out.push_str(instance);
-                                            out.push_str("#");
-                                            out.push_str(name);
-                                            out.push_str(": ");
-                                            print_func_ty(&mut out, ty);
-                                            out.push('\n');
-                                        }
-                                    }
-                                }
-                                _ => continue,
-                            }
+                        for (name, _) in &resources {
+                            out.push_str(name);
+                            out.push_str(": resource\n");
+                        }
+                        for (name, ty) in funcs {
+                            out.push_str(&name);
+                            out.push_str(": ");
+                            print_func_ty(&mut out, ty, &resources);
+                            out.push('\n');
                         }
                         Ok(http::Response::new(http_body_util::Full::new(Bytes::from(
                             out,
@@ -556,37 +765,37 @@ Usage: {exe} [dirs...]"#
                                 );
                             }
                         };
-
-                        let mut store = Store::new(engine, Ctx(None));
-                        if let Some(target) = headers.get("X-Target") {
-                            let target = match header_str(target) {
-                                Ok(target) => target,
+                        let gas_limit = if let Some(gas_limit) = headers.get("X-Gas-Limit") {
+                            match header_str(gas_limit).and_then(|v| {
+                                v.parse::<u64>()
+                                    .context("value is not a valid amount of gas")
+                            }) {
+                                Ok(gas_limit) => Some(gas_limit),
                                 Err(err) => {
                                     return build_http_response(
                                         http::StatusCode::BAD_REQUEST,
-                                        format!("Failed to parse `X-Target` header value: {err:#}"),
+                                        format!(
+                                            "Failed to parse `X-Gas-Limit` header value: {err:#}"
+                                        ),
                                     );
                                 }
-                            };
-                            let Some(Workload { pre, .. }) = components.get(target) else {
-                                return build_http_response(
-                                    http::StatusCode::NOT_FOUND,
-                                    format!("Target component `{target}` not found"),
-                                );
-                            };
-
-                            let target = match pre.instantiate_async(&mut store).await {
-                                Ok(target) => target,
+                            }
+                        } else {
+                            None
+                        };
+                        let target = if let Some(target) = headers.get("X-Target") {
+                            match header_str(target) {
+                                Ok(target) => Some(target),
                                 Err(err) => {
                                     return build_http_response(
                                         http::StatusCode::BAD_REQUEST,
-                                        format!("Failed to instantiate target component: {err:#}"),
+                                        format!("Failed to parse `X-Target` header value: {err:#}"),
                                     );
                                 }
-                            };
-                            store.data_mut().replace(target);
+                            }
+                        } else {
+                            None
                         };
-
                         let Some(codec) = headers.get("X-Codec") else {
                             return build_http_response(
                                 http::StatusCode::BAD_REQUEST,
@@ -602,108 +811,131 @@ Usage: {exe} [dirs...]"#
                                 );
                             }
                         };
-                        let Some(Workload { pre: codec, .. }) = components.get(codec) else {
-                            return build_http_response(
-                                http::StatusCode::NOT_FOUND,
-                                format!("Codec component `{codec}` not found"),
-                            );
-                        };
-                        let codec = match bindings::FormatPre::new(codec.clone()) {
-                            Ok(codec) => codec,
+                        let result_codec = match optional_header(&headers, "X-Result-Codec") {
+                            Ok(result_codec) => result_codec,
                             Err(err) => {
                                 return build_http_response(
-                                    http::StatusCode::NOT_FOUND,
-                                    format!("Failed to cast codec: {err:#}"),
+                                    http::StatusCode::BAD_REQUEST,
+                                    format!("{err:#}"),
                                 );
                             }
                         };
-                        let codec = match codec.instantiate_async(&mut store).await {
-                            Ok(codec) => codec,
-                            Err(err) => {
+                        // Codec components only decode parameters
+                        if let Some(result_codec) = result_codec
+                            && runtime.host_codec(result_codec).is_none()
+                        {
+                            return build_http_response(
+                                http::StatusCode::NOT_ACCEPTABLE,
+                                format!(
+                                    "Results cannot be encoded by codec `{result_codec}`, select a host codec using `X-Result-Codec`"
+                                ),
+                            );
+                        }
+                        let res = if let Some(session) = session {
+                            if target.is_some() {
                                 return build_http_response(
-                                    http::StatusCode::NOT_FOUND,
-                                    format!("Failed to instantiate codec: {err:#}"),
+                                    http::StatusCode::BAD_REQUEST,
+                                    "`X-Target` is only supported on session creation",
                                 );
                             }
-                        };
-                        let (func, ty) = if let Some((instance, func)) = func.split_once('#') {
-                            let Some(types::ComponentItem::ComponentInstance(ty)) =
-                                ty.get_export(engine, instance)
-                            else {
-                                return build_http_response(
-                                    http::StatusCode::NOT_FOUND,
-                                    format!("Instance `{instance}` not found"),
-                                );
+                            // Read the body first, so slow clients do not keep the session busy
+                            let params = match runtime.read_body(body).await {
+                                Ok(params) => params,
+                                Err(err) => {
+                                    return build_http_response(
+                                        error_status(&err),
+                                        err.to_string(),
+                                    );
+                                }
                             };
-                            let Some(types::ComponentItem::ComponentFunc(ty)) =
-                                ty.get_export(engine, func)
-                            else {
-                                return build_http_response(
-                                    http::StatusCode::NOT_FOUND,
-                                    format!("Function `{func}` not found in instance `{instance}`"),
-                                );
+                            let mut session = match sessions.acquire(session, Some(contract)).await
+                            {
+                                Ok(session) => session,
+                                Err(err) => {
+                                    return build_http_response(
+                                        session_error_status(&err),
+                                        err.to_string(),
+                                    );
+                                }
                             };
-
-                            let contract = pre.instantiate_async(&mut store).await?;
-                            let (_, instance) = contract
-                                .get_export(&mut store, None, instance)
-                                .expect("instance export not found");
-                            let (_, func) = contract
-                                .get_export(&mut store, Some(&instance), func)
-                                .expect("function export not found");
-                            let func = contract
-                                .get_func(&mut store, func)
-                                .expect("function not found");
-                            (func, ty)
+                            let res = runtime
+                                .invoke_in(&mut session, func, codec, &params, gas_limit)
+                                .await;
+                            // The contract instance cannot be entered anymore after a trap,
+                            // codec instances trapping during decoding are dropped by the runtime.
+                            if let Err(near_cm::Error::Call { .. }) = res {
+                                session.discard();
+                            }
+                            res
                         } else {
-                            let Some(types::ComponentItem::ComponentFunc(ty)) =
-                                ty.get_export(engine, func)
-                            else {
-                                return build_http_response(
-                                    http::StatusCode::NOT_FOUND,
-                                    format!("Function `{func}` not found"),
-                                );
+                            let opts = InvokeOptions {
+                                target,
+                                gas_limit,
+                                ..InvokeOptions::default()
                             };
-                            let contract = pre.instantiate_async(&mut store).await?;
-                            let func = contract
-                                .get_func(&mut store, func)
-                                .expect("export not found");
-                            (func, ty)
+                            runtime
+                                .invoke_body_with(contract, func, codec, body, opts)
+                                .await
                         };
-                        let params = match deserialize_params(&mut store, &codec, &ty, body).await {
-                            Ok(params) => params,
+                        let res = res.and_then(|outcome| {
+                            let body = if let Some(result_codec) = result_codec {
+                                runtime.encode_results(
+                                    contract,
+                                    func,
+                                    result_codec,
+                                    &outcome.results,
+                                )?
+                            } else {
+                                debug_span!("encode")
+                                    .in_scope(|| format!("{:?}", outcome.results))
+                                    .into()
+                            };
+                            Ok((outcome, body))
+                        });
+                        match res {
+                            Ok((outcome, body)) => {
+                                let mut res = http::Response::new(http_body_util::Full::new(
+                                    Bytes::from(body),
+                                ));
+                                res.headers_mut()
+                                    .insert("X-Gas-Used", outcome.gas_used.into());
+                                append_logs(res.headers_mut(), &outcome.logs);
+                                Ok(res)
+                            }
                             Err(err) => {
-                                return build_http_response(
-                                    http::StatusCode::NOT_FOUND,
-                                    format!("Failed to decode parameters: {err:#}"),
-                                );
+                                let mut res =
+                                    build_http_response(error_status(&err), err.to_string())?;
+                                if let near_cm::Error::Call { gas_used, logs, .. } = err {
+                                    res.headers_mut().insert("X-Gas-Used", gas_used.into());
+                                    append_logs(res.headers_mut(), &logs);
+                                }
+                                Ok(res)
                             }
-                        };
-                        let mut results = vec![Val::Bool(false); ty.results().len()];
-                        if let Err(err) = func.call_async(&mut store, &params, &mut results).await {
-                            return build_http_response(
-                                http::StatusCode::BAD_REQUEST,
-                                format!("Failed to call function: {err:#}"),
-                            );
-                        };
-                        Ok(http::Response::new(http_body_util::Full::new(Bytes::from(
-                            format!("{results:?}"),
-                        ))))
+                        }
                     }
                     method => build_http_response(
                         http::StatusCode::METHOD_NOT_ALLOWED,
                         format!("Method `{method}` not supported"),
                     ),
                 }
+            };
+            async move {
+                let res = res.instrument(span.clone()).await;
+                if let Ok(res) = &res {
+                    span.record("status", res.status().as_u16());
+                }
+                res
             }
         }
     });
     loop {
         let (stream, _) = lis.accept().await?;
-        let conn = srv.serve_connection(TokioIo::new(stream), svc.clone());
+        let conn = srv
+            .serve_connection(TokioIo::new(stream), svc.clone())
+            .with_upgrades();
         tokio::spawn(async {
             if let Err(err) = conn.await {
-                eprintln!("failed to serve connection: {err:?}");
+                warn!("failed to serve connection: {err:?}");
             }
         });
     }
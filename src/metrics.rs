@@ -0,0 +1,280 @@
+//! Operational metrics of a [`Runtime`](crate::Runtime) encoded in the Prometheus
+//! [text exposition format](https://prometheus.io/docs/instrumenting/exposition_formats/)
+//!
+//! Metrics are labeled by contract and function names, which are bounded by the loaded
+//! components. Invocations of unknown contracts or functions are only counted as errors.
+
+use core::fmt::{self, Write as _};
+use core::time::Duration;
+
+use std::collections::BTreeMap;
+use std::sync::{Mutex, PoisonError};
+
+/// Content type of the encoded metrics
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Upper bounds of the phase duration histogram buckets in seconds
+const DURATION_BUCKETS: [f64; 11] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.05, 0.25, 1.0, 10.0,
+];
+
+/// Phase of an invocation
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Phase {
+    /// Instantiation of the contract, target and codec components
+    Instantiate,
+    /// Parameter decoding
+    Decode,
+    /// Function call
+    Execute,
+}
+
+impl Phase {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Instantiate => "instantiate",
+            Self::Decode => "decode",
+            Self::Execute => "execute",
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Histogram {
+    /// Number of observations per bucket, the last one being `+Inf`
+    buckets: [u64; DURATION_BUCKETS.len() + 1],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, v: f64) {
+        let i = DURATION_BUCKETS.partition_point(|le| *le < v);
+        self.buckets[i] += 1;
+        self.sum += v;
+        self.count += 1;
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// Number of calls and gas used by contract and function
+    calls: BTreeMap<(Box<str>, Box<str>), (u64, u64)>,
+    errors: BTreeMap<&'static str, u64>,
+    phases: BTreeMap<(Box<str>, Phase), Histogram>,
+    memory: BTreeMap<Box<str>, usize>,
+}
+
+/// Metrics recorded by invocations
+#[derive(Debug, Default)]
+pub struct Metrics(Mutex<Inner>);
+
+impl Metrics {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Records a call of function `func` of `contract`, which used `gas_used` gas.
+    pub fn record_call(&self, contract: &str, func: &str, gas_used: u64) {
+        let mut inner = self.lock();
+        let (calls, gas) = inner
+            .calls
+            .entry((contract.into(), func.into()))
+            .or_default();
+        *calls += 1;
+        *gas = gas.saturating_add(gas_used);
+    }
+
+    /// Records a failed invocation with error kind `kind`, see [`Error::kind`](crate::Error::kind).
+    pub fn record_error(&self, kind: &'static str) {
+        *self.lock().errors.entry(kind).or_default() += 1;
+    }
+
+    /// Records that `phase` of an invocation of `contract` took `duration`.
+    pub fn record_phase(&self, contract: &str, phase: Phase, duration: Duration) {
+        self.lock()
+            .phases
+            .entry((contract.into(), phase))
+            .or_default()
+            .observe(duration.as_secs_f64());
+    }
+
+    /// Records that a linear memory of an instance of `contract` grew to `size` bytes.
+    pub fn record_memory(&self, contract: &str, size: usize) {
+        let mut inner = self.lock();
+        if let Some(max) = inner.memory.get_mut(contract) {
+            *max = (*max).max(size);
+        } else {
+            inner.memory.insert(contract.into(), size);
+        }
+    }
+
+    /// Appends all recorded metrics to `out`.
+    pub fn encode(&self, out: &mut String) {
+        let inner = self.lock();
+        let Inner {
+            calls,
+            errors,
+            phases,
+            memory,
+        } = &*inner;
+        write_header(
+            out,
+            "near_cm_calls_total",
+            "counter",
+            "Number of function calls",
+        );
+        for ((contract, func), (n, _)) in calls {
+            write_sample(
+                out,
+                "near_cm_calls_total",
+                &[("contract", contract), ("func", func)],
+                n,
+            );
+        }
+        write_header(
+            out,
+            "near_cm_gas_used_total",
+            "counter",
+            "Amount of gas used by function calls",
+        );
+        for ((contract, func), (_, gas)) in calls {
+            write_sample(
+                out,
+                "near_cm_gas_used_total",
+                &[("contract", contract), ("func", func)],
+                gas,
+            );
+        }
+        write_header(
+            out,
+            "near_cm_errors_total",
+            "counter",
+            "Number of failed invocations by error kind",
+        );
+        for (kind, n) in errors {
+            write_sample(out, "near_cm_errors_total", &[("kind", kind)], n);
+        }
+        write_header(
+            out,
+            "near_cm_phase_duration_seconds",
+            "histogram",
+            "Duration of invocation phases in seconds",
+        );
+        for ((contract, phase), hist) in phases {
+            let labels = [("contract", &**contract), ("phase", phase.as_str())];
+            let mut n = 0;
+            for (i, count) in hist.buckets.iter().enumerate() {
+                n += count;
+                let le = DURATION_BUCKETS
+                    .get(i)
+                    .map_or_else(|| "+Inf".into(), f64::to_string);
+                write_sample(
+                    out,
+                    "near_cm_phase_duration_seconds_bucket",
+                    &[labels[0], labels[1], ("le", &le)],
+                    n,
+                );
+            }
+            write_sample(out, "near_cm_phase_duration_seconds_sum", &labels, hist.sum);
+            write_sample(
+                out,
+                "near_cm_phase_duration_seconds_count",
+                &labels,
+                hist.count,
+            );
+        }
+        write_header(
+            out,
+            "near_cm_memory_high_water_bytes",
+            "gauge",
+            "Largest size of a linear memory of a contract instance in bytes",
+        );
+        for (contract, size) in memory {
+            write_sample(
+                out,
+                "near_cm_memory_high_water_bytes",
+                &[("contract", contract)],
+                size,
+            );
+        }
+    }
+}
+
+/// Appends the `HELP` and `TYPE` lines of metric `name` to `out`.
+fn write_header(out: &mut String, name: &str, ty: &str, help: &str) {
+    writeln!(out, "# HELP {name} {help}\n# TYPE {name} {ty}").expect("failed to write to string");
+}
+
+/// Appends a sample of metric `name` with `labels` and `value` to `out`.
+fn write_sample(out: &mut String, name: &str, labels: &[(&str, &str)], value: impl fmt::Display) {
+    out.push_str(name);
+    for (i, (label, v)) in labels.iter().enumerate() {
+        out.push(if i == 0 { '{' } else { ',' });
+        out.push_str(label);
+        out.push_str("=\"");
+        for c in v.chars() {
+            match c {
+                '\\' => out.push_str(r"\\"),
+                '"' => out.push_str(r#"\""#),
+                '\n' => out.push_str(r"\n"),
+                c => out.push(c),
+            }
+        }
+        out.push('"');
+    }
+    if !labels.is_empty() {
+        out.push('}');
+    }
+    writeln!(out, " {value}").expect("failed to write to string");
+}
+
+/// Appends gauge `name` with `help` and unlabeled `value` to `out`.
+pub fn encode_gauge(out: &mut String, name: &str, help: &str, value: impl fmt::Display) {
+    write_header(out, name, "gauge", help);
+    write_sample(out, name, &[], value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metrics_are_prometheus_text() {
+        let metrics = Metrics::default();
+        metrics.record_call("c", "f", 10);
+        metrics.record_call("c", "f", 5);
+        metrics.record_error("out_of_gas");
+        metrics.record_phase("c", Phase::Decode, Duration::from_micros(300));
+        metrics.record_phase("c", Phase::Decode, Duration::from_secs(20));
+        metrics.record_memory("c\"", 1 << 16);
+        metrics.record_memory("c\"", 1 << 10);
+        let mut out = String::default();
+        metrics.encode(&mut out);
+        encode_gauge(
+            &mut out,
+            "near_cm_contracts_loaded",
+            "Number of contracts",
+            2,
+        );
+        for line in [
+            "# TYPE near_cm_calls_total counter",
+            r#"near_cm_calls_total{contract="c",func="f"} 2"#,
+            r#"near_cm_gas_used_total{contract="c",func="f"} 15"#,
+            r#"near_cm_errors_total{kind="out_of_gas"} 1"#,
+            "# TYPE near_cm_phase_duration_seconds histogram",
+            r#"near_cm_phase_duration_seconds_bucket{contract="c",phase="decode",le="0.00025"} 0"#,
+            r#"near_cm_phase_duration_seconds_bucket{contract="c",phase="decode",le="0.0005"} 1"#,
+            r#"near_cm_phase_duration_seconds_bucket{contract="c",phase="decode",le="10"} 1"#,
+            r#"near_cm_phase_duration_seconds_bucket{contract="c",phase="decode",le="+Inf"} 2"#,
+            r#"near_cm_phase_duration_seconds_count{contract="c",phase="decode"} 2"#,
+            r#"near_cm_memory_high_water_bytes{contract="c\""} 65536"#,
+            "near_cm_contracts_loaded 2",
+        ] {
+            assert!(
+                out.lines().any(|l| l == line),
+                "`{line}` missing in:\n{out}"
+            );
+        }
+    }
+}
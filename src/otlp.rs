@@ -0,0 +1,36 @@
+//! Export of [`tracing`] spans to an OpenTelemetry collector using
+//! [OTLP/HTTP](https://opentelemetry.io/docs/specs/otlp/#otlphttp) with protobuf encoding
+//!
+//! Spans are bridged by [`tracing_opentelemetry`] and exported in batches by
+//! [`opentelemetry_sdk`] on a background thread. Spans are dropped if the collector cannot
+//! keep up or is unreachable, exporting never blocks the instrumented code.
+
+use anyhow::Context as _;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::SpanExporter;
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::trace::{SdkTracer, SdkTracerProvider};
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::registry::LookupSpan;
+
+/// Returns a layer exporting spans of service `service_name`.
+///
+/// The collector endpoint and headers are configured by the standard `OTEL_EXPORTER_OTLP_*`
+/// environment variables, e.g. `OTEL_EXPORTER_OTLP_ENDPOINT`, which defaults to
+/// `http://localhost:4318`.
+pub fn layer<S>(service_name: &'static str) -> anyhow::Result<OpenTelemetryLayer<S, SdkTracer>>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .build()
+        .context("failed to build OTLP span exporter")?;
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(service_name).build())
+        .build();
+    let tracer = provider.tracer(service_name);
+    Ok(tracing_opentelemetry::layer().with_tracer(tracer))
+}
@@ -0,0 +1,42 @@
+//! Panic messages reported by contracts using the `near-cm:host/panic` interface
+
+use core::fmt;
+
+use std::sync::Arc;
+
+use wasmtime::StoreContextMut;
+use wasmtime::component::Linker;
+
+/// Error trapping an invocation, in which a contract panicked with a reported message
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GuestPanic {
+    /// Name of the panicking contract
+    pub contract: Arc<str>,
+    pub message: String,
+}
+
+impl fmt::Display for GuestPanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { contract, message } = self;
+        write!(f, "contract `{contract}` panicked: {message}")
+    }
+}
+
+impl std::error::Error for GuestPanic {}
+
+/// Defines `near-cm:host/panic` in `linker`, attributing all panics to `contract`.
+pub fn add_to_linker<T: 'static>(linker: &mut Linker<T>, contract: &str) -> wasmtime::Result<()> {
+    let contract = Arc::<str>::from(contract);
+    let mut linker = linker.instance("near-cm:host/panic@0.1.0")?;
+    linker.func_wrap(
+        "abort",
+        move |_: StoreContextMut<'_, T>, (message,): (String,)| -> wasmtime::Result<()> {
+            Err(GuestPanic {
+                contract: Arc::clone(&contract),
+                message,
+            }
+            .into())
+        },
+    )?;
+    Ok(())
+}
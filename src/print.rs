@@ -0,0 +1,118 @@
+//! Printing of component types in WIT-like syntax
+
+use wasmtime::component::{ResourceType, Type, types};
+
+/// Prints function type `ty`, naming resources after `resources`,
+/// see [`Runtime::resources`](crate::Runtime::resources).
+#[expect(clippy::single_char_add_str)]
+pub fn print_func_ty(
+    out: &mut String,
+    ty: types::ComponentFunc,
+    resources: &[(String, ResourceType)],
+) {
+    out.push_str("func(");
+    let mut params = ty.params();
+    if let Some((name, ty)) = params.next() {
+        out.push_str(name);
+        out.push_str(": ");
+        print_ty(out, ty, resources);
+        for (name, ty) in params {
+            out.push_str(", ");
+            out.push_str(name);
+            out.push_str(": ");
+            print_ty(out, ty, resources);
+        }
+    }
+    out.push_str(")");
+    let mut results = ty.results();
+    if let Some(ty) = results.next() {
+        out.push_str(" -> ");
+        print_ty(out, ty, resources);
+        for ty in results {
+            out.push_str(", ");
+            print_ty(out, ty, resources);
+        }
+    }
+}
+
+#[expect(clippy::single_char_add_str)]
+pub fn print_ty(out: &mut String, ty: Type, resources: &[(String, ResourceType)]) {
+    #[expect(unused)]
+    match ty {
+        Type::Bool => out.push_str("bool"),
+        Type::S8 => out.push_str("s8"),
+        Type::U8 => out.push_str("u8"),
+        Type::S16 => out.push_str("s16"),
+        Type::U16 => out.push_str("u16"),
+        Type::S32 => out.push_str("s32"),
+        Type::U32 => out.push_str("u32"),
+        Type::S64 => out.push_str("s64"),
+        Type::U64 => out.push_str("u64"),
+        Type::Float32 => out.push_str("float32"),
+        Type::Float64 => out.push_str("float64"),
+        Type::Char => out.push_str("char"),
+        Type::String => out.push_str("string"),
+        Type::List(ty) => {
+            out.push_str("list<");
+            print_ty(out, ty.ty(), resources);
+            out.push_str(">");
+        }
+        Type::Record(ty) => {
+            out.push_str("record{");
+            let mut fields = ty.fields();
+            if let Some(types::Field { name, ty }) = fields.next() {
+                out.push_str(name);
+                out.push_str(": ");
+                print_ty(out, ty, resources);
+                for types::Field { name, ty } in fields {
+                    out.push_str(", ");
+                    out.push_str(name);
+                    out.push_str(": ");
+                    print_ty(out, ty, resources);
+                }
+            }
+            out.push_str("}");
+        }
+        Type::Tuple(ty) => {
+            out.push_str("tuple<");
+            let mut tys = ty.types();
+            if let Some(ty) = tys.next() {
+                print_ty(out, ty, resources);
+                for ty in tys {
+                    out.push_str(", ");
+                    print_ty(out, ty, resources);
+                }
+            }
+            out.push_str(">");
+        }
+        Type::Variant(variant) => out.push_str("variant"),
+        Type::Enum(_) => out.push_str("enum"),
+        Type::Option(option_type) => out.push_str("option"),
+        Type::Result(result_type) => out.push_str("result"),
+        Type::Flags(flags) => out.push_str("flags"),
+        Type::Own(ty) => {
+            out.push_str("own<");
+            print_resource(out, &ty, resources);
+            out.push_str(">");
+        }
+        Type::Borrow(ty) => {
+            out.push_str("borrow<");
+            print_resource(out, &ty, resources);
+            out.push_str(">");
+        }
+        Type::Future(future_type) => out.push_str("future"),
+        Type::Stream(stream_type) => out.push_str("stream"),
+        Type::ErrorContext => out.push_str("error-context"),
+    }
+}
+
+fn print_resource(out: &mut String, ty: &ResourceType, resources: &[(String, ResourceType)]) {
+    let name = resources
+        .iter()
+        .find(|(_, other)| other == ty)
+        .map(|(name, _)| {
+            name.rsplit_once('#')
+                .map_or(name.as_str(), |(_, name)| name)
+        });
+    out.push_str(name.unwrap_or("resource"));
+}
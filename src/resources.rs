@@ -0,0 +1,184 @@
+//! Resource handles passed to and returned by contracts
+//!
+//! Codecs decode `own` and `borrow` handles as `u32` handle IDs, which are resolved
+//! against the [`ResourceTable`] of the invocation before calling the contract.
+//! Owned resources returned by the contract are inserted into the table and returned
+//! to the caller as `u32` handle IDs in turn.
+
+use std::collections::BTreeMap;
+
+use anyhow::{Context as _, bail, ensure};
+use wasmtime::component::{ResourceAny, Type, Val, types};
+
+/// Table of resources held by the host on behalf of the caller, keyed by opaque handle IDs
+#[derive(Debug, Default)]
+pub struct ResourceTable {
+    resources: BTreeMap<u32, ResourceAny>,
+    next_id: u32,
+}
+
+impl ResourceTable {
+    /// Inserts resource `resource` and returns its handle ID.
+    pub fn insert(&mut self, resource: ResourceAny) -> anyhow::Result<u32> {
+        let id = self.next_id;
+        self.next_id = id.checked_add(1).context("handle IDs exhausted")?;
+        self.resources.insert(id, resource);
+        Ok(id)
+    }
+
+    /// Returns resource with handle ID `id`.
+    pub fn get(&self, id: u32) -> Option<ResourceAny> {
+        self.resources.get(&id).copied()
+    }
+
+    /// Removes resource with handle ID `id` from the table and returns it.
+    pub fn remove(&mut self, id: u32) -> Option<ResourceAny> {
+        self.resources.remove(&id)
+    }
+
+    /// Replaces handle IDs in parameters `params` of types `tys` by resources.
+    ///
+    /// Parameter types have to be obtained from the instance, see [`Func::params`],
+    /// since resource types of instances differ from the ones of the component.
+    /// Resources passed as `own` are removed from the table, since ownership is transferred
+    /// to the contract.
+    ///
+    /// [`Func::params`]: wasmtime::component::Func::params
+    pub fn lift_params(
+        &mut self,
+        tys: &[(String, Type)],
+        params: Vec<Val>,
+    ) -> anyhow::Result<Vec<Val>> {
+        let mut owned = Vec::default();
+        let params = tys
+            .iter()
+            .zip(params)
+            .map(|((name, ty), v)| {
+                if !has_resources(ty) {
+                    return Ok(v);
+                }
+                self.lift(ty, v, &mut owned)
+                    .with_context(|| format!("failed to resolve handles of param `{name}`"))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        for id in owned {
+            self.resources.remove(&id);
+        }
+        Ok(params)
+    }
+
+    fn lift(&self, ty: &Type, v: Val, owned: &mut Vec<u32>) -> anyhow::Result<Val> {
+        match (ty, v) {
+            (Type::Own(rty) | Type::Borrow(rty), Val::U32(id)) => {
+                let resource = self
+                    .get(id)
+                    .with_context(|| format!("handle `{id}` not found"))?;
+                ensure!(
+                    resource.ty() == *rty,
+                    "handle `{id}` refers to a resource of a different type"
+                );
+                if matches!(ty, Type::Own(..)) {
+                    ensure!(!owned.contains(&id), "handle `{id}` passed as `own` twice");
+                    owned.push(id);
+                }
+                Ok(Val::Resource(resource))
+            }
+            (Type::Own(..) | Type::Borrow(..), _) => bail!("handle is not a `u32` ID"),
+            (Type::List(ty), Val::List(vs)) => {
+                let ty = ty.ty();
+                if !has_resources(&ty) {
+                    return Ok(Val::List(vs));
+                }
+                let vs = vs
+                    .into_iter()
+                    .map(|v| self.lift(&ty, v, owned))
+                    .collect::<anyhow::Result<_>>()?;
+                Ok(Val::List(vs))
+            }
+            (Type::Record(ty), Val::Record(fields)) => {
+                let fields = ty
+                    .fields()
+                    .zip(fields)
+                    .map(|(types::Field { ty, .. }, (name, v))| {
+                        Ok((name, self.lift(&ty, v, owned)?))
+                    })
+                    .collect::<anyhow::Result<_>>()?;
+                Ok(Val::Record(fields))
+            }
+            (Type::Tuple(ty), Val::Tuple(vs)) => {
+                let vs = ty
+                    .types()
+                    .zip(vs)
+                    .map(|(ty, v)| self.lift(&ty, v, owned))
+                    .collect::<anyhow::Result<_>>()?;
+                Ok(Val::Tuple(vs))
+            }
+            (Type::Variant(ty), Val::Variant(name, Some(v))) => {
+                let ty = ty
+                    .cases()
+                    .find(|case| case.name == name)
+                    .and_then(|case| case.ty)
+                    .with_context(|| format!("unknown case `{name}`"))?;
+                let v = self.lift(&ty, *v, owned)?;
+                Ok(Val::Variant(name, Some(Box::new(v))))
+            }
+            (Type::Option(ty), Val::Option(Some(v))) => {
+                let v = self.lift(&ty.ty(), *v, owned)?;
+                Ok(Val::Option(Some(Box::new(v))))
+            }
+            (Type::Result(ty), Val::Result(Ok(Some(v)))) => {
+                let ty = ty.ok().context("unexpected `ok` payload")?;
+                let v = self.lift(&ty, *v, owned)?;
+                Ok(Val::Result(Ok(Some(Box::new(v)))))
+            }
+            (Type::Result(ty), Val::Result(Err(Some(v)))) => {
+                let ty = ty.err().context("unexpected `err` payload")?;
+                let v = self.lift(&ty, *v, owned)?;
+                Ok(Val::Result(Err(Some(Box::new(v)))))
+            }
+            (_, v) => Ok(v),
+        }
+    }
+
+    /// Replaces resources in results `results` by handle IDs of the resources
+    /// inserted into the table.
+    pub fn lower_results(&mut self, results: &mut [Val]) -> anyhow::Result<()> {
+        results.iter_mut().try_for_each(|v| self.lower(v))
+    }
+
+    fn lower(&mut self, v: &mut Val) -> anyhow::Result<()> {
+        match v {
+            Val::Resource(resource) => {
+                ensure!(resource.owned(), "borrowed resources cannot be returned");
+                *v = Val::U32(self.insert(*resource)?);
+                Ok(())
+            }
+            Val::List(vs) | Val::Tuple(vs) => vs.iter_mut().try_for_each(|v| self.lower(v)),
+            Val::Record(fields) => fields.iter_mut().try_for_each(|(_, v)| self.lower(v)),
+            Val::Variant(_, Some(v))
+            | Val::Option(Some(v))
+            | Val::Result(Ok(Some(v)) | Err(Some(v))) => self.lower(v),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Returns `true` if values of type `ty` may contain resource handles.
+pub(crate) fn has_resources(ty: &Type) -> bool {
+    match ty {
+        Type::Own(..) | Type::Borrow(..) => true,
+        Type::List(ty) => has_resources(&ty.ty()),
+        Type::Record(ty) => ty.fields().any(|field| has_resources(&field.ty)),
+        Type::Tuple(ty) => ty.types().any(|ty| has_resources(&ty)),
+        Type::Variant(ty) => ty
+            .cases()
+            .any(|case| case.ty.is_some_and(|ty| has_resources(&ty))),
+        Type::Option(ty) => has_resources(&ty.ty()),
+        Type::Result(ty) => ty
+            .ok()
+            .into_iter()
+            .chain(ty.err())
+            .any(|ty| has_resources(&ty)),
+        _ => false,
+    }
+}
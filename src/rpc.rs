@@ -0,0 +1,229 @@
+//! [NEAR JSON-RPC](https://docs.near.org/api/rpc/contracts#call-a-contract-function) interface
+//!
+//! Only the `query` method with `request_type` `call_function` is supported, which allows
+//! existing NEAR clients, such as `near-api-js`, to call functions of loaded contracts.
+//! `account_id` names the contract and `method_name` the function. Functions exported by
+//! instances may also be named without the instance, e.g. `add` for
+//! `myapp:app/custom@0.1.0#add`, as long as the name is unambiguous and no function is named
+//! exactly like it, and `snake_case` names match `kebab-case` WIT names. `args_base64` holds the parameters encoded using a host codec,
+//! which also encodes the return value into `result`, like NEAR contracts do.
+//! Calls are view calls, see [`InvokeOptions::view`], so contracts cannot change state or
+//! emit events. Block references, such as `finality`, are ignored.
+
+use base64::Engine as _;
+use base64::prelude::BASE64_STANDARD;
+use serde_json::{Value, json};
+
+use crate::{InvokeOptions, Runtime};
+
+/// Block hash reported in responses, there are no blocks
+const BLOCK_HASH: &str = "11111111111111111111111111111111";
+
+/// JSON-RPC error in the shape returned by NEAR RPC nodes
+struct Error {
+    code: i64,
+    message: &'static str,
+    name: &'static str,
+    cause: &'static str,
+    info: Value,
+    data: String,
+}
+
+impl Error {
+    fn parse(msg: impl Into<String>) -> Self {
+        let msg = msg.into();
+        Self {
+            code: -32700,
+            message: "Parse error",
+            name: "REQUEST_VALIDATION_ERROR",
+            cause: "PARSE_ERROR",
+            info: json!({ "error_message": msg }),
+            data: msg,
+        }
+    }
+
+    fn method_not_found(method: &str) -> Self {
+        Self {
+            code: -32601,
+            message: "Method not found",
+            name: "REQUEST_VALIDATION_ERROR",
+            cause: "METHOD_NOT_FOUND",
+            info: json!({ "method_name": method }),
+            data: method.into(),
+        }
+    }
+
+    fn unknown_account(account_id: &str) -> Self {
+        Self {
+            code: -32000,
+            message: "Server error",
+            name: "HANDLER_ERROR",
+            cause: "UNKNOWN_ACCOUNT",
+            info: json!({
+                "requested_account_id": account_id,
+                "block_height": 0,
+                "block_hash": BLOCK_HASH,
+            }),
+            data: format!("account {account_id} does not exist while viewing"),
+        }
+    }
+
+    fn execution(msg: String) -> Self {
+        Self {
+            code: -32000,
+            message: "Server error",
+            name: "HANDLER_ERROR",
+            cause: "CONTRACT_EXECUTION_ERROR",
+            info: json!({
+                "vm_error": msg,
+                "block_height": 0,
+                "block_hash": BLOCK_HASH,
+            }),
+            data: msg,
+        }
+    }
+
+    fn into_json(self) -> Value {
+        json!({
+            "name": self.name,
+            "cause": { "name": self.cause, "info": self.info },
+            "code": self.code,
+            "message": self.message,
+            "data": self.data,
+        })
+    }
+}
+
+/// Handles JSON-RPC request `req` using host codec `codec` and returns the JSON response.
+pub async fn handle(runtime: &Runtime, codec: &str, req: &[u8]) -> Vec<u8> {
+    let (id, res) = match serde_json::from_slice::<Value>(req) {
+        Ok(req) => {
+            let id = req.get("id").cloned().unwrap_or_default();
+            (id, call(runtime, codec, &req).await)
+        }
+        Err(err) => (Value::Null, Err(Error::parse(err.to_string()))),
+    };
+    let mut res = match res {
+        Ok(result) => json!({ "result": result }),
+        Err(err) => json!({ "error": err.into_json() }),
+    };
+    res["jsonrpc"] = "2.0".into();
+    res["id"] = id;
+    serde_json::to_vec(&res).expect("failed to encode JSON-RPC response")
+}
+
+async fn call(runtime: &Runtime, codec: &str, req: &Value) -> Result<Value, Error> {
+    let method = req
+        .get("method")
+        .and_then(Value::as_str)
+        .ok_or_else(|| Error::parse("field `method` missing"))?;
+    if method != "query" {
+        return Err(Error::method_not_found(method));
+    }
+    let params = req
+        .get("params")
+        .and_then(Value::as_object)
+        .ok_or_else(|| Error::parse("field `params` missing"))?;
+    let field = |name: &str| {
+        params
+            .get(name)
+            .and_then(Value::as_str)
+            .ok_or_else(|| Error::parse(format!("field `{name}` missing")))
+    };
+    let request_type = field("request_type")?;
+    if request_type != "call_function" {
+        return Err(Error::parse(format!(
+            "request type `{request_type}` not supported"
+        )));
+    }
+    let contract = field("account_id")?;
+    let method_name = field("method_name")?;
+    let args = BASE64_STANDARD
+        .decode(field("args_base64")?)
+        .map_err(|err| Error::parse(format!("invalid `args_base64`: {err}")))?;
+
+    let Some(funcs) = runtime.functions(contract) else {
+        return Err(Error::unknown_account(contract));
+    };
+    let Some((func, ty)) = resolve(funcs, method_name) else {
+        return Err(Error::execution(format!(
+            "MethodResolveError(MethodNotFound): `{method_name}`"
+        )));
+    };
+    let Some(host_codec) = runtime.host_codec(codec) else {
+        return Err(Error::execution(format!("host codec `{codec}` not found")));
+    };
+    let opts = InvokeOptions {
+        view: true,
+        ..InvokeOptions::default()
+    };
+    let outcome = runtime
+        .invoke_with(contract, &func, codec, &args, opts)
+        .await
+        .map_err(|err| Error::execution(err.to_string()))?;
+    let result = match (ty.results().next(), outcome.results.first()) {
+        (Some(ty), Some(v)) => tracing::debug_span!("encode", codec)
+            .in_scope(|| host_codec.encode_value(&ty, v))
+            .map_err(|err| Error::execution(format!("failed to encode result: {err:#}")))?,
+        _ => Vec::default(),
+    };
+    Ok(json!({
+        "result": result,
+        "logs": outcome.logs,
+        "block_height": 0,
+        "block_hash": BLOCK_HASH,
+    }))
+}
+
+/// Returns the function of `funcs` named `method_name`, see [module documentation](self).
+fn resolve<T>(funcs: Vec<(String, T)>, method_name: &str) -> Option<(String, T)> {
+    let name = method_name.replace('_', "-");
+    let (exact, other): (Vec<_>, Vec<_>) =
+        funcs.into_iter().partition(|(func, _)| func == method_name);
+    if let Some(func) = exact.into_iter().next() {
+        return Some(func);
+    }
+    let mut matches = other.into_iter().filter(|(func, _)| {
+        let short = func
+            .rsplit_once('#')
+            .map_or(func.as_str(), |(_, name)| name);
+        short == method_name || short == name
+    });
+    let func = matches.next()?;
+    if matches.next().is_some() {
+        return None;
+    }
+    Some(func)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn method_names_resolve_to_unambiguous_functions() {
+        let funcs = || {
+            vec![
+                ("myapp:app/custom@0.1.0#get-greeting".to_string(), ()),
+                ("a#add".to_string(), ()),
+                ("b#add".to_string(), ()),
+                ("add".to_string(), ()),
+                ("a#mul".to_string(), ()),
+                ("b#mul".to_string(), ()),
+            ]
+        };
+        let resolve = |name| resolve(funcs(), name).map(|(func, ())| func);
+        assert_eq!(
+            resolve("get_greeting").as_deref(),
+            Some("myapp:app/custom@0.1.0#get-greeting")
+        );
+        assert_eq!(
+            resolve("myapp:app/custom@0.1.0#get-greeting").as_deref(),
+            Some("myapp:app/custom@0.1.0#get-greeting")
+        );
+        assert_eq!(resolve("a#add").as_deref(), Some("a#add"));
+        assert_eq!(resolve("add").as_deref(), Some("add"));
+        assert_eq!(resolve("mul"), None);
+        assert_eq!(resolve("sub"), None);
+    }
+}
@@ -0,0 +1,2432 @@
+use core::time::Duration;
+use core::{fmt, mem};
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Instant;
+
+use anyhow::{Context as _, bail};
+use bytes::{Buf, BufMut as _, Bytes};
+use http_body::Body;
+use http_body_util::{BodyExt as _, Empty};
+use tracing::{Instrument as _, debug_span};
+use wasmtime::component::{
+    Component, Func, Instance, InstancePre, Linker, ResourceType, Val, types,
+};
+use wasmtime::{Engine, Store, Trap};
+use wit_component::ComponentEncoder;
+
+use crate::codec::{CodecPre, ParamsDecoder, TypePlan};
+use crate::codecs::{self, BatchCall, BatchOutcome, HostCodec};
+use crate::events::{self, Event, Events};
+use crate::limits::{LimitExceeded, Limiter, Limits};
+use crate::log::{self, Logs};
+use crate::metrics::{self, Metrics, Phase};
+use crate::panic::{self, GuestPanic};
+use crate::resources::{ResourceTable, has_resources};
+use crate::storage::{self, Storage};
+use crate::{codec, config};
+
+/// Default maximum amount of gas a single invocation can use, in units of Wasmtime fuel.
+pub const DEFAULT_MAX_GAS: u64 = 1_000_000_000;
+
+/// Default wall-clock deadline of a single invocation.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Default maximum size of encoded parameters of a single invocation in bytes.
+pub const DEFAULT_MAX_BODY_SIZE: usize = 4 << 20;
+
+/// Default maximum number of idle instances pooled per stateless contract.
+pub const DEFAULT_POOL_SIZE: usize = 16;
+
+/// Default maximum number of invocations served by a pooled instance.
+pub const DEFAULT_MAX_POOLED_USES: usize = 1_000;
+
+/// Default maximum number of elements of a table of a pooled instance.
+pub const DEFAULT_MAX_POOLED_TABLE_ELEMENTS: usize = 5_000;
+
+/// Default maximum number of invocations in a batch.
+pub const DEFAULT_MAX_BATCH_LEN: usize = 64;
+
+/// Default maximum number of concurrently running invocations of a parallel batch.
+pub const DEFAULT_BATCH_CONCURRENCY: usize = 8;
+
+/// Default maximum amount of gas all invocations of a batch can use in total.
+pub const DEFAULT_MAX_BATCH_GAS: u64 = 10 * DEFAULT_MAX_GAS;
+
+/// Interval, at which the engine epoch is incremented.
+///
+/// This determines the granularity of invocation deadlines.
+const EPOCH_TICK: Duration = Duration::from_millis(10);
+
+/// [`Runtime`] options
+#[derive(Clone, Copy, Debug)]
+pub struct Options {
+    /// Maximum amount of gas a single invocation can use, in units of Wasmtime fuel
+    pub max_gas: u64,
+    /// Wall-clock deadline of a single invocation
+    pub timeout: Duration,
+    /// Resource limits of a single invocation
+    pub limits: Limits,
+    /// Maximum size of encoded parameters of a single invocation in bytes
+    pub max_body_size: usize,
+    /// Maximum number of idle instances pooled per stateless contract,
+    /// see [`ContractOptions::stateless`]
+    pub pool_size: usize,
+    /// Maximum number of invocations served by a pooled instance, before it is retired
+    pub max_pooled_uses: usize,
+    /// Maximum number of elements of a table of a pooled instance, instances with larger
+    /// tables are retired, see [`Limits::table_elements`]
+    pub max_pooled_table_elements: usize,
+    /// Maximum number of invocations in a batch, see [`Runtime::invoke_batch`]
+    pub max_batch_len: usize,
+    /// Maximum number of concurrently running invocations of a parallel batch
+    pub batch_concurrency: usize,
+    /// Maximum amount of gas all invocations of a batch can use in total
+    pub max_batch_gas: u64,
+    /// Whether errors of failed invocations include Wasm backtraces with symbol names
+    /// and, if contracts are built with debug info, source locations
+    pub debug: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            max_gas: DEFAULT_MAX_GAS,
+            timeout: DEFAULT_TIMEOUT,
+            limits: Limits::default(),
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            pool_size: DEFAULT_POOL_SIZE,
+            max_pooled_uses: DEFAULT_MAX_POOLED_USES,
+            max_pooled_table_elements: DEFAULT_MAX_POOLED_TABLE_ELEMENTS,
+            max_batch_len: DEFAULT_MAX_BATCH_LEN,
+            batch_concurrency: DEFAULT_BATCH_CONCURRENCY,
+            max_batch_gas: DEFAULT_MAX_BATCH_GAS,
+            debug: false,
+        }
+    }
+}
+
+/// [`Runtime::load_contract_with`] options
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ContractOptions {
+    /// Whether the contract keeps no in-memory state between invocations.
+    ///
+    /// Instances of stateless contracts are returned to a pool after successful invocations
+    /// without a target component and reused by later invocations instead of instantiating
+    /// the contract anew.
+    ///
+    /// Pooled instances are reused as they are: linear memories, globals and tables of the
+    /// contract and of the codec instances cached with it are not reset, and neither are
+    /// memory high-water marks, which keep reporting the size of the instance's memories.
+    /// Instances are retired once they served [`Options::max_pooled_uses`] invocations or
+    /// their tables grew beyond [`Options::max_pooled_table_elements`], which bounds the
+    /// growth of state leaked by the contract or its codecs.
+    /// Only storage transactions, events and logs are per invocation. Declaring a contract
+    /// stateless is therefore an obligation of the contract, invocations must neither depend
+    /// on nor leave behind in-memory state, and the same applies to codec components used
+    /// with it.
+    pub stateless: bool,
+}
+
+/// [`Runtime::invoke_with`] options
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InvokeOptions<'a> {
+    /// Component, which root function imports of the contract are forwarded to
+    pub target: Option<&'a str>,
+    /// Maximum amount of gas the invoked function can use, clamped to [`Options::max_gas`].
+    /// Defaults to [`Options::max_gas`].
+    pub gas_limit: Option<u64>,
+    /// Whether the invocation is a view call, like NEAR view calls, which fails on attempts
+    /// to change state or emit events. State changes made during instantiation are discarded.
+    pub view: bool,
+}
+
+/// Result of a successful invocation
+#[derive(Clone, Debug)]
+pub struct Outcome {
+    /// Values returned by the invoked function
+    pub results: Vec<Val>,
+    /// Amount of gas used by the invoked function
+    pub gas_used: u64,
+    /// Messages logged during the invocation, see [`log`](crate::log)
+    pub logs: Vec<String>,
+}
+
+/// Contract instance, which keeps its in-memory state and resources between invocations
+///
+/// Sessions are created by [`Runtime::new_session`] and invoked by [`Runtime::invoke_in`].
+/// Resources returned by the contract are kept in a per-session resource table
+/// and returned to the caller as `u32` handle IDs, which can be passed back as `own`
+/// or `borrow` parameters in later invocations within the same session.
+pub struct Session {
+    contract: Box<str>,
+    store: Store<Ctx>,
+    instance: Instance,
+    /// Codec component instances by name, instantiated on first use
+    codecs: HashMap<Box<str>, codec::Codec>,
+    resources: ResourceTable,
+    /// Number of successful invocations served by a pooled instance, see [`Workload::recycle`]
+    uses: usize,
+}
+
+impl Session {
+    /// Returns the name of the contract instantiated by the session.
+    pub fn contract(&self) -> &str {
+        &self.contract
+    }
+}
+
+/// Invocation error
+#[derive(Debug)]
+pub enum Error {
+    /// Contract, codec, instance or function not found
+    NotFound(String),
+    /// Component instantiation failed
+    Instantiate {
+        /// Role of the component in the invocation, e.g. `contract`
+        component: &'static str,
+        err: anyhow::Error,
+    },
+    /// Parameter decoding failed
+    Decode(anyhow::Error),
+    /// The invocation deadline was reached outside of Wasm execution,
+    /// e.g. while reading the body
+    Timeout,
+    /// Function call failed, all state changes are discarded
+    Call {
+        err: anyhow::Error,
+        gas_used: u64,
+        /// Messages logged during the invocation
+        logs: Vec<String>,
+    },
+    /// State changes could not be committed
+    Commit(anyhow::Error),
+    /// Result encoding failed
+    Encode(anyhow::Error),
+}
+
+impl Error {
+    /// Returns the underlying Wasmtime error, if any.
+    pub fn wasmtime_error(&self) -> Option<&anyhow::Error> {
+        match self {
+            Self::NotFound(..) | Self::Timeout => None,
+            Self::Instantiate { err, .. }
+            | Self::Decode(err)
+            | Self::Call { err, .. }
+            | Self::Commit(err)
+            | Self::Encode(err) => Some(err),
+        }
+    }
+
+    /// Returns `true` if the error was caused by the invocation running out of gas.
+    pub fn is_out_of_gas(&self) -> bool {
+        self.wasmtime_error()
+            .is_some_and(|err| err.downcast_ref() == Some(&Trap::OutOfFuel))
+    }
+
+    /// Returns `true` if the error was caused by the invocation deadline being reached.
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, Self::Timeout)
+            || self
+                .wasmtime_error()
+                .is_some_and(|err| err.downcast_ref() == Some(&Trap::Interrupt))
+    }
+
+    /// Returns `true` if the error was caused by encoded parameters exceeding
+    /// [`Options::max_body_size`].
+    pub fn is_body_too_large(&self) -> bool {
+        self.wasmtime_error()
+            .is_some_and(|err| err.is::<BodyTooLarge>())
+    }
+
+    /// Returns `true` if the error was caused by a batch exceeding [`Options::max_batch_len`].
+    pub fn is_batch_too_large(&self) -> bool {
+        self.wasmtime_error()
+            .is_some_and(|err| err.is::<BatchTooLarge>())
+    }
+
+    /// Returns `true` if the error was caused by a function returning resources being invoked
+    /// outside of a session, see [`SessionRequired`].
+    pub fn is_session_required(&self) -> bool {
+        self.wasmtime_error()
+            .is_some_and(|err| err.is::<SessionRequired>())
+    }
+
+    /// Returns the panic reported by the contract, if the error was caused by it,
+    /// see [`panic`](crate::panic).
+    pub fn guest_panic(&self) -> Option<&GuestPanic> {
+        self.wasmtime_error()?.downcast_ref()
+    }
+
+    /// Returns `true` if the error was caused by a resource limit being exceeded.
+    pub fn is_limit_exceeded(&self) -> bool {
+        self.wasmtime_error()
+            .is_some_and(|err| err.is::<LimitExceeded>())
+    }
+
+    /// Returns `true` if committing failed, because state read by the invocation
+    /// was changed by a concurrent invocation, see [`storage::Conflict`].
+    pub fn is_conflict(&self) -> bool {
+        matches!(self, Self::Commit(err) if err.is::<storage::Conflict>())
+    }
+
+    /// Returns the kind of the error, e.g. `out_of_gas`, as reported by [`metrics`](crate::metrics).
+    pub fn kind(&self) -> &'static str {
+        if self.is_out_of_gas() {
+            "out_of_gas"
+        } else if self.is_timeout() {
+            "timeout"
+        } else if self.is_body_too_large() {
+            "body_too_large"
+        } else if self.is_batch_too_large() {
+            "batch_too_large"
+        } else if self.is_session_required() {
+            "session_required"
+        } else if self.is_limit_exceeded() {
+            "limit_exceeded"
+        } else if self.guest_panic().is_some() {
+            "panic"
+        } else if self.is_conflict() {
+            "conflict"
+        } else {
+            match self {
+                Self::NotFound(..) => "not_found",
+                Self::Instantiate { .. } => "instantiate",
+                Self::Decode(..) => "decode",
+                Self::Timeout => "timeout",
+                Self::Call { .. } => "call",
+                Self::Commit(..) => "commit",
+                Self::Encode(..) => "encode",
+            }
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound(msg) => f.write_str(msg),
+            Self::Instantiate { component, err } => {
+                write!(f, "failed to instantiate {component}: {err:#}")
+            }
+            Self::Decode(err) => write!(f, "failed to decode parameters: {err:#}"),
+            Self::Timeout => f.write_str("invocation deadline reached"),
+            Self::Call { err, .. } => write!(f, "failed to call function: {err:#}"),
+            Self::Commit(err) => write!(f, "failed to commit state changes: {err:#}"),
+            Self::Encode(err) => write!(f, "failed to encode results: {err:#}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Error returned if encoded parameters exceed [`Options::max_body_size`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BodyTooLarge {
+    pub maximum: usize,
+}
+
+impl fmt::Display for BodyTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { maximum } = self;
+        write!(f, "body size exceeds the limit of {maximum} bytes")
+    }
+}
+
+impl std::error::Error for BodyTooLarge {}
+
+/// Error returned if a batch exceeds [`Options::max_batch_len`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BatchTooLarge {
+    pub maximum: usize,
+}
+
+impl fmt::Display for BatchTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { maximum } = self;
+        write!(f, "batch exceeds the limit of {maximum} invocations")
+    }
+}
+
+impl std::error::Error for BatchTooLarge {}
+
+/// Error returned if a function returning resources is invoked outside of a session.
+///
+/// Resources only live as long as the instance they were created by, handles returned by
+/// plain invocations would refer to resources already dropped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SessionRequired {
+    pub func: String,
+}
+
+impl fmt::Display for SessionRequired {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { func } = self;
+        write!(
+            f,
+            "function `{func}` returns resources and can only be invoked within a session"
+        )
+    }
+}
+
+impl std::error::Error for SessionRequired {}
+
+/// Gas remaining for the invocations of a batch, see [`Options::max_batch_gas`]
+struct GasBudget(Mutex<u64>);
+
+impl GasBudget {
+    /// Reserves up to `gas` gas for an invocation and returns the reserved amount.
+    fn reserve(&self, gas: u64) -> u64 {
+        let mut remaining = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let gas = gas.min(*remaining);
+        *remaining -= gas;
+        gas
+    }
+
+    /// Returns `gas` reserved, but not used by an invocation.
+    fn refund(&self, gas: u64) {
+        let mut remaining = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        *remaining = remaining.saturating_add(gas);
+    }
+}
+
+/// Encoded parameters of an invocation
+enum Params<'a, B> {
+    Buf(&'a [u8]),
+    /// Body, which is streamed into codecs supporting incremental deserialization
+    Body(B),
+}
+
+impl<B: Body> Params<'_, B> {
+    /// Fails with [`BodyTooLarge`] if the parameters are known to exceed `max` bytes,
+    /// i.e. before reading a body.
+    fn check_size(&self, max: usize) -> Result<(), Error> {
+        match self {
+            Self::Buf(buf) => check_size(buf.len().try_into().unwrap_or(u64::MAX), max),
+            Self::Body(body) => check_size(body.size_hint().lower(), max),
+        }
+    }
+}
+
+/// Fails with [`BodyTooLarge`] if `size` exceeds `max` bytes.
+fn check_size(size: u64, max: usize) -> Result<(), Error> {
+    if size > max.try_into().unwrap_or(u64::MAX) {
+        return Err(Error::Decode(BodyTooLarge { maximum: max }.into()));
+    }
+    Ok(())
+}
+
+/// Returns the next chunk of `body`, failing once the total size `len` of all chunks
+/// exceeds `max` or with [`Elapsed`] once `deadline` is reached.
+///
+/// The epoch deadline only interrupts Wasm execution, so reads are bounded separately.
+async fn next_chunk<B>(
+    body: &mut B,
+    len: &mut usize,
+    max: usize,
+    deadline: tokio::time::Instant,
+) -> anyhow::Result<Option<B::Data>>
+where
+    B: Body + Unpin,
+    B::Error: std::error::Error + Send + Sync + 'static,
+{
+    while let Some(frame) = tokio::time::timeout_at(deadline, body.frame()).await? {
+        let frame = frame.context("failed to read body")?;
+        let Ok(data) = frame.into_data() else {
+            continue;
+        };
+        *len = len.saturating_add(data.remaining());
+        if *len > max {
+            return Err(BodyTooLarge { maximum: max }.into());
+        }
+        return Ok(Some(data));
+    }
+    Ok(None)
+}
+
+struct Ctx {
+    /// Instance, which root function imports are forwarded to
+    target: Option<Instance>,
+    /// Pending state changes, committed once the invocation succeeds
+    tx: storage::Transaction,
+    /// Pending events, published once the invocation is committed
+    events: Vec<Event>,
+    /// Messages logged during the invocation
+    logs: Logs,
+    /// Resource limits of the invocation
+    limits: Limiter,
+}
+
+struct Workload {
+    pre: InstancePre<Ctx>,
+    ty: types::Component,
+    /// Set if the component implements the codec world
+    codec: Option<CodecPre<Ctx>>,
+    /// Reflect type plans of exported functions, built on first invocation
+    plans: Mutex<HashMap<Box<str>, Arc<TypePlan>>>,
+    /// Idle instances, set if the component is a stateless contract
+    pool: Option<Mutex<Vec<Session>>>,
+}
+
+impl Workload {
+    /// Returns the reflect type plan of function `func` of type `ty`.
+    fn plan(&self, func: &str, ty: &types::ComponentFunc) -> anyhow::Result<Arc<TypePlan>> {
+        let mut plans = self.plans.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(plan) = plans.get(func) {
+            return Ok(Arc::clone(plan));
+        }
+        let plan = debug_span!("build_type_plan", func).in_scope(|| TypePlan::new(ty))?;
+        let plan = Arc::new(plan);
+        plans.insert(func.into(), Arc::clone(&plan));
+        Ok(plan)
+    }
+
+    /// Takes an idle instance from the pool, if any.
+    fn take_pooled(&self) -> Option<Session> {
+        self.pool
+            .as_ref()?
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop()
+    }
+
+    /// Returns `session` to the pool, unless the pool is full or the instance is to be retired.
+    ///
+    /// The instance is not reset, see [`ContractOptions::stateless`].
+    fn recycle(&self, mut session: Session, options: &Options) {
+        let Some(pool) = &self.pool else {
+            return;
+        };
+        session.uses = session.uses.saturating_add(1);
+        if session.uses >= options.max_pooled_uses
+            || session.store.data().limits.table_high_water() > options.max_pooled_table_elements
+        {
+            return;
+        }
+        let mut pool = pool.lock().unwrap_or_else(PoisonError::into_inner);
+        if pool.len() < options.pool_size {
+            pool.push(session);
+        }
+    }
+}
+
+/// Interfaces exported by codec components
+const CODEC_EXPORTS: [&str; 2] = [
+    "cosmonic:reflect/reflect@0.1.0",
+    "cosmonic:serde/deserializer@0.1.0",
+];
+
+/// Returns the error of failed instantiation of `component` in `store`,
+/// see [`Limiter::instantiation_error`].
+fn instantiate_error(store: &Store<Ctx>, component: &'static str, err: anyhow::Error) -> Error {
+    Error::Instantiate {
+        component,
+        err: store.data().limits.instantiation_error(err),
+    }
+}
+
+#[expect(clippy::needless_borrow)]
+fn compile_component(engine: &Engine, buf: &[u8]) -> anyhow::Result<Component> {
+    let mut enc = ComponentEncoder::default().module(&buf)?;
+    let buf = enc.encode()?;
+    Component::new(engine, buf).context("failed to compile component")
+}
+
+/// Codec used to decode parameters of an invocation
+enum Decoder<'a> {
+    Host(&'a dyn HostCodec),
+    Component(&'a codec::Codec),
+}
+
+impl Decoder<'_> {
+    /// Decodes parameters of function `func` of `workload` with type `ty` from `buf`.
+    async fn decode(
+        &self,
+        store: &mut Store<Ctx>,
+        workload: &Workload,
+        func: &str,
+        ty: &types::ComponentFunc,
+        buf: &[u8],
+    ) -> anyhow::Result<Vec<Val>> {
+        match self {
+            Self::Host(codec) => codec.decode_params(ty, buf),
+            Self::Component(codec) => {
+                let plan = workload.plan(func, ty)?;
+                codec::deserialize_params(store, codec, ty, &plan, buf).await
+            }
+        }
+    }
+
+    /// Like [`Decoder::decode`], but reads parameters from `body` of at most `max` bytes
+    /// until `deadline`, which is streamed into codecs supporting incremental deserialization.
+    #[expect(clippy::too_many_arguments)]
+    async fn decode_body<B>(
+        &self,
+        store: &mut Store<Ctx>,
+        workload: &Workload,
+        func: &str,
+        ty: &types::ComponentFunc,
+        mut body: B,
+        max: usize,
+        deadline: tokio::time::Instant,
+    ) -> anyhow::Result<Vec<Val>>
+    where
+        B: Body + Unpin,
+        B::Error: std::error::Error + Send + Sync + 'static,
+    {
+        let mut len = 0;
+        if let Self::Component(codec) = self
+            && codec.is_streaming()
+            && ty.params().len() > 0
+        {
+            let plan = workload.plan(func, ty)?;
+            let decoder = ParamsDecoder::new(store, codec, &plan).await?;
+            let pushed = async {
+                while let Some(mut data) = next_chunk(&mut body, &mut len, max, deadline).await? {
+                    while data.has_remaining() {
+                        let chunk = data.chunk();
+                        let n = chunk.len();
+                        decoder.push(store, chunk).await?;
+                        data.advance(n);
+                    }
+                }
+                anyhow::Ok(())
+            }
+            .await;
+            if let Err(err) = pushed {
+                decoder.abort(store).await;
+                return Err(err);
+            }
+            return decoder.finish(store, ty).await;
+        }
+        let mut buf = Vec::default();
+        while let Some(data) = next_chunk(&mut body, &mut len, max, deadline).await? {
+            buf.put(data);
+        }
+        self.decode(store, workload, func, ty, &buf).await
+    }
+}
+
+/// Contract runtime, which invokes functions exported by loaded components
+/// with parameters decoded by codecs.
+///
+/// Contracts and codec components share a single namespace, loaded components implementing
+/// the codec world are registered as codecs. Codecs built into the host, see [`codecs`],
+/// take precedence over codec components with the same name.
+///
+/// [`codecs`]: crate::codecs
+pub struct Runtime {
+    engine: Engine,
+    storage: Arc<dyn Storage>,
+    options: Options,
+    /// Invocation deadline in epoch ticks
+    deadline: u64,
+    components: BTreeMap<Box<str>, Workload>,
+    host_codecs: BTreeMap<Box<str>, Arc<dyn HostCodec>>,
+    events: Events,
+    metrics: Metrics,
+}
+
+impl Runtime {
+    /// Creates a new [`Runtime`] using Wasmtime `config`, see [`config`](crate::config).
+    ///
+    /// Async support, fuel consumption and epoch interruption are enabled on top of `config`,
+    /// Wasm backtraces are enabled if [`Options::debug`] is set and disabled otherwise.
+    pub fn new(
+        config: &wasmtime::Config,
+        storage: Arc<dyn Storage>,
+        options: Options,
+    ) -> anyhow::Result<Self> {
+        let mut config = config.clone();
+        config::with_async_support(&mut config)
+            .consume_fuel(true)
+            .epoch_interruption(true)
+            .wasm_backtrace(options.debug)
+            .wasm_backtrace_details(if options.debug {
+                wasmtime::WasmBacktraceDetails::Enable
+            } else {
+                wasmtime::WasmBacktraceDetails::Disable
+            });
+        let engine = Engine::new(&config)?;
+        std::thread::spawn({
+            let engine = engine.weak();
+            move || {
+                while let Some(engine) = engine.upgrade() {
+                    engine.increment_epoch();
+                    drop(engine);
+                    std::thread::sleep(EPOCH_TICK);
+                }
+            }
+        });
+        let deadline = options
+            .timeout
+            .as_nanos()
+            .div_ceil(EPOCH_TICK.as_nanos())
+            .try_into()
+            .unwrap_or(u64::MAX);
+        Ok(Self {
+            engine,
+            storage,
+            options,
+            deadline,
+            components: BTreeMap::default(),
+            host_codecs: codecs::builtin()
+                .into_iter()
+                .map(|(name, codec)| (name.into(), codec))
+                .collect(),
+            events: Events::default(),
+            metrics: Metrics::default(),
+        })
+    }
+
+    /// Registers host codec `codec` under `name`, replacing a host codec previously
+    /// registered under the same name.
+    pub fn register_codec(&mut self, name: &str, codec: Arc<dyn HostCodec>) {
+        self.host_codecs.insert(name.into(), codec);
+    }
+
+    /// Compiles Wasm module `wasm` into a component and loads it under `name`,
+    /// replacing a component previously loaded under the same name.
+    ///
+    /// Components exporting any of the codec interfaces are validated to implement
+    /// the whole codec world and registered as codecs.
+    pub fn load_contract(&mut self, name: &str, wasm: &[u8]) -> anyhow::Result<()> {
+        self.load_contract_with(name, wasm, ContractOptions::default())
+    }
+
+    /// Like [`Runtime::load_contract`], but with [`ContractOptions`].
+    pub fn load_contract_with(
+        &mut self,
+        name: &str,
+        wasm: &[u8],
+        opts: ContractOptions,
+    ) -> anyhow::Result<()> {
+        let component = compile_component(&self.engine, wasm)?;
+        let mut linker = Linker::new(&self.engine);
+        storage::add_to_linker(&mut linker, name, |cx: &mut Ctx| &mut cx.tx)?;
+        events::add_to_linker(&mut linker, name, |cx: &mut Ctx| {
+            (!cx.tx.is_read_only()).then_some(&mut cx.events)
+        })?;
+        log::add_to_linker(&mut linker, name, |cx: &mut Ctx| &mut cx.logs)?;
+        panic::add_to_linker(&mut linker, name)?;
+        for (name, ty) in component.component_type().imports(&self.engine) {
+            let types::ComponentItem::ComponentFunc(..) = ty else {
+                continue;
+            };
+            let name = Arc::from(name);
+            linker.root().func_new_async(&name, {
+                let name = Arc::clone(&name);
+                move |mut store, params, results| {
+                    let name = Arc::clone(&name);
+                    Box::new(async move {
+                        let Some(target) = store.data().target else {
+                            bail!("target component missing");
+                        };
+                        let f = target
+                            .get_func(&mut store, name.as_ref())
+                            .context("function not found")?;
+                        // Changes made and events emitted by the target are rolled back
+                        // if the call fails
+                        let savepoint = store.data().tx.savepoint();
+                        let events = store.data().events.len();
+                        let res = async {
+                            f.call_async(&mut store, params, results).await?;
+                            f.post_return_async(&mut store).await
+                        }
+                        .await;
+                        if res.is_err() {
+                            let cx = store.data_mut();
+                            cx.tx.rollback(savepoint);
+                            cx.events.truncate(events);
+                        }
+                        res
+                    })
+                }
+            })?;
+        }
+        let ty = linker.substituted_component_type(&component)?;
+        let pre = linker.instantiate_pre(&component)?;
+        let codec = if CODEC_EXPORTS
+            .iter()
+            .any(|name| ty.get_export(&self.engine, name).is_some())
+        {
+            let codec = CodecPre::new(pre.clone())
+                .with_context(|| format!("component `{name}` is not a valid codec"))?;
+            Some(codec)
+        } else {
+            None
+        };
+        self.components.insert(
+            name.into(),
+            Workload {
+                pre,
+                ty,
+                codec,
+                plans: Mutex::default(),
+                pool: opts.stateless.then(Mutex::default),
+            },
+        );
+        Ok(())
+    }
+
+    /// Returns the [`Engine`] used by the runtime.
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    /// Returns names of all loaded components.
+    pub fn contracts(&self) -> impl Iterator<Item = &str> {
+        self.components.keys().map(AsRef::as_ref)
+    }
+
+    /// Returns the publisher of events emitted by committed invocations.
+    pub fn events(&self) -> &Events {
+        &self.events
+    }
+
+    /// Returns the metrics recorded by invocations.
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Appends the recorded metrics and the number of loaded components to `out`,
+    /// see [`metrics`](crate::metrics).
+    pub fn encode_metrics(&self, out: &mut String) {
+        self.metrics.encode(out);
+        metrics::encode_gauge(
+            out,
+            "near_cm_contracts_loaded",
+            "Number of loaded components, including codec components",
+            self.components.len(),
+        );
+    }
+
+    /// Returns host codec `name`, if it is registered.
+    pub fn host_codec(&self, name: &str) -> Option<&dyn HostCodec> {
+        self.host_codecs.get(name).map(AsRef::as_ref)
+    }
+
+    /// Returns names of all host codecs and loaded codec components.
+    pub fn codecs(&self) -> impl Iterator<Item = &str> {
+        let components = self
+            .components
+            .iter()
+            .filter(|(name, Workload { codec, .. })| {
+                codec.is_some() && !self.host_codecs.contains_key(*name)
+            })
+            .map(|(name, _)| name.as_ref());
+        self.host_codecs.keys().map(AsRef::as_ref).chain(components)
+    }
+
+    /// Returns all resource types exported by component `contract`, if it is loaded.
+    ///
+    /// Resources exported by instances are named like functions, see [`Runtime::functions`].
+    pub fn resources(&self, contract: &str) -> Option<Vec<(String, ResourceType)>> {
+        let Workload { ty, .. } = self.components.get(contract)?;
+        let mut resources = Vec::default();
+        for (name, ty) in ty.exports(&self.engine) {
+            match ty {
+                types::ComponentItem::Resource(ty) => resources.push((name.into(), ty)),
+                types::ComponentItem::ComponentInstance(ty) => {
+                    let instance = name;
+                    for (name, ty) in ty.exports(&self.engine) {
+                        if let types::ComponentItem::Resource(ty) = ty {
+                            resources.push((format!("{instance}#{name}"), ty));
+                        }
+                    }
+                }
+                _ => continue,
+            }
+        }
+        Some(resources)
+    }
+
+    /// Returns all functions exported by component `contract`, if it is loaded.
+    ///
+    /// Functions exported by instances are named `<instance>#<function>`.
+    pub fn functions(&self, contract: &str) -> Option<Vec<(String, types::ComponentFunc)>> {
+        let Workload { ty, .. } = self.components.get(contract)?;
+        let mut funcs = Vec::default();
+        for (name, ty) in ty.exports(&self.engine) {
+            match ty {
+                types::ComponentItem::ComponentFunc(ty) => funcs.push((name.into(), ty)),
+                types::ComponentItem::ComponentInstance(ty) => {
+                    let instance = name;
+                    for (name, ty) in ty.exports(&self.engine) {
+                        if let types::ComponentItem::ComponentFunc(ty) = ty {
+                            funcs.push((format!("{instance}#{name}"), ty));
+                        }
+                    }
+                }
+                _ => continue,
+            }
+        }
+        Some(funcs)
+    }
+
+    /// Returns the type of function `func` exported by `workload`.
+    fn func_ty(&self, workload: &Workload, func: &str) -> Result<types::ComponentFunc, Error> {
+        let ty = if let Some((instance, func)) = func.split_once('#') {
+            let Some(types::ComponentItem::ComponentInstance(ty)) =
+                workload.ty.get_export(&self.engine, instance)
+            else {
+                return Err(Error::NotFound(format!("instance `{instance}` not found")));
+            };
+            let Some(types::ComponentItem::ComponentFunc(ty)) = ty.get_export(&self.engine, func)
+            else {
+                return Err(Error::NotFound(format!(
+                    "function `{func}` not found in instance `{instance}`"
+                )));
+            };
+            ty
+        } else {
+            let Some(types::ComponentItem::ComponentFunc(ty)) =
+                workload.ty.get_export(&self.engine, func)
+            else {
+                return Err(Error::NotFound(format!("function `{func}` not found")));
+            };
+            ty
+        };
+        Ok(ty)
+    }
+
+    /// Creates a session by instantiating `contract`, with root function imports forwarded
+    /// to an instance of component `target`, if set.
+    pub async fn new_session(
+        &self,
+        contract: &str,
+        target: Option<&str>,
+    ) -> Result<Session, Error> {
+        let Some(Workload { pre, .. }) = self.components.get(contract) else {
+            return Err(Error::NotFound(format!("contract `{contract}` not found")));
+        };
+        let start = Instant::now();
+        let mut store = Store::new(
+            &self.engine,
+            Ctx {
+                target: None,
+                tx: storage::Transaction::new(Arc::clone(&self.storage)),
+                events: Vec::default(),
+                logs: Logs::default(),
+                limits: Limiter::new(self.options.limits),
+            },
+        );
+        store.limiter(|cx| &mut cx.limits);
+        // Instantiation is not charged to the caller, but still needs to be bounded.
+        store
+            .set_fuel(self.options.max_gas)
+            .expect("fuel consumption is enabled");
+        store.set_epoch_deadline(self.deadline);
+        store.epoch_deadline_trap();
+        if let Some(target) = target {
+            let Some(Workload { pre, .. }) = self.components.get(target) else {
+                return Err(Error::NotFound(format!(
+                    "target component `{target}` not found"
+                )));
+            };
+            let target = pre
+                .instantiate_async(&mut store)
+                .instrument(debug_span!(
+                    "instantiate",
+                    component = "target component",
+                    target
+                ))
+                .await
+                .map_err(|err| instantiate_error(&store, "target component", err))?;
+            store.data_mut().target = Some(target);
+        };
+        let instance = pre
+            .instantiate_async(&mut store)
+            .instrument(debug_span!("instantiate", component = "contract", contract))
+            .await
+            .map_err(|err| instantiate_error(&store, "contract", err))?;
+        self.metrics
+            .record_phase(contract, Phase::Instantiate, start.elapsed());
+        Ok(Session {
+            contract: contract.into(),
+            store,
+            instance,
+            codecs: HashMap::default(),
+            resources: ResourceTable::default(),
+            uses: 0,
+        })
+    }
+
+    /// Invokes function `func` of `contract` with parameters decoded from `params`
+    /// by `codec`.
+    pub async fn invoke(
+        &self,
+        contract: &str,
+        func: &str,
+        codec: &str,
+        params: &[u8],
+    ) -> Result<Outcome, Error> {
+        self.invoke_with(contract, func, codec, params, InvokeOptions::default())
+            .await
+    }
+
+    /// Like [`Runtime::invoke`], but with [`InvokeOptions`].
+    pub async fn invoke_with(
+        &self,
+        contract: &str,
+        func: &str,
+        codec: &str,
+        params: &[u8],
+        opts: InvokeOptions<'_>,
+    ) -> Result<Outcome, Error> {
+        self.invoke_params::<Empty<Bytes>>(contract, func, codec, Params::Buf(params), opts)
+            .await
+            .inspect_err(|err| self.metrics.record_error(err.kind()))
+    }
+
+    /// Like [`Runtime::invoke_with`], but reads parameters from HTTP `body`.
+    ///
+    /// The body is streamed into codec components supporting incremental deserialization,
+    /// i.e. exporting the `near-cm:codec/stream-deserializer` interface, and buffered
+    /// otherwise. Reading the body counts towards the invocation timeout.
+    pub async fn invoke_body_with<B>(
+        &self,
+        contract: &str,
+        func: &str,
+        codec: &str,
+        body: B,
+        opts: InvokeOptions<'_>,
+    ) -> Result<Outcome, Error>
+    where
+        B: Body + Unpin,
+        B::Error: std::error::Error + Send + Sync + 'static,
+    {
+        self.invoke_params(contract, func, codec, Params::Body(body), opts)
+            .await
+            .inspect_err(|err| self.metrics.record_error(err.kind()))
+    }
+
+    /// Encodes results `results` of function `func` of `contract` using host codec `codec`.
+    pub fn encode_results(
+        &self,
+        contract: &str,
+        func: &str,
+        codec: &str,
+        results: &[Val],
+    ) -> Result<Vec<u8>, Error> {
+        let Some(host_codec) = self.host_codecs.get(codec) else {
+            return Err(Error::NotFound(format!("host codec `{codec}` not found")));
+        };
+        let Some(workload) = self.components.get(contract) else {
+            return Err(Error::NotFound(format!("contract `{contract}` not found")));
+        };
+        let ty = self.func_ty(workload, func)?;
+        debug_span!("encode", codec)
+            .in_scope(|| host_codec.encode_results(&ty, results))
+            .map_err(Error::Encode)
+            .inspect_err(|err| self.metrics.record_error(err.kind()))
+    }
+
+    /// Invokes a batch of functions decoded from `buf` by host codec `codec` and returns
+    /// their outcomes or error messages encoded by `codec`, see [`HostCodec::decode_batch`].
+    ///
+    /// Invocations are independent of each other, each one is committed once it succeeds.
+    /// If `parallel` is set, at most [`Options::batch_concurrency`] invocations run
+    /// concurrently as separate tokio tasks, otherwise they run in order.
+    ///
+    /// Batches are limited to [`Options::max_batch_len`] invocations. [`InvokeOptions::gas_limit`]
+    /// applies to every invocation, all invocations together can use at most
+    /// [`Options::max_batch_gas`]. Gas is reserved before and refunded after every invocation,
+    /// invocations which cannot reserve any gas fail without running.
+    pub async fn invoke_batch(
+        self: &Arc<Self>,
+        codec: &str,
+        buf: &[u8],
+        opts: InvokeOptions<'_>,
+        parallel: bool,
+    ) -> Result<Vec<u8>, Error> {
+        let Some(host_codec) = self.host_codecs.get(codec) else {
+            return Err(Error::NotFound(format!("host codec `{codec}` not found")));
+        };
+        let max_batch_len = self.options.max_batch_len;
+        let calls = host_codec
+            .decode_batch(buf)
+            .and_then(|calls| {
+                if calls.len() > max_batch_len {
+                    return Err(BatchTooLarge {
+                        maximum: max_batch_len,
+                    }
+                    .into());
+                }
+                Ok(calls)
+            })
+            .map_err(Error::Decode)
+            .inspect_err(|err| self.metrics.record_error(err.kind()))?;
+        let budget = Arc::new(GasBudget(Mutex::new(self.options.max_batch_gas)));
+        let mut outcomes = Vec::with_capacity(calls.len());
+        if parallel {
+            let codec = Arc::<str>::from(codec);
+            let target = opts.target.map(Arc::<str>::from);
+            let permits = Arc::new(tokio::sync::Semaphore::new(
+                self.options.batch_concurrency.max(1),
+            ));
+            let tasks = calls
+                .into_iter()
+                .map(|call| {
+                    let runtime = Arc::clone(self);
+                    let codec = Arc::clone(&codec);
+                    let target = target.clone();
+                    let budget = Arc::clone(&budget);
+                    let permits = Arc::clone(&permits);
+                    let gas_limit = opts.gas_limit;
+                    let view = opts.view;
+                    tokio::spawn(async move {
+                        let _permit = permits
+                            .acquire_owned()
+                            .await
+                            .expect("semaphore is never closed");
+                        let opts = InvokeOptions {
+                            target: target.as_deref(),
+                            gas_limit,
+                            view,
+                        };
+                        runtime.invoke_batch_call(&codec, call, opts, &budget).await
+                    })
+                })
+                .collect::<Vec<_>>();
+            for task in tasks {
+                let outcome = task
+                    .await
+                    .unwrap_or_else(|err| Err(format!("invocation task failed: {err}")));
+                outcomes.push(outcome);
+            }
+        } else {
+            for call in calls {
+                outcomes.push(self.invoke_batch_call(codec, call, opts, &budget).await);
+            }
+        }
+        host_codec.encode_batch(&outcomes).map_err(Error::Encode)
+    }
+
+    async fn invoke_batch_call(
+        &self,
+        codec: &str,
+        call: BatchCall,
+        mut opts: InvokeOptions<'_>,
+        budget: &GasBudget,
+    ) -> Result<BatchOutcome, String> {
+        let BatchCall {
+            contract,
+            func,
+            args,
+        } = call;
+        let max_gas = self.options.max_gas;
+        let gas_limit = opts.gas_limit.map_or(max_gas, |gas| gas.min(max_gas));
+        let reserved = budget.reserve(gas_limit);
+        if reserved == 0 && gas_limit > 0 {
+            return Err("gas budget of the batch exhausted".into());
+        }
+        opts.gas_limit = Some(reserved);
+        let res = self.invoke_with(&contract, &func, codec, &args, opts).await;
+        let gas_used = match &res {
+            Ok(Outcome { gas_used, .. }) | Err(Error::Call { gas_used, .. }) => *gas_used,
+            Err(..) => 0,
+        };
+        budget.refund(reserved.saturating_sub(gas_used));
+        let Outcome {
+            results,
+            gas_used,
+            logs,
+        } = res.map_err(|err| err.to_string())?;
+        let results = self
+            .encode_results(&contract, &func, codec, &results)
+            .map_err(|err| err.to_string())?;
+        Ok(BatchOutcome {
+            results,
+            gas_used,
+            logs,
+        })
+    }
+
+    /// Drops resource with handle ID `id` of `session`, using at most `gas_limit` gas,
+    /// see [`InvokeOptions::gas_limit`]. Returns `None` if there is no such resource.
+    ///
+    /// State changes made by the destructor of the resource are committed, the outcome
+    /// holds no results.
+    pub async fn drop_resource(
+        &self,
+        session: &mut Session,
+        id: u32,
+        gas_limit: Option<u64>,
+    ) -> Result<Option<Outcome>, Error> {
+        let Some(resource) = session.resources.remove(id) else {
+            return Ok(None);
+        };
+        let max_gas = self.options.max_gas;
+        let gas_limit = gas_limit.map_or(max_gas, |gas| gas.min(max_gas));
+        let store = &mut session.store;
+        store.data_mut().logs.set_func("[resource-drop]");
+        store.data_mut().tx.set_read_only(false);
+        store
+            .set_fuel(gas_limit)
+            .expect("fuel consumption is enabled");
+        store.set_epoch_deadline(self.deadline);
+        let res = resource
+            .resource_drop_async::<Ctx>(&mut *store)
+            .instrument(debug_span!(
+                "drop",
+                contract = session.contract.as_ref(),
+                id
+            ))
+            .await;
+        let gas_used =
+            gas_limit.saturating_sub(store.get_fuel().expect("fuel consumption is enabled"));
+        let (tx, events, logs) = self.take_effects(store.data_mut());
+        if let Err(err) = res {
+            return Err(Error::Call {
+                err,
+                gas_used,
+                logs,
+            })
+            .inspect_err(|err| self.metrics.record_error(err.kind()));
+        }
+        debug_span!("commit")
+            .in_scope(|| tx.commit())
+            .map_err(Error::Commit)
+            .inspect_err(|err| self.metrics.record_error(err.kind()))?;
+        self.events.publish(events);
+        Ok(Some(Outcome {
+            results: Vec::default(),
+            gas_used,
+            logs,
+        }))
+    }
+
+    /// Takes the transaction, pending events and logs of an invocation from `cx`.
+    fn take_effects(&self, cx: &mut Ctx) -> (storage::Transaction, Vec<Event>, Vec<String>) {
+        let tx = mem::replace(
+            &mut cx.tx,
+            storage::Transaction::new(Arc::clone(&self.storage)),
+        );
+        (tx, mem::take(&mut cx.events), cx.logs.take())
+    }
+
+    /// Invokes function `func` within `session` with parameters decoded from `params`
+    /// by `codec`, using at most `gas_limit` gas, see [`InvokeOptions::gas_limit`].
+    ///
+    /// State changes are committed after every successful invocation. Sessions, in which
+    /// an invocation trapped, cannot be invoked anymore.
+    pub async fn invoke_in(
+        &self,
+        session: &mut Session,
+        func: &str,
+        codec: &str,
+        params: &[u8],
+        gas_limit: Option<u64>,
+    ) -> Result<Outcome, Error> {
+        self.invoke_session::<Empty<Bytes>>(
+            session,
+            func,
+            codec,
+            Params::Buf(params),
+            gas_limit,
+            false,
+        )
+        .await
+        .inspect_err(|err| self.metrics.record_error(err.kind()))
+    }
+
+    /// Like [`Runtime::invoke_in`], but reads parameters from HTTP `body`,
+    /// see [`Runtime::invoke_body_with`].
+    pub async fn invoke_body_in<B>(
+        &self,
+        session: &mut Session,
+        func: &str,
+        codec: &str,
+        body: B,
+        gas_limit: Option<u64>,
+    ) -> Result<Outcome, Error>
+    where
+        B: Body + Unpin,
+        B::Error: std::error::Error + Send + Sync + 'static,
+    {
+        self.invoke_session(session, func, codec, Params::Body(body), gas_limit, false)
+            .await
+            .inspect_err(|err| self.metrics.record_error(err.kind()))
+    }
+
+    /// Reads HTTP `body` into a buffer, failing with [`BodyTooLarge`] once it exceeds
+    /// [`Options::max_body_size`] and with [`Error::Timeout`] once reading it takes longer
+    /// than [`Options::timeout`].
+    ///
+    /// [`Runtime::invoke_body_in`] holds the session while reading the body, reading it
+    /// beforehand and invoking [`Runtime::invoke_in`] keeps slow clients from blocking
+    /// other invocations within the session.
+    pub async fn read_body<B>(&self, mut body: B) -> Result<Vec<u8>, Error>
+    where
+        B: Body + Unpin,
+        B::Error: std::error::Error + Send + Sync + 'static,
+    {
+        let max = self.options.max_body_size;
+        let deadline = tokio::time::Instant::now() + self.options.timeout;
+        async {
+            check_size(body.size_hint().lower(), max)?;
+            let mut buf = Vec::default();
+            let mut len = 0;
+            while let Some(data) = next_chunk(&mut body, &mut len, max, deadline)
+                .await
+                .map_err(|err| {
+                    if err.is::<tokio::time::error::Elapsed>() {
+                        Error::Timeout
+                    } else {
+                        Error::Decode(err)
+                    }
+                })?
+            {
+                buf.put(data);
+            }
+            Ok(buf)
+        }
+        .await
+        .inspect_err(|err: &Error| self.metrics.record_error(err.kind()))
+    }
+
+    async fn invoke_params<B>(
+        &self,
+        contract: &str,
+        func: &str,
+        codec: &str,
+        params: Params<'_, B>,
+        opts: InvokeOptions<'_>,
+    ) -> Result<Outcome, Error>
+    where
+        B: Body + Unpin,
+        B::Error: std::error::Error + Send + Sync + 'static,
+    {
+        let Some(workload) = self.components.get(contract) else {
+            return Err(Error::NotFound(format!("contract `{contract}` not found")));
+        };
+        // Fail before instantiating anything
+        let ty = self.func_ty(workload, func)?;
+        if ty.results().any(|ty| has_resources(&ty)) {
+            return Err(Error::Call {
+                err: SessionRequired { func: func.into() }.into(),
+                gas_used: 0,
+                logs: Vec::default(),
+            });
+        }
+        if !self.host_codecs.contains_key(codec)
+            && !matches!(
+                self.components.get(codec),
+                Some(Workload {
+                    codec: Some(..),
+                    ..
+                })
+            )
+        {
+            return Err(Error::NotFound(format!("codec `{codec}` not found")));
+        }
+        params.check_size(self.options.max_body_size)?;
+        let pooled = opts.target.is_none().then(|| workload.take_pooled());
+        let mut session = match pooled.flatten() {
+            Some(session) => session,
+            None => self.new_session(contract, opts.target).await?,
+        };
+        let res = self
+            .invoke_session(&mut session, func, codec, params, opts.gas_limit, opts.view)
+            .await;
+        // Instances may be left in an unusable state by failed invocations
+        if res.is_ok() && opts.target.is_none() {
+            workload.recycle(session, &self.options);
+        }
+        res
+    }
+
+    async fn invoke_session<B>(
+        &self,
+        session: &mut Session,
+        func: &str,
+        codec: &str,
+        params: Params<'_, B>,
+        gas_limit: Option<u64>,
+        view: bool,
+    ) -> Result<Outcome, Error>
+    where
+        B: Body + Unpin,
+        B::Error: std::error::Error + Send + Sync + 'static,
+    {
+        let max_body_size = self.options.max_body_size;
+        params.check_size(max_body_size)?;
+        let max_gas = self.options.max_gas;
+        let gas_limit = gas_limit.map_or(max_gas, |gas| gas.min(max_gas));
+        let Session {
+            contract,
+            store,
+            instance,
+            codecs,
+            resources,
+            ..
+        } = session;
+        let workload = self
+            .components
+            .get(contract.as_ref())
+            .expect("session contract not found");
+        let ty = self.func_ty(workload, func)?;
+
+        // Codec instantiation and parameter decoding are not charged to the caller,
+        // but still need to be bounded.
+        store
+            .set_fuel(max_gas)
+            .expect("fuel consumption is enabled");
+        store.set_epoch_deadline(self.deadline);
+        let deadline = tokio::time::Instant::now() + self.options.timeout;
+        let decoder = if let Some(codec) = self.host_codecs.get(codec) {
+            Decoder::Host(codec.as_ref())
+        } else {
+            let Some(Workload {
+                codec: Some(pre), ..
+            }) = self.components.get(codec)
+            else {
+                return Err(Error::NotFound(format!("codec `{codec}` not found")));
+            };
+            if !codecs.contains_key(codec) {
+                let start = Instant::now();
+                let instance = pre
+                    .instantiate_async(&mut *store)
+                    .instrument(debug_span!("instantiate", component = "codec", codec))
+                    .await
+                    .map_err(|err| instantiate_error(store, "codec", err))?;
+                self.metrics
+                    .record_phase(contract, Phase::Instantiate, start.elapsed());
+                codecs.insert(codec.into(), instance);
+            }
+            Decoder::Component(&codecs[codec])
+        };
+        let name = func;
+        let func = if let Some((instance_name, func)) = func.split_once('#') {
+            let (_, idx) = instance
+                .get_export(&mut *store, None, instance_name)
+                .expect("instance export not found");
+            let (_, idx) = instance
+                .get_export(&mut *store, Some(&idx), func)
+                .expect("function export not found");
+            instance.get_func(&mut *store, idx)
+        } else {
+            instance.get_func(&mut *store, func)
+        };
+        let func: Func = func.expect("function not found");
+        let cached = matches!(decoder, Decoder::Component(..));
+        let start = Instant::now();
+        let params = async {
+            match params {
+                Params::Buf(buf) => decoder.decode(store, workload, name, &ty, buf).await,
+                Params::Body(body) => {
+                    decoder
+                        .decode_body(store, workload, name, &ty, body, max_body_size, deadline)
+                        .await
+                }
+            }
+        }
+        .instrument(debug_span!("decode", codec))
+        .await;
+        let params = match params {
+            Ok(params) => params,
+            Err(err) => {
+                // The codec instance cannot be entered anymore, if it trapped
+                if cached && err.is::<Trap>() {
+                    codecs.remove(codec);
+                }
+                if err.is::<tokio::time::error::Elapsed>() {
+                    return Err(Error::Timeout);
+                }
+                return Err(Error::Decode(err));
+            }
+        };
+        let params = resources
+            .lift_params(&func.params(&*store), params)
+            .map_err(Error::Decode)?;
+        self.metrics
+            .record_phase(contract, Phase::Decode, start.elapsed());
+        let mut results = vec![Val::Bool(false); ty.results().len()];
+        store.data_mut().logs.set_func(name);
+        store.data_mut().tx.set_read_only(view);
+        store
+            .set_fuel(gas_limit)
+            .expect("fuel consumption is enabled");
+        let span = debug_span!("call", contract = contract.as_ref(), func = name);
+        let start = Instant::now();
+        let res = func
+            .call_async(&mut *store, &params, &mut results)
+            .instrument(span.clone())
+            .await;
+        let gas_used =
+            gas_limit.saturating_sub(store.get_fuel().expect("fuel consumption is enabled"));
+        let res = async {
+            res?;
+            // Cleanup after the call is not charged to the caller
+            store
+                .set_fuel(max_gas)
+                .expect("fuel consumption is enabled");
+            func.post_return_async(&mut *store).await?;
+            resources.lower_results(&mut results)
+        }
+        .instrument(span)
+        .await;
+        self.metrics
+            .record_phase(contract, Phase::Execute, start.elapsed());
+        self.metrics.record_call(contract, name, gas_used);
+        self.metrics
+            .record_memory(contract, store.data().limits.memory_high_water());
+        let (tx, events, logs) = self.take_effects(store.data_mut());
+        if let Err(err) = res {
+            return Err(Error::Call {
+                err,
+                gas_used,
+                logs,
+            });
+        }
+        // View calls never change state, not even by instantiation
+        if !view {
+            debug_span!("commit")
+                .in_scope(|| tx.commit())
+                .map_err(Error::Commit)?;
+            self.events.publish(events);
+        }
+        Ok(Outcome {
+            results,
+            gas_used,
+            logs,
+        })
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Returns core module `wat` with embedded component metadata of `world` in `resolve`.
+    fn embed(wat: &str, resolve: &wit_parser::Resolve, world: wit_parser::WorldId) -> Vec<u8> {
+        let mut wasm = wat::parse_str(wat).expect("failed to parse module");
+        wit_component::embed_component_metadata(
+            &mut wasm,
+            resolve,
+            world,
+            wit_component::StringEncoding::UTF8,
+        )
+        .expect("failed to embed component metadata");
+        wasm
+    }
+
+    /// Returns core module `wat` with embedded component metadata of world `wit`.
+    fn component(wat: &str, wit: &str) -> Vec<u8> {
+        let mut resolve = wit_parser::Resolve::default();
+        let pkg = resolve
+            .push_str("test.wit", wit)
+            .expect("failed to parse WIT");
+        let world = resolve.select_world(&[pkg], None).expect("world not found");
+        embed(wat, &resolve, world)
+    }
+
+    /// Returns a codec component decoding parameters `(u32)` from 4 little-endian bytes,
+    /// which supports incremental deserialization and, if `type_builder` is set, exports
+    /// `near-cm:codec/type-builder`. Errors of its stream decoder are prefixed by `stream: `.
+    ///
+    /// The codec counts its live resources and traps once more than 8 are alive,
+    /// i.e. if the host leaks them.
+    fn u32_codec(type_builder: bool) -> Vec<u8> {
+        const REFLECT: &str = "cosmonic:reflect/reflect@0.1.0";
+        const DESERIALIZER: &str = "cosmonic:serde/deserializer@0.1.0";
+        const STREAM: &str = "near-cm:codec/stream-deserializer@0.1.0";
+        let mut wat = format!(
+            r#"(module
+                (import "[export]{REFLECT}" "[resource-new]tuple-type"
+                    (func $new-tuple-type (param i32) (result i32)))
+                (import "[export]{REFLECT}" "[resource-new]tuple-value"
+                    (func $new-tuple-value (param i32) (result i32)))
+                (import "[export]{DESERIALIZER}" "[resource-new]error"
+                    (func $new-error (param i32) (result i32)))
+                (import "[export]{STREAM}" "[resource-new]decoder"
+                    (func $new-decoder (param i32) (result i32)))
+                (memory (export "memory") 1)
+                (data (i32.const 0x400) "invalid length")
+                (data (i32.const 0x410) "stream: invalid length")
+                (global $live (mut i32) (i32.const 0))
+                (global $heap (mut i32) (i32.const 0x1000))
+                (global $val (mut i32) (i32.const 0))
+                (global $pushed (mut i32) (i32.const 0))
+                (func $created
+                    (global.set $live (i32.add (global.get $live) (i32.const 1)))
+                    (if (i32.gt_u (global.get $live) (i32.const 8)) (then unreachable)))
+                (func $dropped
+                    (global.set $live (i32.sub (global.get $live) (i32.const 1))))
+                (func (export "cabi_realloc") (param i32 i32 i32 i32) (result i32)
+                    (local $ptr i32)
+                    (if (i32.gt_u (i32.add (global.get $heap) (local.get 3)) (i32.const 0x10000))
+                        (then (global.set $heap (i32.const 0x1000))))
+                    (local.set $ptr (global.get $heap))
+                    (global.set $heap (i32.and
+                        (i32.add (i32.add (local.get $ptr) (local.get 3)) (i32.const 7))
+                        (i32.const -8)))
+                    (local.get $ptr))
+                ;; Returns `ok(tuple(v))` of `result<value, error>`
+                (func $ok (param $v i32) (result i32)
+                    (global.set $val (local.get $v))
+                    (call $created)
+                    (i32.store8 (i32.const 0x100) (i32.const 0))
+                    (i32.store8 (i32.const 0x108) (i32.const 15))
+                    (i32.store (i32.const 0x110) (call $new-tuple-value (i32.const 1)))
+                    (i32.const 0x100))
+                ;; Returns `err(error)` of `result<value, error>` with message `rep`
+                (func $err (param $rep i32) (result i32)
+                    (call $created)
+                    (i32.store8 (i32.const 0x100) (i32.const 1))
+                    (i32.store (i32.const 0x108) (call $new-error (local.get $rep)))
+                    (i32.const 0x100))
+                (func (export "{REFLECT}#[constructor]tuple-type") (param i32 i32) (result i32)
+                    (call $created)
+                    (call $new-tuple-type (i32.const 1)))
+                (func (export "{REFLECT}#[constructor]record-type") (param i32 i32) (result i32)
+                    unreachable)
+                (func (export "{REFLECT}#[constructor]variant-type") (param i32 i32) (result i32)
+                    unreachable)
+                (func (export "{REFLECT}#[constructor]enum-type") (param i32 i32) (result i32)
+                    unreachable)
+                (func (export "{REFLECT}#[constructor]flags-type") (param i32 i32) (result i32)
+                    unreachable)
+                (func (export "{REFLECT}#[constructor]option-type")
+                    (param i32 i32 i32) (result i32)
+                    unreachable)
+                (func (export "{REFLECT}#[constructor]result-type")
+                    (param i32 i32 i32 i32 i32 i32 i32 i32) (result i32)
+                    unreachable)
+                (func (export "{REFLECT}#[static]tuple-value.into-value") (param i32) (result i32)
+                    (call $dropped)
+                    (i32.store8 (i32.const 0x300) (i32.const 3))
+                    (i32.store (i32.const 0x308) (global.get $val))
+                    (i32.store (i32.const 0x100) (i32.const 0x300))
+                    (i32.store (i32.const 0x104) (i32.const 1))
+                    (i32.const 0x100))
+                (func (export "{REFLECT}#[static]record-value.into-value") (param i32) (result i32)
+                    unreachable)
+                (func (export "{REFLECT}#[static]variant-value.into-value") (param i32) (result i32)
+                    unreachable)
+                (func (export "{REFLECT}#[static]option-value.into-value") (param i32) (result i32)
+                    unreachable)
+                (func (export "{REFLECT}#[static]result-value.into-value") (param i32) (result i32)
+                    unreachable)
+                (func (export "{REFLECT}#[dtor]tuple-type") (param i32) (call $dropped))
+                (func (export "{REFLECT}#[dtor]tuple-value") (param i32) (call $dropped))
+                (func (export "{DESERIALIZER}#[method]error.to-string") (param $rep i32) (result i32)
+                    (i32.store (i32.const 0x100)
+                        (select (i32.const 0x400) (i32.const 0x410)
+                            (i32.eq (local.get $rep) (i32.const 1))))
+                    (i32.store (i32.const 0x104)
+                        (select (i32.const 14) (i32.const 22)
+                            (i32.eq (local.get $rep) (i32.const 1))))
+                    (i32.const 0x100))
+                (func (export "{DESERIALIZER}#from-list")
+                    (param $ptr i32) (param $len i32) (param i32 i32 i32) (result i32)
+                    (if (result i32) (i32.ne (local.get $len) (i32.const 4))
+                        (then (call $err (i32.const 1)))
+                        (else (call $ok (i32.load (local.get $ptr))))))
+                (func (export "{DESERIALIZER}#[dtor]error") (param i32) (call $dropped))
+                (func (export "{STREAM}#[constructor]decoder") (param i32 i32 i32) (result i32)
+                    (global.set $pushed (i32.const 0))
+                    (call $created)
+                    (call $new-decoder (i32.const 1)))
+                (func (export "{STREAM}#[method]decoder.push")
+                    (param i32) (param $ptr i32) (param $len i32) (result i32)
+                    (if (i32.gt_u (i32.add (global.get $pushed) (local.get $len)) (i32.const 4))
+                        (then
+                            (call $created)
+                            (i32.store8 (i32.const 0x100) (i32.const 1))
+                            (i32.store (i32.const 0x104) (call $new-error (i32.const 2)))
+                            (return (i32.const 0x100))))
+                    (memory.copy
+                        (i32.add (i32.const 0x200) (global.get $pushed))
+                        (local.get $ptr)
+                        (local.get $len))
+                    (global.set $pushed (i32.add (global.get $pushed) (local.get $len)))
+                    (i32.store8 (i32.const 0x100) (i32.const 0))
+                    (i32.const 0x100))
+                (func (export "{STREAM}#[static]decoder.finish") (param i32) (result i32)
+                    (call $dropped)
+                    (if (result i32) (i32.ne (global.get $pushed) (i32.const 4))
+                        (then (call $err (i32.const 2)))
+                        (else (call $ok (i32.load (i32.const 0x200))))))
+                (func (export "{STREAM}#[dtor]decoder") (param i32) (call $dropped))"#
+        );
+        if type_builder {
+            wat.push_str(
+                r#"
+                (func (export "near-cm:codec/type-builder@0.1.0#build-tuple")
+                    (param i32 i32) (result i32)
+                    (call $created)
+                    (i32.store8 (i32.const 0x100) (i32.const 0))
+                    (i32.store (i32.const 0x104) (call $new-tuple-type (i32.const 1)))
+                    (i32.const 0x100))"#,
+            );
+        }
+        wat.push(')');
+
+        let mut resolve = wit_parser::Resolve::default();
+        let (pkg, _) = resolve
+            .push_path(concat!(env!("CARGO_MANIFEST_DIR"), "/wit"))
+            .expect("failed to parse WIT");
+        let world = if type_builder {
+            "codec"
+        } else {
+            "streaming-format"
+        };
+        let world = resolve
+            .select_world(&[pkg], Some(world))
+            .expect("world not found");
+        embed(&wat, &resolve, world)
+    }
+
+    /// Returns a contract with function `count`, which increments and returns a global,
+    /// and function `trap`, which traps.
+    pub(crate) fn counter() -> Vec<u8> {
+        component(
+            r#"(module
+                (global $n (mut i32) (i32.const 0))
+                (func (export "count") (result i32)
+                    (global.set $n (i32.add (global.get $n) (i32.const 1)))
+                    (global.get $n))
+                (func (export "trap") unreachable))"#,
+            "package test:counter; world counter { export count: func() -> u32; export trap: func(); }",
+        )
+    }
+
+    /// Returns a contract with function `spin`, which loops `n` times,
+    /// and function `forever`, which loops forever.
+    fn spinner() -> Vec<u8> {
+        component(
+            r#"(module
+                (func (export "spin") (param $n i32)
+                    (loop $l
+                        (br_if $l (local.tee $n (i32.sub (local.get $n) (i32.const 1))))))
+                (func (export "forever") (loop $l (br $l))))"#,
+            "package test:spinner; world spinner { export spin: func(n: u32); export forever: func(); }",
+        )
+    }
+
+    /// Returns a contract with resources `coin` and `note` in interface `test:vault/coins`,
+    /// functions `mint` and `write` returning them, and functions `peek`, `burn` and `merge`
+    /// taking coins.
+    fn vault() -> Vec<u8> {
+        const COINS: &str = "test:vault/coins";
+        component(
+            &format!(
+                r#"(module
+                    (import "[export]{COINS}" "[resource-new]coin"
+                        (func $new-coin (param i32) (result i32)))
+                    (import "[export]{COINS}" "[resource-new]note"
+                        (func $new-note (param i32) (result i32)))
+                    (func (export "{COINS}#mint") (result i32)
+                        (call $new-coin (i32.const 7)))
+                    (func (export "{COINS}#write") (result i32)
+                        (call $new-note (i32.const 1)))
+                    (func (export "{COINS}#peek") (param i32) (result i32)
+                        (local.get 0))
+                    (func (export "{COINS}#burn") (param i32) (result i32)
+                        (i32.const 1))
+                    (func (export "{COINS}#merge") (param i32 i32))
+                    (func (export "{COINS}#[dtor]coin") (param i32))
+                    (func (export "{COINS}#[dtor]note") (param i32)))"#
+            ),
+            r#"package test:vault;
+            interface coins {
+                resource coin;
+                resource note;
+                mint: func() -> coin;
+                write: func() -> note;
+                peek: func(c: borrow<coin>) -> u32;
+                burn: func(c: coin) -> u32;
+                merge: func(a: coin, b: coin);
+            }
+            world vault { export coins; }"#,
+        )
+    }
+
+    /// Message reported by [`panicker`]
+    const PANIC_MESSAGE: &str = "assertion `left == right` failed";
+
+    /// Returns a contract with function `foo`, which asserts its parameter to be `myfoo`
+    /// and reports [`PANIC_MESSAGE`] using `near-cm:host/panic` otherwise,
+    /// like the panic hook of `./contract/contract`.
+    fn panicker() -> Vec<u8> {
+        let wat = format!(
+            r#"(module
+                (import "near-cm:host/panic@0.1.0" "abort" (func $abort (param i32 i32)))
+                (memory (export "memory") 1)
+                (data (i32.const 0x100) "myfoo")
+                (data (i32.const 0x200) "{PANIC_MESSAGE}")
+                (global $heap (mut i32) (i32.const 0x1000))
+                (func (export "cabi_realloc") (param i32 i32 i32 i32) (result i32)
+                    (global.get $heap)
+                    (global.set $heap (i32.add (global.get $heap) (local.get 3))))
+                (func $foo (export "foo") (param $ptr i32) (param $len i32) (result i64)
+                    (if (i32.or
+                            (i32.ne (local.get $len) (i32.const 5))
+                            (i32.or
+                                (i32.ne (i32.load (local.get $ptr)) (i32.load (i32.const 0x100)))
+                                (i32.ne
+                                    (i32.load8_u offset=4 (local.get $ptr))
+                                    (i32.load8_u (i32.const 0x104)))))
+                        (then
+                            (call $abort (i32.const 0x200) (i32.const {len}))
+                            unreachable))
+                    (i64.const 42)))"#,
+            len = PANIC_MESSAGE.len(),
+        );
+        let mut resolve = wit_parser::Resolve::default();
+        resolve
+            .push_path(concat!(env!("CARGO_MANIFEST_DIR"), "/host/wit"))
+            .expect("failed to parse WIT");
+        let pkg = resolve
+            .push_str(
+                "test.wit",
+                "package test:panicker; world panicker { import near-cm:host/panic@0.1.0; export foo: func(foo: string) -> u64; }",
+            )
+            .expect("failed to parse WIT");
+        let world = resolve.select_world(&[pkg], None).expect("world not found");
+        embed(&wat, &resolve, world)
+    }
+
+    /// Body, which never yields a frame
+    struct Stalled;
+
+    impl Body for Stalled {
+        type Data = Bytes;
+        type Error = core::convert::Infallible;
+
+        fn poll_frame(
+            self: core::pin::Pin<&mut Self>,
+            _: &mut core::task::Context<'_>,
+        ) -> core::task::Poll<Option<Result<http_body::Frame<Bytes>, Self::Error>>> {
+            core::task::Poll::Pending
+        }
+    }
+
+    /// Returns a contract with function `run`, which traps on instantiation.
+    fn broken() -> Vec<u8> {
+        component(
+            r#"(module
+                (func $start unreachable)
+                (start $start)
+                (func (export "run") (param i32)))"#,
+            "package test:broken; world broken { export run: func(n: u32); }",
+        )
+    }
+
+    /// Body yielding chunks `.0` as separate frames without a size hint
+    struct Chunks(std::collections::VecDeque<Bytes>);
+
+    impl Chunks {
+        fn new(chunks: &[&'static [u8]]) -> Self {
+            Self(chunks.iter().copied().map(Bytes::from_static).collect())
+        }
+    }
+
+    impl Body for Chunks {
+        type Data = Bytes;
+        type Error = core::convert::Infallible;
+
+        fn poll_frame(
+            mut self: core::pin::Pin<&mut Self>,
+            _: &mut core::task::Context<'_>,
+        ) -> core::task::Poll<Option<Result<http_body::Frame<Bytes>, Self::Error>>> {
+            core::task::Poll::Ready(
+                self.0
+                    .pop_front()
+                    .map(|buf| Ok(http_body::Frame::data(buf))),
+            )
+        }
+    }
+
+    /// Returns a runtime with `options` and contracts `contracts` loaded.
+    pub(crate) fn runtime(options: Options, contracts: &[(&str, Vec<u8>)]) -> Runtime {
+        let mut runtime = Runtime::new(
+            &wasmtime::Config::new(),
+            storage::open("memory").expect("failed to open storage"),
+            options,
+        )
+        .expect("failed to create runtime");
+        for (name, wasm) in contracts {
+            runtime
+                .load_contract(name, wasm)
+                .expect("failed to load contract");
+        }
+        runtime
+    }
+
+    async fn count(runtime: &Runtime, contract: &str) -> Val {
+        let Outcome { mut results, .. } = runtime
+            .invoke_with(contract, "count", "json", b"[]", InvokeOptions::default())
+            .await
+            .expect("failed to invoke");
+        results.pop().expect("result missing")
+    }
+
+    #[tokio::test]
+    async fn gas_is_metered_and_limited() {
+        let runtime = runtime(
+            Options {
+                max_gas: 100_000,
+                ..Options::default()
+            },
+            &[("spinner", spinner())],
+        );
+        let spin = |n: u32, gas_limit| {
+            let params = format!("[{n}]");
+            let runtime = &runtime;
+            async move {
+                let opts = InvokeOptions {
+                    gas_limit,
+                    ..InvokeOptions::default()
+                };
+                runtime
+                    .invoke_with("spinner", "spin", "json", params.as_bytes(), opts)
+                    .await
+            }
+        };
+        let few = spin(10, None).await.expect("failed to invoke").gas_used;
+        let many = spin(1000, None).await.expect("failed to invoke").gas_used;
+        assert!(0 < few && few < many, "{few} {many}");
+        assert_eq!(
+            spin(10, None).await.expect("failed to invoke").gas_used,
+            few
+        );
+
+        // The exact amount of gas required suffices
+        let outcome = spin(10, Some(few)).await.expect("failed to invoke");
+        assert_eq!(outcome.gas_used, few);
+        let err = spin(1000, Some(few))
+            .await
+            .expect_err("invocation did not run out of gas");
+        assert!(err.is_out_of_gas(), "{err:?}");
+        assert_eq!(err.kind(), "out_of_gas");
+        assert!(matches!(err, Error::Call { gas_used, .. } if gas_used == few));
+        // Gas limits are clamped to the maximum
+        let err = spin(1_000_000, Some(u64::MAX))
+            .await
+            .expect_err("invocation did not run out of gas");
+        assert!(err.is_out_of_gas(), "{err:?}");
+        assert!(matches!(
+            err,
+            Error::Call {
+                gas_used: 100_000,
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn deadlines_are_enforced() {
+        let runtime = runtime(
+            Options {
+                max_gas: u64::MAX,
+                timeout: Duration::from_millis(50),
+                ..Options::default()
+            },
+            &[("spinner", spinner())],
+        );
+        let err = runtime
+            .invoke("spinner", "forever", "json", b"[]")
+            .await
+            .expect_err("invocation did not time out");
+        assert!(err.is_timeout(), "{err:?}");
+        assert_eq!(err.kind(), "timeout");
+
+        let err = runtime
+            .invoke_body_with("spinner", "spin", "json", Stalled, InvokeOptions::default())
+            .await
+            .expect_err("invocation did not time out");
+        assert!(matches!(err, Error::Timeout), "{err:?}");
+        assert_eq!(err.kind(), "timeout");
+    }
+
+    #[tokio::test]
+    async fn pooled_instances_are_not_reset() {
+        let mut runtime = Runtime::new(
+            &wasmtime::Config::new(),
+            storage::open("memory").expect("failed to open storage"),
+            Options::default(),
+        )
+        .expect("failed to create runtime");
+        let wasm = counter();
+        runtime
+            .load_contract("fresh", &wasm)
+            .expect("failed to load contract");
+        runtime
+            .load_contract_with("pooled", &wasm, ContractOptions { stateless: true })
+            .expect("failed to load contract");
+
+        assert_eq!(count(&runtime, "fresh").await, Val::U32(1));
+        assert_eq!(count(&runtime, "fresh").await, Val::U32(1));
+        // In-memory state survives in pooled instances
+        assert_eq!(count(&runtime, "pooled").await, Val::U32(1));
+        assert_eq!(count(&runtime, "pooled").await, Val::U32(2));
+        // Instances are not returned to the pool after failed invocations
+        let err = runtime
+            .invoke_with("pooled", "trap", "json", b"[]", InvokeOptions::default())
+            .await
+            .expect_err("invocation did not trap");
+        assert!(matches!(err, Error::Call { .. }), "{err:?}");
+        assert_eq!(count(&runtime, "pooled").await, Val::U32(1));
+    }
+
+    #[tokio::test]
+    async fn pooled_instances_are_retired() {
+        let mut runtime = runtime(
+            Options {
+                max_pooled_uses: 4,
+                max_pooled_table_elements: 10,
+                ..Options::default()
+            },
+            &[],
+        );
+        runtime
+            .load_contract_with("pooled", &counter(), ContractOptions { stateless: true })
+            .expect("failed to load contract");
+        let table = component(
+            r#"(module
+                (global $n (mut i32) (i32.const 0))
+                (table $t 1 funcref)
+                (func (export "count") (result i32)
+                    (global.set $n (i32.add (global.get $n) (i32.const 1)))
+                    (global.get $n))
+                (func (export "grow") (param i32)
+                    (drop (table.grow $t (ref.null func) (local.get 0)))))"#,
+            "package test:table; world table { export count: func() -> u32; export grow: func(n: u32); }",
+        );
+        runtime
+            .load_contract_with("table", &table, ContractOptions { stateless: true })
+            .expect("failed to load contract");
+
+        // Instances are retired after serving the maximum number of invocations
+        for n in [1, 2, 3, 4, 1, 2, 3, 4, 1] {
+            assert_eq!(count(&runtime, "pooled").await, Val::U32(n));
+        }
+
+        // Instances are retired once their tables grow beyond the maximum
+        let grow = async |n: u32| {
+            runtime
+                .invoke("table", "grow", "json", format!("[{n}]").as_bytes())
+                .await
+                .expect("failed to invoke");
+        };
+        assert_eq!(count(&runtime, "table").await, Val::U32(1));
+        grow(10).await;
+        assert_eq!(count(&runtime, "table").await, Val::U32(1));
+        grow(9).await;
+        assert_eq!(count(&runtime, "table").await, Val::U32(2));
+    }
+
+    #[tokio::test]
+    async fn bodies_are_streamed_into_codecs() {
+        let runtime = runtime(
+            Options::default(),
+            &[
+                ("spinner", spinner()),
+                ("u32", u32_codec(false)),
+                ("u32_builder", u32_codec(true)),
+            ],
+        );
+        for codec in ["u32", "u32_builder"] {
+            let invoke = |chunks| {
+                runtime.invoke_body_with(
+                    "spinner",
+                    "spin",
+                    codec,
+                    Chunks::new(chunks),
+                    InvokeOptions::default(),
+                )
+            };
+            invoke(&[b"\x01\0", b"\0", b"\0"])
+                .await
+                .expect("failed to invoke");
+            let err = invoke(&[b"\x01\0", b"\0\0", b"\0"])
+                .await
+                .expect_err("trailing byte decoded");
+            assert!(
+                matches!(&err, Error::Decode(err) if format!("{err:#}").contains("stream: invalid length")),
+                "{err:?}"
+            );
+            let err = invoke(&[b"\x01\0"])
+                .await
+                .expect_err("missing bytes decoded");
+            assert!(
+                matches!(&err, Error::Decode(err) if format!("{err:#}").contains("stream: invalid length")),
+                "{err:?}"
+            );
+
+            // Buffers are deserialized as a whole
+            runtime
+                .invoke_with(
+                    "spinner",
+                    "spin",
+                    codec,
+                    b"\x01\0\0\0",
+                    InvokeOptions::default(),
+                )
+                .await
+                .expect("failed to invoke");
+            let err = runtime
+                .invoke_with(
+                    "spinner",
+                    "spin",
+                    codec,
+                    b"\x01\0\0",
+                    InvokeOptions::default(),
+                )
+                .await
+                .expect_err("missing byte decoded");
+            assert!(
+                matches!(&err, Error::Decode(err) if !format!("{err:#}").contains("stream:")),
+                "{err:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn body_size_is_limited() {
+        let runtime = runtime(
+            Options {
+                max_body_size: 4,
+                ..Options::default()
+            },
+            &[
+                ("broken", broken()),
+                ("spinner", spinner()),
+                ("u32", u32_codec(false)),
+            ],
+        );
+        // Sizes known upfront are checked before anything is instantiated
+        let err = runtime
+            .invoke_with("broken", "run", "json", b"[1] ", InvokeOptions::default())
+            .await
+            .expect_err("instantiation did not fail");
+        assert!(matches!(err, Error::Instantiate { .. }), "{err:?}");
+        let err = runtime
+            .invoke_with("broken", "run", "json", b"[1]  ", InvokeOptions::default())
+            .await
+            .expect_err("body too large decoded");
+        assert!(err.is_body_too_large(), "{err:?}");
+        let body = http_body_util::Full::new(Bytes::from_static(b"[1]  "));
+        let err = runtime
+            .invoke_body_with("broken", "run", "json", body, InvokeOptions::default())
+            .await
+            .expect_err("body too large decoded");
+        assert!(err.is_body_too_large(), "{err:?}");
+
+        // Bodies without a size hint fail once they exceed the limit
+        for (codec, chunks) in [
+            ("json", [&b"[1"[..], b"]", b"   "]),
+            ("u32", [&b"\x01\0"[..], b"\0\0", b"\0"]),
+        ] {
+            let err = runtime
+                .invoke_body_with(
+                    "spinner",
+                    "spin",
+                    codec,
+                    Chunks::new(&chunks),
+                    InvokeOptions::default(),
+                )
+                .await
+                .expect_err("body too large decoded");
+            assert!(err.is_body_too_large(), "{codec}: {err:?}");
+        }
+
+        let mut session = runtime
+            .new_session("spinner", None)
+            .await
+            .expect("failed to create session");
+        let body = http_body_util::Full::new(Bytes::from_static(b"[1]  "));
+        let err = runtime
+            .invoke_body_in(&mut session, "spin", "json", body, None)
+            .await
+            .expect_err("body too large decoded");
+        assert!(err.is_body_too_large(), "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn codec_resources_are_dropped() {
+        let runtime = runtime(
+            Options {
+                max_body_size: 8,
+                ..Options::default()
+            },
+            &[
+                ("spinner", spinner()),
+                ("u32", u32_codec(false)),
+                ("u32_builder", u32_codec(true)),
+            ],
+        );
+        let mut session = runtime
+            .new_session("spinner", None)
+            .await
+            .expect("failed to create session");
+        // The codec instances cached by the session trap once they leak resources
+        for _ in 0..50 {
+            for codec in ["u32", "u32_builder"] {
+                runtime
+                    .invoke_in(&mut session, "spin", codec, b"\x01\0\0\0", None)
+                    .await
+                    .expect("failed to invoke");
+                runtime
+                    .invoke_body_in(
+                        &mut session,
+                        "spin",
+                        codec,
+                        Chunks::new(&[b"\x01\0\0\0"]),
+                        None,
+                    )
+                    .await
+                    .expect("failed to invoke");
+                for chunks in [
+                    &[&b"\x01"[..]][..],
+                    &[b"\x01\0\0\0\0"],
+                    &[b"\0\0\0\0", b"\0\0\0\0\0"],
+                ] {
+                    let err = runtime
+                        .invoke_body_in(&mut session, "spin", codec, Chunks::new(chunks), None)
+                        .await
+                        .expect_err("invalid parameters decoded");
+                    assert!(
+                        matches!(&err, Error::Decode(err) if !err.is::<Trap>()),
+                        "{err:?}"
+                    );
+                }
+                let err = runtime
+                    .invoke_in(&mut session, "spin", codec, b"\x01", None)
+                    .await
+                    .expect_err("invalid parameters decoded");
+                assert!(
+                    matches!(&err, Error::Decode(err) if format!("{err:#}").contains("invalid length")),
+                    "{err:?}"
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn resource_handles_are_resolved() {
+        let runtime = runtime(Options::default(), &[("vault", vault())]);
+        let err = runtime
+            .invoke("vault", "test:vault/coins#mint", "json", b"[]")
+            .await
+            .expect_err("resource returned outside of a session");
+        assert!(err.is_session_required(), "{err:?}");
+
+        let mut session = runtime
+            .new_session("vault", None)
+            .await
+            .expect("failed to create session");
+        let mut invoke = async |func: &str, params: &str| {
+            runtime
+                .invoke_in(
+                    &mut session,
+                    &format!("test:vault/coins#{func}"),
+                    "json",
+                    params.as_bytes(),
+                    None,
+                )
+                .await
+                .map(|outcome| outcome.results)
+        };
+        let decode_err = |err| match err {
+            Error::Decode(err) => format!("{err:#}"),
+            err => panic!("unexpected error: {err:?}"),
+        };
+        // Returned resources are inserted into the table
+        assert_eq!(
+            invoke("mint", "[]").await.expect("failed to invoke"),
+            [Val::U32(0)]
+        );
+        assert_eq!(
+            invoke("mint", "[]").await.expect("failed to invoke"),
+            [Val::U32(1)]
+        );
+        assert_eq!(
+            invoke("write", "[]").await.expect("failed to invoke"),
+            [Val::U32(2)]
+        );
+        assert_eq!(
+            invoke("peek", "[0]").await.expect("failed to invoke"),
+            [Val::U32(7)]
+        );
+
+        // Resources of other types are rejected
+        let err = decode_err(
+            invoke("burn", "[2]")
+                .await
+                .expect_err("invalid handles resolved"),
+        );
+        assert!(err.contains("different type"), "{err}");
+        // Ownership can only be transferred once
+        let err = decode_err(
+            invoke("merge", "[1, 1]")
+                .await
+                .expect_err("invalid handles resolved"),
+        );
+        assert!(err.contains("passed as `own` twice"), "{err}");
+        // Handles are only removed once parameters are resolved
+        assert_eq!(
+            invoke("peek", "[1]").await.expect("failed to invoke"),
+            [Val::U32(7)]
+        );
+
+        // Owned handles are removed from the table, borrowed ones are not
+        assert_eq!(
+            invoke("burn", "[0]").await.expect("failed to invoke"),
+            [Val::U32(1)]
+        );
+        let err = decode_err(
+            invoke("peek", "[0]")
+                .await
+                .expect_err("invalid handles resolved"),
+        );
+        assert!(err.contains("handle `0` not found"), "{err}");
+        let err = decode_err(
+            invoke("burn", "[0]")
+                .await
+                .expect_err("invalid handles resolved"),
+        );
+        assert!(err.contains("handle `0` not found"), "{err}");
+        assert_eq!(
+            invoke("burn", "[1]").await.expect("failed to invoke"),
+            [Val::U32(1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn bodies_are_read_within_limits() {
+        let runtime = runtime(
+            Options {
+                max_body_size: 4,
+                timeout: Duration::from_millis(50),
+                ..Options::default()
+            },
+            &[],
+        );
+        let buf = runtime
+            .read_body(Chunks::new(&[b"\x01\0", b"\0\0"]))
+            .await
+            .expect("failed to read body");
+        assert_eq!(buf, b"\x01\0\0\0");
+        let err = runtime
+            .read_body(Chunks::new(&[b"\x01\0", b"\0\0\0"]))
+            .await
+            .expect_err("body exceeding the limit read");
+        assert!(err.is_body_too_large(), "{err:?}");
+        let err = runtime
+            .read_body(http_body_util::Full::new(Bytes::from_static(
+                b"\x01\0\0\0\0",
+            )))
+            .await
+            .expect_err("body exceeding the limit read");
+        assert!(err.is_body_too_large(), "{err:?}");
+        let err = runtime
+            .read_body(Stalled)
+            .await
+            .expect_err("reading the body did not time out");
+        assert!(matches!(err, Error::Timeout), "{err:?}");
+    }
+
+    #[tokio::test]
+    async fn batches_are_limited() {
+        let gas = runtime(Options::default(), &[("spinner", spinner())])
+            .invoke("spinner", "spin", "json", b"[1000]")
+            .await
+            .expect("failed to invoke")
+            .gas_used;
+        let runtime = Arc::new(runtime(
+            Options {
+                max_batch_len: 4,
+                max_batch_gas: 2 * gas + gas / 2,
+                ..Options::default()
+            },
+            &[("spinner", spinner())],
+        ));
+        let batch = |n: usize| {
+            let call = r#"{"contract": "spinner", "func": "spin", "args": [1000]}"#;
+            format!("[{}]", vec![call; n].join(","))
+        };
+        let invoke = async |n: usize, gas_limit, parallel| {
+            let opts = InvokeOptions {
+                gas_limit,
+                ..InvokeOptions::default()
+            };
+            let buf = runtime
+                .invoke_batch("json", batch(n).as_bytes(), opts, parallel)
+                .await?;
+            let outcomes: Vec<serde_json::Value> =
+                serde_json::from_slice(&buf).expect("failed to parse outcomes");
+            Ok::<_, Error>(outcomes)
+        };
+
+        let err = invoke(5, None, false)
+            .await
+            .expect_err("batch exceeding the limit invoked");
+        assert!(err.is_batch_too_large(), "{err:?}");
+        assert_eq!(err.kind(), "batch_too_large");
+
+        // The budget runs out while the third invocation runs
+        let outcomes = invoke(4, None, false).await.expect("failed to invoke");
+        assert_eq!(outcomes[0]["gas_used"], gas);
+        assert_eq!(outcomes[1]["gas_used"], gas);
+        let err = outcomes[2]["err"].as_str().expect("invocation succeeded");
+        assert!(err.contains("fuel"), "{err}");
+        assert_eq!(
+            outcomes[3]["err"], "gas budget of the batch exhausted",
+            "{outcomes:?}"
+        );
+
+        // Invocations running concurrently share the budget
+        let outcomes = invoke(4, Some(gas), true).await.expect("failed to invoke");
+        let ok = outcomes.iter().filter(|outcome| outcome["ok"].is_array());
+        assert_eq!(ok.count(), 2, "{outcomes:?}");
+    }
+
+    #[tokio::test]
+    async fn panics_are_reported() {
+        let backtrace = |err: &Error| {
+            err.wasmtime_error()
+                .and_then(|err| err.downcast_ref::<wasmtime::WasmBacktrace>())
+                .map(ToString::to_string)
+        };
+        for debug in [false, true] {
+            let runtime = runtime(
+                Options {
+                    debug,
+                    ..Options::default()
+                },
+                &[("contract", panicker())],
+            );
+            let outcome = runtime
+                .invoke("contract", "foo", "json", br#"["myfoo"]"#)
+                .await
+                .expect("failed to invoke");
+            assert_eq!(outcome.results, [Val::U64(42)]);
+
+            let err = runtime
+                .invoke("contract", "foo", "json", br#"["nofoo"]"#)
+                .await
+                .expect_err("assertion did not fail");
+            assert_eq!(
+                err.guest_panic(),
+                Some(&GuestPanic {
+                    contract: "contract".into(),
+                    message: PANIC_MESSAGE.into(),
+                })
+            );
+            assert_eq!(err.kind(), "panic");
+            // Backtraces are only captured in debug mode
+            match backtrace(&err) {
+                Some(backtrace) => {
+                    assert!(debug, "{backtrace}");
+                    assert!(backtrace.contains("foo"), "{backtrace}");
+                }
+                None => assert!(!debug, "{err:?}"),
+            }
+        }
+    }
+}
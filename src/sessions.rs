@@ -0,0 +1,472 @@
+//! Long-lived [`Session`]s addressed by unguessable session IDs minted on creation
+
+use core::fmt::{self, Write as _};
+use core::ops::{Deref, DerefMut};
+use core::time::Duration;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Instant;
+
+use tokio::sync::OwnedMutexGuard;
+
+use crate::{Runtime, Session};
+
+/// Default duration after which idle sessions are evicted
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Default maximum number of concurrent sessions
+pub const DEFAULT_MAX_SESSIONS: usize = 64;
+
+/// Session options
+#[derive(Clone, Copy, Debug)]
+pub struct Options {
+    /// Duration after which sessions, which are not in use, are evicted
+    pub idle_timeout: Duration,
+    /// Maximum number of concurrent sessions
+    pub max_sessions: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            max_sessions: DEFAULT_MAX_SESSIONS,
+        }
+    }
+}
+
+/// Number of random bytes in a session ID
+const ID_BYTES: usize = 16;
+
+/// Session creation and acquisition error
+#[derive(Debug)]
+pub enum Error {
+    /// The session does not exist, e.g. because it was evicted
+    NotFound,
+    /// The maximum number of concurrent sessions is reached
+    Limit { maximum: usize },
+    /// The session is bound to a different contract
+    Contract { contract: Box<str> },
+    /// Minting the session ID failed
+    Id(getrandom::Error),
+    /// Creating the session failed
+    Runtime(crate::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound => f.write_str("session not found"),
+            Self::Limit { maximum } => {
+                write!(f, "maximum number of {maximum} concurrent sessions reached")
+            }
+            Self::Contract { contract } => {
+                write!(f, "session is bound to contract `{contract}`")
+            }
+            Self::Id(err) => write!(f, "failed to mint session ID: {err}"),
+            Self::Runtime(err) => write!(f, "failed to create session: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+type Slot = Arc<tokio::sync::Mutex<Session>>;
+
+struct Entry {
+    contract: Box<str>,
+    slot: Slot,
+    last_used: Instant,
+}
+
+/// Registry of sessions by ID
+///
+/// Invocations within a session are serialized, sessions not used for longer than
+/// [`Options::idle_timeout`] are evicted by [`Sessions::evict_idle`] and on acquisition
+/// of any session.
+pub struct Sessions {
+    options: Options,
+    entries: Mutex<HashMap<Box<str>, Entry>>,
+}
+
+impl Sessions {
+    /// Creates an empty session registry.
+    pub fn new(options: Options) -> Self {
+        Self {
+            options,
+            entries: Mutex::default(),
+        }
+    }
+
+    /// Returns the options of the registry.
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    /// Returns the number of sessions.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Returns `true` if there are no sessions.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Creates a session by instantiating `contract`, with root function imports forwarded
+    /// to an instance of component `target`, if set, see [`Runtime::new_session`].
+    ///
+    /// Returns the ID of the session, which is minted from random bytes.
+    pub async fn create(
+        &self,
+        runtime: &Runtime,
+        contract: &str,
+        target: Option<&str>,
+    ) -> Result<Box<str>, Error> {
+        // Fail early, before instantiating the contract
+        let evicted = {
+            let mut entries = self.lock();
+            let evicted = self.evict_idle_locked(&mut entries, Instant::now());
+            if entries.len() >= self.options.max_sessions {
+                return Err(Error::Limit {
+                    maximum: self.options.max_sessions,
+                });
+            }
+            evicted
+        };
+        drop(evicted);
+        let session = runtime
+            .new_session(contract, target)
+            .await
+            .map_err(Error::Runtime)?;
+        let id = new_id().map_err(Error::Id)?;
+        let mut entries = self.lock();
+        if entries.len() >= self.options.max_sessions {
+            drop(entries);
+            return Err(Error::Limit {
+                maximum: self.options.max_sessions,
+            });
+        }
+        entries.insert(
+            id.clone(),
+            Entry {
+                contract: contract.into(),
+                slot: Arc::new(tokio::sync::Mutex::new(session)),
+                last_used: Instant::now(),
+            },
+        );
+        Ok(id)
+    }
+
+    /// Acquires exclusive access to session `id`, which has to be bound to `contract`, if set,
+    /// waiting for an in-flight invocation within the session to complete.
+    pub async fn acquire(&self, id: &str, contract: Option<&str>) -> Result<Guard<'_>, Error> {
+        let slot = {
+            let now = Instant::now();
+            let mut entries = self.lock();
+            let evicted = self.evict_idle_locked(&mut entries, now);
+            let slot = match entries.get_mut(id) {
+                Some(entry) if contract.is_some_and(|contract| *entry.contract != *contract) => {
+                    Err(Error::Contract {
+                        contract: entry.contract.clone(),
+                    })
+                }
+                Some(entry) => {
+                    entry.last_used = now;
+                    Ok(Arc::clone(&entry.slot))
+                }
+                None => Err(Error::NotFound),
+            };
+            drop(entries);
+            // Stores are dropped outside of the lock
+            drop(evicted);
+            slot?
+        };
+        let session = Arc::clone(&slot).lock_owned().await;
+        // The session may have been evicted or removed meanwhile
+        if !self.is_registered(id, &slot) {
+            return Err(Error::NotFound);
+        }
+        Ok(Guard {
+            sessions: self,
+            id: id.into(),
+            slot,
+            session,
+        })
+    }
+
+    /// Removes session `id`, returns `false` if there is no such session.
+    ///
+    /// An in-flight invocation within the session completes, but the session
+    /// cannot be acquired anymore.
+    pub fn remove(&self, id: &str) -> bool {
+        let entry = self.lock().remove(id);
+        entry.is_some()
+    }
+
+    /// Evicts sessions, which have not been used for longer than [`Options::idle_timeout`],
+    /// and returns the number of evicted sessions.
+    pub fn evict_idle(&self) -> usize {
+        let mut entries = self.lock();
+        let evicted = self.evict_idle_locked(&mut entries, Instant::now());
+        drop(entries);
+        evicted.len()
+    }
+
+    fn evict_idle_locked(
+        &self,
+        entries: &mut HashMap<Box<str>, Entry>,
+        now: Instant,
+    ) -> Vec<Entry> {
+        let idle_timeout = self.options.idle_timeout;
+        entries
+            .extract_if(|_, entry| {
+                now.saturating_duration_since(entry.last_used) >= idle_timeout
+                    && entry.slot.try_lock().is_ok()
+            })
+            .map(|(_, entry)| entry)
+            .collect()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<Box<str>, Entry>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn is_registered(&self, id: &str, slot: &Slot) -> bool {
+        self.lock()
+            .get(id)
+            .is_some_and(|entry| Arc::ptr_eq(&entry.slot, slot))
+    }
+
+    fn remove_slot(&self, id: &str, slot: &Slot) {
+        let mut entries = self.lock();
+        if entries
+            .get(id)
+            .is_some_and(|entry| Arc::ptr_eq(&entry.slot, slot))
+        {
+            entries.remove(id);
+        }
+    }
+}
+
+/// Exclusive access to a session acquired by [`Sessions::acquire`]
+pub struct Guard<'a> {
+    sessions: &'a Sessions,
+    id: Box<str>,
+    slot: Slot,
+    session: OwnedMutexGuard<Session>,
+}
+
+impl Guard<'_> {
+    /// Removes the session from the registry, e.g. after an invocation within it trapped.
+    pub fn discard(self) {
+        self.sessions.remove_slot(&self.id, &self.slot);
+    }
+}
+
+impl Deref for Guard<'_> {
+    type Target = Session;
+
+    fn deref(&self) -> &Session {
+        &self.session
+    }
+}
+
+impl DerefMut for Guard<'_> {
+    fn deref_mut(&mut self) -> &mut Session {
+        &mut self.session
+    }
+}
+
+impl Drop for Guard<'_> {
+    fn drop(&mut self) {
+        // Sessions are idle from the end of their last invocation on
+        let mut entries = self.sessions.lock();
+        if let Some(entry) = entries.get_mut(&self.id)
+            && Arc::ptr_eq(&entry.slot, &self.slot)
+        {
+            entry.last_used = Instant::now();
+        }
+    }
+}
+
+/// Returns a new session ID consisting of hex-encoded random bytes.
+fn new_id() -> Result<Box<str>, getrandom::Error> {
+    let mut buf = [0; ID_BYTES];
+    getrandom::fill(&mut buf)?;
+    let mut id = String::with_capacity(2 * ID_BYTES);
+    for b in buf {
+        write!(id, "{b:02x}").expect("failed to write to string");
+    }
+    Ok(id.into())
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use super::*;
+
+    use crate::runtime::tests::{counter, runtime};
+
+    fn contracts() -> Runtime {
+        runtime(
+            crate::Options::default(),
+            &[("a", counter()), ("b", counter())],
+        )
+    }
+
+    #[tokio::test]
+    async fn sessions_are_limited() {
+        let runtime = contracts();
+        let sessions = Sessions::new(Options {
+            max_sessions: 2,
+            ..Options::default()
+        });
+        let id = sessions
+            .create(&runtime, "a", None)
+            .await
+            .expect("failed to create session");
+        sessions
+            .create(&runtime, "b", None)
+            .await
+            .expect("failed to create session");
+        let err = sessions
+            .create(&runtime, "a", None)
+            .await
+            .expect_err("session created beyond the limit");
+        assert!(matches!(err, Error::Limit { maximum: 2 }), "{err:?}");
+        assert_eq!(sessions.len(), 2);
+
+        assert!(sessions.remove(&id));
+        assert!(!sessions.remove(&id));
+        sessions
+            .create(&runtime, "a", None)
+            .await
+            .expect("failed to create session");
+    }
+
+    #[tokio::test]
+    async fn idle_sessions_are_evicted() {
+        let runtime = contracts();
+        let sessions = Sessions::new(Options {
+            idle_timeout: Duration::from_millis(50),
+            ..Options::default()
+        });
+        let idle = sessions
+            .create(&runtime, "a", None)
+            .await
+            .expect("failed to create session");
+        let busy = sessions
+            .create(&runtime, "a", None)
+            .await
+            .expect("failed to create session");
+        drop(
+            sessions
+                .acquire(&idle, None)
+                .await
+                .expect("session not found"),
+        );
+        let guard = sessions
+            .acquire(&busy, None)
+            .await
+            .expect("session not found");
+        assert_eq!(sessions.evict_idle(), 0);
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        // Sessions in use are not evicted
+        assert_eq!(sessions.evict_idle(), 1);
+        let err = sessions
+            .acquire(&idle, None)
+            .await
+            .err()
+            .expect("evicted session acquired");
+        assert!(matches!(err, Error::NotFound), "{err:?}");
+
+        // Sessions are idle from their release on
+        drop(guard);
+        assert_eq!(sessions.evict_idle(), 0);
+        sessions
+            .acquire(&busy, None)
+            .await
+            .expect("session not found");
+    }
+
+    #[tokio::test]
+    async fn sessions_are_bound_to_contracts() {
+        let runtime = contracts();
+        let sessions = Sessions::new(Options::default());
+        let id = sessions
+            .create(&runtime, "a", None)
+            .await
+            .expect("failed to create session");
+        let err = sessions
+            .acquire(&id, Some("b"))
+            .await
+            .err()
+            .expect("session acquired for a different contract");
+        assert!(
+            matches!(&err, Error::Contract { contract } if &**contract == "a"),
+            "{err:?}"
+        );
+        sessions
+            .acquire(&id, Some("a"))
+            .await
+            .expect("session not found");
+        sessions
+            .acquire(&id, None)
+            .await
+            .expect("session not found");
+
+        let err = sessions
+            .create(&runtime, "c", None)
+            .await
+            .expect_err("session created for unknown contract");
+        assert!(
+            matches!(err, Error::Runtime(crate::Error::NotFound(..))),
+            "{err:?}"
+        );
+        assert_eq!(sessions.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn sessions_are_removed_while_in_use() {
+        let runtime = contracts();
+        let sessions = Sessions::new(Options::default());
+        let id = sessions
+            .create(&runtime, "a", None)
+            .await
+            .expect("failed to create session");
+        let mut guard = sessions
+            .acquire(&id, None)
+            .await
+            .expect("session not found");
+        // Invocations waiting for the session fail once it is removed
+        let (res, ()) = tokio::join!(sessions.acquire(&id, None), async {
+            assert!(sessions.remove(&id));
+            // The in-flight invocation completes
+            runtime
+                .invoke_in(&mut guard, "count", "json", b"[]", None)
+                .await
+                .expect("failed to invoke");
+            drop(guard);
+        });
+        let err = res.err().expect("removed session acquired");
+        assert!(matches!(err, Error::NotFound), "{err:?}");
+        assert!(sessions.is_empty());
+
+        let id = sessions
+            .create(&runtime, "a", None)
+            .await
+            .expect("failed to create session");
+        let guard = sessions
+            .acquire(&id, None)
+            .await
+            .expect("session not found");
+        let (res, ()) = tokio::join!(sessions.acquire(&id, None), async {
+            guard.discard();
+        });
+        let err = res.err().expect("discarded session acquired");
+        assert!(matches!(err, Error::NotFound), "{err:?}");
+        assert!(sessions.is_empty());
+    }
+}
@@ -0,0 +1,698 @@
+use core::cmp::Ordering;
+use core::fmt;
+use core::ops::Bound;
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+
+use anyhow::bail;
+use wasmtime::StoreContextMut;
+use wasmtime::component::Linker;
+
+/// A key-value pair returned by [`Storage::scan`].
+pub type Entry = (Vec<u8>, Vec<u8>);
+
+/// Changes to apply to [`Storage`] per namespace, `None` values denote removals.
+pub type Changes = BTreeMap<Arc<str>, BTreeMap<Vec<u8>, Option<Vec<u8>>>>;
+
+/// Key-value storage backend shared by all contracts.
+///
+/// All keys are scoped by a `namespace`, which is the name of the contract
+/// performing the operation.
+///
+/// Contracts never write to the backend directly, all changes are buffered
+/// in a [`Transaction`] and applied by [`Storage::commit`].
+///
+/// Concurrent transactions are isolated optimistically: each one records the committed
+/// state it observed in a [`ReadSet`], which is validated by [`Storage::commit`].
+pub trait Storage: Send + Sync {
+    fn read(&self, namespace: &str, key: &[u8]) -> anyhow::Result<Option<Vec<u8>>>;
+
+    fn has_key(&self, namespace: &str, key: &[u8]) -> anyhow::Result<bool> {
+        self.read(namespace, key).map(|v| v.is_some())
+    }
+
+    /// Returns at most `limit` entries with keys starting with `prefix` in
+    /// ascending key order, skipping all keys less than or equal to `start_after`.
+    fn scan(
+        &self,
+        namespace: &str,
+        prefix: &[u8],
+        start_after: Option<&[u8]>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<Entry>>;
+
+    /// Atomically validates `reads`, see [`ReadSet::validate`], and applies `changes`.
+    ///
+    /// Commits must be serialized, so that no other commit changes the state between
+    /// validation and application.
+    fn commit(&self, reads: &ReadSet, changes: Changes) -> anyhow::Result<()>;
+}
+
+/// Error returned by [`Storage::commit`] if state read by a transaction was changed
+/// by a concurrently committed transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Conflict;
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("state read by the invocation was changed concurrently")
+    }
+}
+
+impl std::error::Error for Conflict {}
+
+/// Read of committed state
+#[derive(Debug)]
+enum Read {
+    Value {
+        namespace: Arc<str>,
+        key: Vec<u8>,
+        value: Option<Vec<u8>>,
+    },
+    HasKey {
+        namespace: Arc<str>,
+        key: Vec<u8>,
+        ok: bool,
+    },
+    Scan {
+        namespace: Arc<str>,
+        prefix: Vec<u8>,
+        start_after: Option<Vec<u8>>,
+        limit: usize,
+        entries: Vec<Entry>,
+    },
+}
+
+/// Committed state observed by a [`Transaction`]
+#[derive(Debug, Default)]
+pub struct ReadSet(Vec<Read>);
+
+impl ReadSet {
+    /// Returns `true` if no committed state was read.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Fails with [`Conflict`] if repeating any of the reads on `storage` would observe
+    /// a different result.
+    pub fn validate(&self, storage: &(impl Storage + ?Sized)) -> anyhow::Result<()> {
+        for read in &self.0 {
+            let ok = match read {
+                Read::Value {
+                    namespace,
+                    key,
+                    value,
+                } => storage.read(namespace, key)? == *value,
+                Read::HasKey { namespace, key, ok } => storage.has_key(namespace, key)? == *ok,
+                Read::Scan {
+                    namespace,
+                    prefix,
+                    start_after,
+                    limit,
+                    entries,
+                } => storage.scan(namespace, prefix, start_after.as_deref(), *limit)? == *entries,
+            };
+            if !ok {
+                return Err(Conflict.into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the lower bound of a prefix scan.
+fn scan_start<'a>(prefix: &'a [u8], start_after: Option<&'a [u8]>) -> Bound<&'a [u8]> {
+    match start_after {
+        Some(key) if key >= prefix => Bound::Excluded(key),
+        _ => Bound::Included(prefix),
+    }
+}
+
+type Namespace = BTreeMap<Vec<u8>, Vec<u8>>;
+
+/// In-memory [`Storage`], state is lost on restart.
+#[derive(Default)]
+pub struct MemoryStorage {
+    namespaces: RwLock<BTreeMap<Box<str>, Namespace>>,
+    /// Serializes commits
+    commit: Mutex<()>,
+}
+
+impl Storage for MemoryStorage {
+    fn read(&self, namespace: &str, key: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+        let namespaces = self
+            .namespaces
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        Ok(namespaces
+            .get(namespace)
+            .and_then(|entries| entries.get(key))
+            .cloned())
+    }
+
+    fn scan(
+        &self,
+        namespace: &str,
+        prefix: &[u8],
+        start_after: Option<&[u8]>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<Entry>> {
+        let namespaces = self
+            .namespaces
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        let Some(entries) = namespaces.get(namespace) else {
+            return Ok(Vec::default());
+        };
+        Ok(entries
+            .range::<[u8], _>((scan_start(prefix, start_after), Bound::Unbounded))
+            .take_while(|(k, _)| k.starts_with(prefix))
+            .take(limit)
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect())
+    }
+
+    fn commit(&self, reads: &ReadSet, changes: Changes) -> anyhow::Result<()> {
+        let _commit = self.commit.lock().unwrap_or_else(PoisonError::into_inner);
+        reads.validate(self)?;
+        let mut namespaces = self
+            .namespaces
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        for (namespace, changes) in changes {
+            let entries = namespaces.entry(namespace.as_ref().into()).or_default();
+            for (key, value) in changes {
+                if let Some(value) = value {
+                    entries.insert(key, value);
+                } else {
+                    entries.remove(&key);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// [`Storage`] persisted on disk in a [`sled`] database.
+///
+/// All namespaces share a single tree, keys are prefixed by the length of the
+/// namespace followed by the namespace itself.
+#[cfg(feature = "sled")]
+pub struct SledStorage {
+    db: sled::Db,
+    /// Serializes commits
+    commit: Mutex<()>,
+}
+
+#[cfg(feature = "sled")]
+impl SledStorage {
+    pub fn open(path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        use anyhow::Context as _;
+
+        let db = sled::open(path).context("failed to open sled database")?;
+        Ok(Self {
+            db,
+            commit: Mutex::default(),
+        })
+    }
+
+    fn key(namespace: &str, key: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let len = u32::try_from(namespace.len())?;
+        let mut buf = Vec::with_capacity(4 + namespace.len() + key.len());
+        buf.extend_from_slice(&len.to_be_bytes());
+        buf.extend_from_slice(namespace.as_bytes());
+        buf.extend_from_slice(key);
+        Ok(buf)
+    }
+}
+
+#[cfg(feature = "sled")]
+impl Storage for SledStorage {
+    fn read(&self, namespace: &str, key: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+        let v = self.db.get(Self::key(namespace, key)?)?;
+        Ok(v.map(|v| v.to_vec()))
+    }
+
+    fn scan(
+        &self,
+        namespace: &str,
+        prefix: &[u8],
+        start_after: Option<&[u8]>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<Entry>> {
+        let header = Self::key(namespace, &[])?.len();
+        let start = match scan_start(prefix, start_after) {
+            Bound::Included(key) => Bound::Included(Self::key(namespace, key)?),
+            Bound::Excluded(key) => Bound::Excluded(Self::key(namespace, key)?),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let prefix = Self::key(namespace, prefix)?;
+        let mut entries = Vec::default();
+        for entry in self.db.range((start, Bound::Unbounded)) {
+            let (k, v) = entry?;
+            if entries.len() >= limit || !k.starts_with(&prefix) {
+                break;
+            }
+            entries.push((k[header..].to_vec(), v.to_vec()));
+        }
+        Ok(entries)
+    }
+
+    fn commit(&self, reads: &ReadSet, changes: Changes) -> anyhow::Result<()> {
+        let _commit = self.commit.lock().unwrap_or_else(PoisonError::into_inner);
+        reads.validate(self)?;
+        let mut batch = sled::Batch::default();
+        for (namespace, changes) in changes {
+            for (key, value) in changes {
+                let key = Self::key(&namespace, &key)?;
+                if let Some(value) = value {
+                    batch.insert(key, value);
+                } else {
+                    batch.remove(key);
+                }
+            }
+        }
+        self.db.apply_batch(batch)?;
+        Ok(())
+    }
+}
+
+/// Opens a [`Storage`] backend given a specification of form `memory` or `sled:<path>`.
+pub fn open(spec: &str) -> anyhow::Result<Arc<dyn Storage>> {
+    match spec.split_once(':') {
+        None if spec == "memory" => Ok(Arc::new(MemoryStorage::default())),
+        #[cfg(feature = "sled")]
+        Some(("sled", path)) => SledStorage::open(path).map(|db| Arc::new(db) as _),
+        #[cfg(not(feature = "sled"))]
+        Some(("sled", _)) => {
+            bail!("`sled` storage requires `near-cm` to be built with `sled` feature")
+        }
+        _ => bail!("unsupported storage backend `{spec}`"),
+    }
+}
+
+struct JournalEntry {
+    namespace: Arc<str>,
+    key: Vec<u8>,
+    /// Pending change of `key` prior to the write, if any.
+    prev: Option<Option<Vec<u8>>>,
+}
+
+/// Error returned on attempt to change state within a read-only [`Transaction`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProhibitedInView;
+
+impl fmt::Display for ProhibitedInView {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("state changes are prohibited in view calls")
+    }
+}
+
+impl std::error::Error for ProhibitedInView {}
+
+/// Position in a [`Transaction`], which it can be rolled back to.
+#[derive(Clone, Copy, Debug)]
+pub struct Savepoint(usize);
+
+/// A write-ahead overlay over [`Storage`], buffering changes of a single invocation.
+///
+/// Changes are only applied to the underlying storage by [`Transaction::commit`],
+/// dropping the transaction discards them. Committed state read by the transaction
+/// is recorded and validated on commit, see [`Storage::commit`].
+pub struct Transaction {
+    storage: Arc<dyn Storage>,
+    changes: Changes,
+    journal: Vec<JournalEntry>,
+    reads: ReadSet,
+    read_only: bool,
+}
+
+impl Transaction {
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        Self {
+            storage,
+            changes: Changes::default(),
+            journal: Vec::default(),
+            reads: ReadSet::default(),
+            read_only: false,
+        }
+    }
+
+    /// Sets whether the transaction is read-only, like in NEAR view calls.
+    ///
+    /// Writes to read-only transactions fail with [`ProhibitedInView`].
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.read_only = read_only;
+    }
+
+    /// Returns `true` if the transaction is read-only, see [`Transaction::set_read_only`].
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn pending(&self, namespace: &str, key: &[u8]) -> Option<&Option<Vec<u8>>> {
+        self.changes.get(namespace)?.get(key)
+    }
+
+    pub fn read(&mut self, namespace: &Arc<str>, key: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+        if let Some(v) = self.pending(namespace, key) {
+            return Ok(v.clone());
+        }
+        let value = self.storage.read(namespace, key)?;
+        self.reads.0.push(Read::Value {
+            namespace: Arc::clone(namespace),
+            key: key.to_vec(),
+            value: value.clone(),
+        });
+        Ok(value)
+    }
+
+    pub fn has_key(&mut self, namespace: &Arc<str>, key: &[u8]) -> anyhow::Result<bool> {
+        if let Some(v) = self.pending(namespace, key) {
+            return Ok(v.is_some());
+        }
+        let ok = self.storage.has_key(namespace, key)?;
+        self.reads.0.push(Read::HasKey {
+            namespace: Arc::clone(namespace),
+            key: key.to_vec(),
+            ok,
+        });
+        Ok(ok)
+    }
+
+    /// Sets `key` to `value` or removes it if `value` is `None`, returning the previous value.
+    pub fn write(
+        &mut self,
+        namespace: &Arc<str>,
+        key: Vec<u8>,
+        value: Option<Vec<u8>>,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        if self.read_only {
+            return Err(ProhibitedInView.into());
+        }
+        let prev = self.read(namespace, &key)?;
+        let changes = self.changes.entry(Arc::clone(namespace)).or_default();
+        let pending = changes.insert(key.clone(), value);
+        self.journal.push(JournalEntry {
+            namespace: Arc::clone(namespace),
+            key,
+            prev: pending,
+        });
+        Ok(prev)
+    }
+
+    /// See [`Storage::scan`].
+    pub fn scan(
+        &mut self,
+        namespace: &Arc<str>,
+        prefix: &[u8],
+        start_after: Option<&[u8]>,
+        limit: usize,
+    ) -> anyhow::Result<Vec<Entry>> {
+        let pending: Vec<_> = self
+            .changes
+            .get(namespace)
+            .map(|changes| {
+                changes
+                    .range::<[u8], _>((scan_start(prefix, start_after), Bound::Unbounded))
+                    .take_while(|(k, _)| k.starts_with(prefix))
+                    .collect()
+            })
+            .unwrap_or_default();
+        // Each pending change can shadow at most a single committed entry
+        let committed_limit = limit.saturating_add(pending.len());
+        let committed = self
+            .storage
+            .scan(namespace, prefix, start_after, committed_limit)?;
+        self.reads.0.push(Read::Scan {
+            namespace: Arc::clone(namespace),
+            prefix: prefix.to_vec(),
+            start_after: start_after.map(<[u8]>::to_vec),
+            limit: committed_limit,
+            entries: committed.clone(),
+        });
+
+        let mut entries = Vec::with_capacity(limit.min(pending.len() + committed.len()));
+        let mut pending = pending.into_iter().peekable();
+        let mut committed = committed.into_iter().peekable();
+        while entries.len() < limit {
+            let ord = match (pending.peek(), committed.peek()) {
+                (None, None) => break,
+                (Some(..), None) => Ordering::Less,
+                (None, Some(..)) => Ordering::Greater,
+                (Some((pending, _)), Some((committed, _))) => {
+                    pending.as_slice().cmp(committed.as_slice())
+                }
+            };
+            if ord == Ordering::Greater {
+                entries.extend(committed.next());
+                continue;
+            }
+            if ord == Ordering::Equal {
+                committed.next();
+            }
+            if let Some((k, Some(v))) = pending.next() {
+                entries.push((k.clone(), v.clone()));
+            }
+        }
+        Ok(entries)
+    }
+
+    pub fn savepoint(&self) -> Savepoint {
+        Savepoint(self.journal.len())
+    }
+
+    /// Discards all changes made after `savepoint`.
+    pub fn rollback(&mut self, Savepoint(n): Savepoint) {
+        for JournalEntry {
+            namespace,
+            key,
+            prev,
+        } in self.journal.drain(n..).rev()
+        {
+            let Some(changes) = self.changes.get_mut(&namespace) else {
+                continue;
+            };
+            if let Some(prev) = prev {
+                changes.insert(key, prev);
+            } else {
+                changes.remove(&key);
+                // Transactions without changes are not validated on commit
+                if changes.is_empty() {
+                    self.changes.remove(&namespace);
+                }
+            }
+        }
+    }
+
+    /// Atomically applies all changes to the underlying storage, failing with [`Conflict`]
+    /// if committed state read by the transaction has changed since.
+    ///
+    /// Transactions without changes are not validated, they are serializable
+    /// at the time of their reads.
+    pub fn commit(self) -> anyhow::Result<()> {
+        if self.changes.is_empty() {
+            return Ok(());
+        }
+        self.storage.commit(&self.reads, self.changes)
+    }
+}
+
+/// Defines `near-cm:host/storage` in `linker`, scoping all operations to `namespace`.
+///
+/// All operations are performed on the [`Transaction`] returned by `get`.
+pub fn add_to_linker<T: 'static>(
+    linker: &mut Linker<T>,
+    namespace: &str,
+    get: fn(&mut T) -> &mut Transaction,
+) -> wasmtime::Result<()> {
+    let namespace = Arc::<str>::from(namespace);
+    let mut linker = linker.instance("near-cm:host/storage@0.1.0")?;
+    linker.func_wrap("read", {
+        let namespace = Arc::clone(&namespace);
+        move |mut store: StoreContextMut<'_, T>, (key,): (Vec<u8>,)| {
+            let v = get(store.data_mut()).read(&namespace, &key)?;
+            Ok((v,))
+        }
+    })?;
+    linker.func_wrap("write", {
+        let namespace = Arc::clone(&namespace);
+        move |mut store: StoreContextMut<'_, T>, (key, value): (Vec<u8>, Vec<u8>)| {
+            let v = get(store.data_mut()).write(&namespace, key, Some(value))?;
+            Ok((v,))
+        }
+    })?;
+    linker.func_wrap("remove", {
+        let namespace = Arc::clone(&namespace);
+        move |mut store: StoreContextMut<'_, T>, (key,): (Vec<u8>,)| {
+            let v = get(store.data_mut()).write(&namespace, key, None)?;
+            Ok((v,))
+        }
+    })?;
+    linker.func_wrap("has-key", {
+        let namespace = Arc::clone(&namespace);
+        move |mut store: StoreContextMut<'_, T>, (key,): (Vec<u8>,)| {
+            let ok = get(store.data_mut()).has_key(&namespace, &key)?;
+            Ok((ok,))
+        }
+    })?;
+    linker.func_wrap(
+        "iter-prefix",
+        move |mut store: StoreContextMut<'_, T>,
+              (prefix, start_after, limit): (Vec<u8>, Option<Vec<u8>>, u32)| {
+            let entries = get(store.data_mut()).scan(
+                &namespace,
+                &prefix,
+                start_after.as_deref(),
+                limit.try_into().unwrap_or(usize::MAX),
+            )?;
+            Ok((entries,))
+        },
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(kvs: &[(&str, &str)]) -> Vec<Entry> {
+        kvs.iter()
+            .map(|(k, v)| (k.as_bytes().to_vec(), v.as_bytes().to_vec()))
+            .collect()
+    }
+
+    /// Returns storage with committed keys `a`, `b`, `c` and `d` in namespace `ns`.
+    fn storage(ns: &Arc<str>) -> Arc<dyn Storage> {
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::default());
+        let mut tx = Transaction::new(Arc::clone(&storage));
+        for k in ["a", "b", "c", "d"] {
+            tx.write(ns, k.into(), Some(k.into())).unwrap();
+        }
+        tx.commit().unwrap();
+        storage
+    }
+
+    #[test]
+    fn nested_rollback() {
+        let ns: Arc<str> = "ns".into();
+        let storage = storage(&ns);
+        let mut tx = Transaction::new(Arc::clone(&storage));
+        tx.write(&ns, "a".into(), Some("1".into())).unwrap();
+        let outer = tx.savepoint();
+        tx.write(&ns, "a".into(), Some("2".into())).unwrap();
+        tx.write(&ns, "b".into(), None).unwrap();
+        let inner = tx.savepoint();
+        tx.write(&ns, "a".into(), Some("3".into())).unwrap();
+        tx.write(&ns, "e".into(), Some("e".into())).unwrap();
+
+        tx.rollback(inner);
+        assert_eq!(tx.read(&ns, b"a").unwrap(), Some("2".into()));
+        assert!(!tx.has_key(&ns, b"b").unwrap());
+        assert!(!tx.has_key(&ns, b"e").unwrap());
+
+        tx.rollback(outer);
+        assert_eq!(tx.read(&ns, b"a").unwrap(), Some("1".into()));
+        assert_eq!(tx.read(&ns, b"b").unwrap(), Some("b".into()));
+
+        tx.commit().unwrap();
+        assert_eq!(storage.read(&ns, b"a").unwrap(), Some("1".into()));
+        assert_eq!(storage.read(&ns, b"b").unwrap(), Some("b".into()));
+        assert_eq!(storage.read(&ns, b"e").unwrap(), None);
+    }
+
+    #[test]
+    fn scan_merges_pending_changes() {
+        let ns: Arc<str> = "ns".into();
+        let storage = storage(&ns);
+        let mut tx = Transaction::new(storage);
+        tx.write(&ns, "a".into(), None).unwrap();
+        tx.write(&ns, "b".into(), None).unwrap();
+        tx.write(&ns, "bb".into(), Some("x".into())).unwrap();
+        tx.write(&ns, "c".into(), Some("y".into())).unwrap();
+        tx.write(&ns, "e".into(), Some("e".into())).unwrap();
+
+        assert_eq!(
+            tx.scan(&ns, b"", None, usize::MAX).unwrap(),
+            entries(&[("bb", "x"), ("c", "y"), ("d", "d"), ("e", "e")])
+        );
+        // Pending deletes do not count towards the limit
+        assert_eq!(
+            tx.scan(&ns, b"", None, 2).unwrap(),
+            entries(&[("bb", "x"), ("c", "y")])
+        );
+        assert_eq!(
+            tx.scan(&ns, b"", Some(b"c"), 2).unwrap(),
+            entries(&[("d", "d"), ("e", "e")])
+        );
+        assert_eq!(
+            tx.scan(&ns, b"b", None, usize::MAX).unwrap(),
+            entries(&[("bb", "x")])
+        );
+        // Scans do not observe other namespaces
+        assert_eq!(tx.scan(&"other".into(), b"", None, 10).unwrap(), []);
+    }
+
+    #[test]
+    fn concurrent_changes_conflict() {
+        let ns: Arc<str> = "ns".into();
+        let storage = storage(&ns);
+        let mut tx1 = Transaction::new(Arc::clone(&storage));
+        let mut tx2 = Transaction::new(Arc::clone(&storage));
+        let mut tx3 = Transaction::new(Arc::clone(&storage));
+        let mut tx4 = Transaction::new(Arc::clone(&storage));
+        // Read-modify-write of the same key
+        tx1.read(&ns, b"a").unwrap();
+        tx1.write(&ns, "a".into(), Some("1".into())).unwrap();
+        tx2.read(&ns, b"a").unwrap();
+        tx2.write(&ns, "a".into(), Some("2".into())).unwrap();
+        // Insert into a scanned range
+        assert_eq!(tx3.scan(&ns, b"a", None, 10).unwrap().len(), 1);
+        tx3.write(&ns, "x".into(), Some("x".into())).unwrap();
+        // Disjoint keys
+        tx4.read(&ns, b"d").unwrap();
+        tx4.write(&ns, "d".into(), Some("4".into())).unwrap();
+
+        tx1.commit().unwrap();
+        assert!(tx2.commit().unwrap_err().is::<Conflict>());
+        tx4.commit().unwrap();
+        assert_eq!(storage.read(&ns, b"a").unwrap(), Some("1".into()));
+        assert_eq!(storage.read(&ns, b"d").unwrap(), Some("4".into()));
+
+        let mut tx = Transaction::new(Arc::clone(&storage));
+        tx.write(&ns, "ab".into(), Some("ab".into())).unwrap();
+        tx.commit().unwrap();
+        assert!(tx3.commit().unwrap_err().is::<Conflict>());
+        assert_eq!(storage.read(&ns, b"x").unwrap(), None);
+    }
+
+    #[test]
+    fn rolled_back_transactions_commit_without_conflict() {
+        let ns: Arc<str> = "ns".into();
+        let storage = storage(&ns);
+        let mut tx = Transaction::new(Arc::clone(&storage));
+        let start = tx.savepoint();
+        tx.read(&ns, b"a").unwrap();
+        tx.write(&ns, "a".into(), Some("1".into())).unwrap();
+        tx.rollback(start);
+
+        let mut other = Transaction::new(Arc::clone(&storage));
+        other.write(&ns, "a".into(), Some("2".into())).unwrap();
+        other.commit().unwrap();
+
+        // Nothing is left to write, so the stale read does not matter
+        tx.commit().unwrap();
+        assert_eq!(storage.read(&ns, b"a").unwrap(), Some("2".into()));
+    }
+
+    #[test]
+    fn read_only_prohibits_writes() {
+        let ns: Arc<str> = "ns".into();
+        let mut tx = Transaction::new(storage(&ns));
+        tx.set_read_only(true);
+        let err = tx.write(&ns, "a".into(), None).unwrap_err();
+        assert!(err.is::<ProhibitedInView>());
+        assert_eq!(tx.read(&ns, b"a").unwrap(), Some("a".into()));
+    }
+}
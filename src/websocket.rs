@@ -0,0 +1,210 @@
+//! Minimal server side of the [WebSocket protocol](https://www.rfc-editor.org/rfc/rfc6455)
+//!
+//! Only what is needed to push messages to clients is implemented: the opening handshake
+//! and unfragmented frames. Extensions and subprotocols are not supported.
+
+use std::io;
+
+use anyhow::{Context as _, ensure};
+use base64::Engine as _;
+use base64::prelude::BASE64_STANDARD;
+use sha1::{Digest as _, Sha1};
+use tokio::io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _};
+
+/// GUID appended to the client key by the opening handshake
+const GUID: &[u8] = b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Frame opcodes
+pub mod opcode {
+    pub const CONTINUATION: u8 = 0x0;
+    pub const TEXT: u8 = 0x1;
+    pub const BINARY: u8 = 0x2;
+    pub const CLOSE: u8 = 0x8;
+    pub const PING: u8 = 0x9;
+    pub const PONG: u8 = 0xa;
+}
+
+/// Close status codes
+pub mod status {
+    pub const NORMAL: u16 = 1000;
+    pub const GOING_AWAY: u16 = 1001;
+    pub const PROTOCOL_ERROR: u16 = 1002;
+    pub const TOO_BIG: u16 = 1009;
+    pub const TRY_AGAIN_LATER: u16 = 1013;
+}
+
+/// A single WebSocket frame
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    /// Whether this is the final fragment of a message
+    pub fin: bool,
+    pub opcode: u8,
+    /// Unmasked payload
+    pub payload: Vec<u8>,
+}
+
+/// Validates the opening handshake request `headers` and returns
+/// the `Sec-WebSocket-Accept` response header value.
+pub fn accept(headers: &http::HeaderMap) -> anyhow::Result<String> {
+    let has_token = |name: http::HeaderName, token: &str| {
+        headers.get_all(name).iter().any(|v| {
+            v.to_str()
+                .is_ok_and(|v| v.split(',').any(|v| v.trim().eq_ignore_ascii_case(token)))
+        })
+    };
+    ensure!(
+        has_token(http::header::CONNECTION, "upgrade"),
+        "`Connection` header does not contain `upgrade`"
+    );
+    ensure!(
+        has_token(http::header::UPGRADE, "websocket"),
+        "`Upgrade` header does not contain `websocket`"
+    );
+    let version = headers
+        .get(http::header::SEC_WEBSOCKET_VERSION)
+        .context("`Sec-WebSocket-Version` header missing")?;
+    ensure!(
+        version == "13",
+        "WebSocket version `{}` not supported, expected `13`",
+        String::from_utf8_lossy(version.as_bytes())
+    );
+    let key = headers
+        .get(http::header::SEC_WEBSOCKET_KEY)
+        .context("`Sec-WebSocket-Key` header missing")?;
+    Ok(accept_key(key.as_bytes()))
+}
+
+/// Returns the `Sec-WebSocket-Accept` value for `Sec-WebSocket-Key` value `key`.
+pub fn accept_key(key: &[u8]) -> String {
+    let digest = Sha1::new().chain_update(key).chain_update(GUID).finalize();
+    BASE64_STANDARD.encode(digest)
+}
+
+/// Reads a frame of at most `max` payload bytes sent by a client.
+///
+/// Frames sent by clients must be masked.
+pub async fn read_frame(r: &mut (impl AsyncRead + Unpin), max: usize) -> io::Result<Frame> {
+    let mut header = [0; 2];
+    r.read_exact(&mut header).await?;
+    let fin = header[0] & 0x80 != 0;
+    if header[0] & 0x70 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "reserved frame bits set",
+        ));
+    }
+    let opcode = header[0] & 0x0f;
+    if header[1] & 0x80 == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "client frame is not masked",
+        ));
+    }
+    let len = match header[1] & 0x7f {
+        126 => u64::from(r.read_u16().await?),
+        127 => r.read_u64().await?,
+        n => u64::from(n),
+    };
+    let len = usize::try_from(len)
+        .ok()
+        .filter(|len| *len <= max)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("frame payload exceeds the limit of {max} bytes"),
+            )
+        })?;
+    let mut mask = [0; 4];
+    r.read_exact(&mut mask).await?;
+    let mut payload = vec![0; len];
+    r.read_exact(&mut payload).await?;
+    for (i, b) in payload.iter_mut().enumerate() {
+        *b ^= mask[i % 4];
+    }
+    Ok(Frame {
+        fin,
+        opcode,
+        payload,
+    })
+}
+
+/// Writes an unmasked, final frame with `opcode` and `payload`.
+pub async fn write_frame(
+    w: &mut (impl AsyncWrite + Unpin),
+    opcode: u8,
+    payload: &[u8],
+) -> io::Result<()> {
+    let mut buf = Vec::with_capacity(payload.len() + 10);
+    buf.push(0x80 | opcode);
+    match payload.len() {
+        n @ ..126 => buf.push(n as u8),
+        n @ ..=0xffff => {
+            buf.push(126);
+            buf.extend_from_slice(&(n as u16).to_be_bytes());
+        }
+        n => {
+            buf.push(127);
+            buf.extend_from_slice(&(n as u64).to_be_bytes());
+        }
+    }
+    buf.extend_from_slice(payload);
+    w.write_all(&buf).await?;
+    w.flush().await
+}
+
+/// Writes a close frame with status `code` and `reason`.
+pub async fn write_close(
+    w: &mut (impl AsyncWrite + Unpin),
+    code: u16,
+    reason: &str,
+) -> io::Result<()> {
+    let mut payload = Vec::with_capacity(2 + reason.len());
+    payload.extend_from_slice(&code.to_be_bytes());
+    // Control frame payloads are limited to 125 bytes
+    let mut n = reason.len().min(123);
+    while !reason.is_char_boundary(n) {
+        n -= 1;
+    }
+    payload.extend_from_slice(&reason.as_bytes()[..n]);
+    write_frame(w, opcode::CLOSE, &payload).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handshake_accepts_rfc_example_key() {
+        assert_eq!(
+            accept_key(b"dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[tokio::test]
+    async fn frames_roundtrip() {
+        let mut buf = Vec::default();
+        write_frame(&mut buf, opcode::TEXT, &[b'x'; 300])
+            .await
+            .unwrap();
+        assert_eq!(buf[..4], [0x81, 126, 0x01, 0x2c]);
+
+        // Masked client frame `Hello` from RFC 6455 section 5.7
+        let frame = [
+            0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58,
+        ];
+        let frame = read_frame(&mut frame.as_slice(), 125).await.unwrap();
+        assert_eq!(
+            frame,
+            Frame {
+                fin: true,
+                opcode: opcode::TEXT,
+                payload: b"Hello".to_vec(),
+            }
+        );
+        let err = read_frame(&mut [0x81, 0x05].as_slice(), 125)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}